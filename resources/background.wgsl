@@ -0,0 +1,43 @@
+// Full screen backgrounds drawn before the scene, see src/graphics/background.rs
+
+struct BackgroundUniform {
+    // Inverse of the projection times the camera rotation, maps the far plane to view directions
+    inverse_view_projection: mat4x4<f32>,
+    top: vec4<f32>,
+    bottom: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> background: BackgroundUniform;
+
+@group(1) @binding(0)
+var t_sky: texture_cube<f32>;
+@group(1) @binding(1)
+var s_sky: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// One triangle covering the screen, on the far plane
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let ndc = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+@fragment
+fn fs_gradient(in: VertexOutput) -> @location(0) vec4<f32> {
+    return mix(background.bottom, background.top, in.ndc.y * 0.5 + 0.5);
+}
+
+@fragment
+fn fs_skybox(in: VertexOutput) -> @location(0) vec4<f32> {
+    let far = background.inverse_view_projection * vec4<f32>(in.ndc, 1.0, 1.0);
+    return vec4<f32>(textureSample(t_sky, s_sky, far.xyz / far.w).rgb, 1.0);
+}
@@ -0,0 +1,138 @@
+// Bloom over a mip chain, see src/graphics/bloom.rs. The prefilter thresholds the HDR scene into
+// the first mip, each level is then downsampled from the one above and upsampled back additively.
+
+struct BloomUniform {
+    threshold: f32,
+    // Width of the soft transition below the threshold
+    knee: f32,
+    // Upsampling filter radius in uv units, horizontally
+    radius: f32,
+    intensity: f32,
+}
+
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+@group(1) @binding(0)
+var<uniform> bloom: BloomUniform;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// One triangle covering the screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn sample_source(uv: vec2<f32>, offset: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    return textureSampleLevel(t_source, s_source, uv + offset * texel, 0.0).rgb;
+}
+
+// Weight keeping single very bright pixels from flickering
+fn karis_weight(color: vec3<f32>) -> f32 {
+    return 1.0 / (1.0 + dot(color, vec3<f32>(0.2126, 0.7152, 0.0722)));
+}
+
+fn karis_group(a: vec3<f32>, b: vec3<f32>, c: vec3<f32>, d: vec3<f32>) -> vec4<f32> {
+    let sum = (a + b + c + d) * 0.25;
+    let weight = karis_weight(sum);
+    return vec4<f32>(sum * weight, weight);
+}
+
+fn soft_threshold(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft = clamp(brightness - bloom.threshold + bloom.knee, 0.0, 2.0 * bloom.knee);
+    soft = soft * soft / (4.0 * bloom.knee + 1e-4);
+    let contribution = max(soft, brightness - bloom.threshold) / max(brightness, 1e-4);
+    return color * contribution;
+}
+
+// The 13 taps of the Call of Duty: Advanced Warfare downsample, in source texels
+struct Taps {
+    a: vec3<f32>, b: vec3<f32>, c: vec3<f32>,
+    d: vec3<f32>, e: vec3<f32>, f: vec3<f32>,
+    g: vec3<f32>, h: vec3<f32>, i: vec3<f32>,
+    j: vec3<f32>, k: vec3<f32>, l: vec3<f32>, m: vec3<f32>,
+}
+
+fn gather_taps(uv: vec2<f32>) -> Taps {
+    var taps: Taps;
+    taps.a = sample_source(uv, vec2<f32>(-2.0, 2.0));
+    taps.b = sample_source(uv, vec2<f32>(0.0, 2.0));
+    taps.c = sample_source(uv, vec2<f32>(2.0, 2.0));
+    taps.d = sample_source(uv, vec2<f32>(-2.0, 0.0));
+    taps.e = sample_source(uv, vec2<f32>(0.0, 0.0));
+    taps.f = sample_source(uv, vec2<f32>(2.0, 0.0));
+    taps.g = sample_source(uv, vec2<f32>(-2.0, -2.0));
+    taps.h = sample_source(uv, vec2<f32>(0.0, -2.0));
+    taps.i = sample_source(uv, vec2<f32>(2.0, -2.0));
+    taps.j = sample_source(uv, vec2<f32>(-1.0, 1.0));
+    taps.k = sample_source(uv, vec2<f32>(1.0, 1.0));
+    taps.l = sample_source(uv, vec2<f32>(-1.0, -1.0));
+    taps.m = sample_source(uv, vec2<f32>(1.0, -1.0));
+    return taps;
+}
+
+// First level, from the scene: Karis averaged per group of taps, then thresholded
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let t = gather_taps(in.uv);
+    let groups = karis_group(t.a, t.b, t.d, t.e) * 0.125
+        + karis_group(t.b, t.c, t.e, t.f) * 0.125
+        + karis_group(t.d, t.e, t.g, t.h) * 0.125
+        + karis_group(t.e, t.f, t.h, t.i) * 0.125
+        + karis_group(t.j, t.k, t.l, t.m) * 0.5;
+
+    let color = groups.rgb / max(groups.w, 1e-4);
+    return vec4<f32>(soft_threshold(color), 1.0);
+}
+
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let t = gather_taps(in.uv);
+    let color = t.e * 0.125
+        + (t.a + t.c + t.g + t.i) * 0.03125
+        + (t.b + t.d + t.f + t.h) * 0.0625
+        + (t.j + t.k + t.l + t.m) * 0.125;
+    return vec4<f32>(color, 1.0);
+}
+
+// 3x3 tent filter
+fn tent(uv: vec2<f32>, radius: vec2<f32>) -> vec3<f32> {
+    var color = textureSampleLevel(t_source, s_source, uv, 0.0).rgb * 4.0;
+    color += textureSampleLevel(t_source, s_source, uv + vec2<f32>(-radius.x, 0.0), 0.0).rgb * 2.0;
+    color += textureSampleLevel(t_source, s_source, uv + vec2<f32>(radius.x, 0.0), 0.0).rgb * 2.0;
+    color += textureSampleLevel(t_source, s_source, uv + vec2<f32>(0.0, -radius.y), 0.0).rgb * 2.0;
+    color += textureSampleLevel(t_source, s_source, uv + vec2<f32>(0.0, radius.y), 0.0).rgb * 2.0;
+    color += textureSampleLevel(t_source, s_source, uv + vec2<f32>(-radius.x, -radius.y), 0.0).rgb;
+    color += textureSampleLevel(t_source, s_source, uv + vec2<f32>(radius.x, -radius.y), 0.0).rgb;
+    color += textureSampleLevel(t_source, s_source, uv + vec2<f32>(-radius.x, radius.y), 0.0).rgb;
+    color += textureSampleLevel(t_source, s_source, uv + vec2<f32>(radius.x, radius.y), 0.0).rgb;
+    return color / 16.0;
+}
+
+// Blended additively onto the larger level
+@fragment
+fn fs_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_source));
+    let radius = vec2<f32>(bloom.radius, bloom.radius * size.x / size.y);
+    return vec4<f32>(tent(in.uv, radius), 1.0);
+}
+
+// Adds the first level onto the scene
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(t_source, s_source, in.uv, 0.0).rgb;
+    return vec4<f32>(color * bloom.intensity, 0.0);
+}
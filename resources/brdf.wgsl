@@ -0,0 +1,99 @@
+// Shading shared by the forward shaders and the deferred lighting pass: GGX distribution,
+// height-correlated Smith visibility and Schlick Fresnel with a Lambertian diffuse lobe for
+// metallic-roughness surfaces, and Phong or Blinn-Phong.
+
+#include "lights.wgsl"
+
+const PI: f32 = 3.14159265359;
+
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+// Smith G divided by the 4 * n_dot_l * n_dot_v denominator
+fn visibility_smith(n_dot_l: f32, n_dot_v: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let ggx_v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2);
+    let ggx_l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2);
+    let ggx = ggx_v + ggx_l;
+    if ggx > 0.0 {
+        return 0.5 / ggx;
+    }
+    return 0.0;
+}
+
+fn fresnel_schlick(v_dot_h: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - v_dot_h, 0.0, 1.0), 5.0);
+}
+
+// What the lights need to know about a point of a surface, alpha is the squared roughness
+struct Surface {
+    normal: vec3<f32>,
+    view: vec3<f32>,
+    albedo: vec3<f32>,
+    f0: vec3<f32>,
+    metallic: f32,
+    alpha: f32,
+}
+
+fn shade_pbr(light: Light, surface: Surface, world_position: vec3<f32>) -> vec3<f32> {
+    let sample = sample_light(light, world_position, surface.normal);
+    let to_light = sample.to_light;
+
+    let n_dot_l = dot(surface.normal, to_light);
+    if n_dot_l <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+
+    let halfway = normalize(to_light + surface.view);
+    let n_dot_v = max(dot(surface.normal, surface.view), 0.0001);
+    let n_dot_h = max(dot(surface.normal, halfway), 0.0);
+    let v_dot_h = max(dot(surface.view, halfway), 0.0);
+
+    let fresnel = fresnel_schlick(v_dot_h, surface.f0);
+    let specular = fresnel * distribution_ggx(n_dot_h, surface.alpha) * visibility_smith(n_dot_l, n_dot_v, surface.alpha);
+    let diffuse = (1.0 - fresnel) * (1.0 - surface.metallic) * surface.albedo / PI;
+
+    return sample.radiance * n_dot_l * (diffuse + specular);
+}
+
+// Ambient and image based light, with a roughness aware Fresnel term splitting diffuse and specular.
+// `irradiance` is `environment_diffuse(surface.normal)`, taken in uniform control flow.
+fn indirect_pbr(surface: Surface, roughness: f32, irradiance: vec3<f32>) -> vec3<f32> {
+    let n_dot_v = max(dot(surface.normal, surface.view), 0.0001);
+    let fresnel = surface.f0 + (max(vec3<f32>(1.0 - roughness), surface.f0) - surface.f0) * pow(1.0 - n_dot_v, 5.0);
+    let diffuse_weight = (1.0 - fresnel) * (1.0 - surface.metallic);
+    return lights.ambient.rgb * surface.albedo
+        + diffuse_weight * irradiance * surface.albedo
+        + environment_specular(surface.normal, surface.view, surface.f0, roughness);
+}
+
+// Phong, or Blinn-Phong with `blinn`
+fn shade_phong(light: Light,
+    normal: vec3<f32>,
+    view: vec3<f32>,
+    world_position: vec3<f32>,
+    albedo: vec3<f32>,
+    specular_color: vec3<f32>,
+    shininess: f32,
+    blinn: bool) -> vec3<f32> {
+
+    let sample = sample_light(light, world_position, normal);
+    let to_light = sample.to_light;
+
+    let n_dot_l = max(dot(normal, to_light), 0.0);
+    var specular = 0.0;
+    if n_dot_l > 0.0 {
+        if blinn {
+            let halfway = normalize(to_light + view);
+            specular = pow(max(dot(normal, halfway), 0.0), shininess);
+        } else {
+            let reflected = reflect(-to_light, normal);
+            specular = pow(max(dot(reflected, view), 0.0), shininess);
+        }
+    }
+
+    return sample.radiance * (albedo * n_dot_l + specular_color * specular);
+}
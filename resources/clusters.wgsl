@@ -0,0 +1,131 @@
+// Clustered light culling, see src/graphics/clusters.rs. The view is split into CLUSTERS_X by
+// CLUSTERS_Y screen tiles and CLUSTERS_Z depth slices, `build_clusters` finds each cluster's view
+// space bounds and `assign_lights` lists the lights reaching into it for the lit shaders.
+// The structs must match resources/lights.wgsl.
+
+#include "light_constants.wgsl"
+
+const KIND_DIRECTIONAL: f32 = 0.0;
+
+struct Light {
+    position: vec4<f32>,
+    direction: vec4<f32>,
+    color: vec4<f32>,
+    cone: vec4<f32>,
+}
+
+struct LightsUniform {
+    ambient: vec4<f32>,
+    count: vec4<u32>,
+    shadow: vec4<f32>,
+    shadow_matrices: array<mat4x4<f32>, MAX_SHADOW_LAYERS>,
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    cluster: vec4<f32>,
+}
+
+struct ClusterBounds {
+    min: vec4<f32>,
+    max: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> lights: LightsUniform;
+@group(0) @binding(1)
+var<storage, read> light_list: array<Light>;
+@group(0) @binding(2)
+var<storage, read_write> clusters: array<ClusterBounds>;
+@group(0) @binding(3)
+var<storage, read_write> cluster_light_counts: array<u32>;
+@group(0) @binding(4)
+var<storage, read_write> cluster_light_indices: array<u32>;
+// The most lights reaching into one cluster, those past MAX_CLUSTER_LIGHTS are dropped from it
+@group(0) @binding(5)
+var<storage, read_write> cluster_max_lights: atomic<u32>;
+
+fn cluster_index(id: vec3<u32>) -> u32 {
+    return id.x + id.y * CLUSTERS_X + id.z * CLUSTERS_X * CLUSTERS_Y;
+}
+
+// View depth where a slice starts, the inverse of `cluster_slice` in resources/lights.wgsl
+fn slice_depth(slice: u32) -> f32 {
+    let depth = (f32(slice) - lights.cluster.y) / lights.cluster.x;
+    if lights.cluster.w > 0.5 {
+        return exp(depth);
+    }
+    return depth;
+}
+
+fn unproject(ndc: vec3<f32>) -> vec3<f32> {
+    let position = lights.inverse_projection * vec4<f32>(ndc, 1.0);
+    return position.xyz / position.w;
+}
+
+@compute @workgroup_size(16, 9, 1)
+fn build_clusters(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= CLUSTERS_X || id.y >= CLUSTERS_Y || id.z >= CLUSTERS_Z {
+        return;
+    }
+
+    // Tile corners in normalized device coordinates, y pointing up while tiles count down
+    let tile_min = vec2<f32>(f32(id.x) / f32(CLUSTERS_X), f32(id.y + 1u) / f32(CLUSTERS_Y));
+    let tile_max = vec2<f32>(f32(id.x + 1u) / f32(CLUSTERS_X), f32(id.y) / f32(CLUSTERS_Y));
+    let ndc_min = vec2<f32>(tile_min.x * 2.0 - 1.0, 1.0 - tile_min.y * 2.0);
+    let ndc_max = vec2<f32>(tile_max.x * 2.0 - 1.0, 1.0 - tile_max.y * 2.0);
+
+    var bounds_min = vec3<f32>(3.4e38);
+    var bounds_max = vec3<f32>(-3.4e38);
+    for (var i = 0u; i < 2u; i++) {
+        let clip = lights.projection * vec4<f32>(0.0, 0.0, -slice_depth(id.z + i), 1.0);
+        let z = clip.z / clip.w;
+        for (var corner = 0u; corner < 4u; corner++) {
+            let x = select(ndc_min.x, ndc_max.x, (corner & 1u) != 0u);
+            let y = select(ndc_min.y, ndc_max.y, (corner & 2u) != 0u);
+            let point = unproject(vec3<f32>(x, y, z));
+            bounds_min = min(bounds_min, point);
+            bounds_max = max(bounds_max, point);
+        }
+    }
+
+    let index = cluster_index(id);
+    clusters[index].min = vec4<f32>(bounds_min, 0.0);
+    clusters[index].max = vec4<f32>(bounds_max, 0.0);
+}
+
+// Point and spot lights are tested as spheres of their range, directional lights reach everywhere
+@compute @workgroup_size(16, 9, 1)
+fn assign_lights(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= CLUSTERS_X || id.y >= CLUSTERS_Y || id.z >= CLUSTERS_Z {
+        return;
+    }
+
+    let index = cluster_index(id);
+    let bounds = clusters[index];
+    let first = index * MAX_CLUSTER_LIGHTS;
+
+    var count = 0u;
+    var reaching = 0u;
+    let light_count = min(lights.count.x, MAX_LIGHTS);
+    for (var i = 0u; i < light_count; i++) {
+        let light = light_list[i];
+
+        var reaches = true;
+        if light.position.w != KIND_DIRECTIONAL {
+            let center = (lights.view * vec4<f32>(light.position.xyz, 1.0)).xyz;
+            let closest = clamp(center, bounds.min.xyz, bounds.max.xyz);
+            let offset = closest - center;
+            reaches = dot(offset, offset) <= light.direction.w * light.direction.w;
+        }
+
+        if reaches && count < MAX_CLUSTER_LIGHTS {
+            cluster_light_indices[first + count] = i;
+            count++;
+        }
+        if reaches {
+            reaching++;
+        }
+    }
+    cluster_light_counts[index] = count;
+    atomicMax(&cluster_max_lights, reaching);
+}
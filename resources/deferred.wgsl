@@ -0,0 +1,106 @@
+// Lighting pass of the deferred path, see src/graphics/deferred.rs. A full screen triangle shades
+// every G-buffer pixel with all lights, the layout is described in resources/gbuffer.wgsl.
+
+#include "lights.wgsl"
+#include "brdf.wgsl"
+
+// camera_position is a point (w = 1) or, for orthographic cameras, the direction towards the camera (w = 0)
+struct DeferredUniform {
+    inverse_view_projection: mat4x4<f32>,
+    camera_position: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> deferred: DeferredUniform;
+
+@group(2) @binding(0)
+var t_albedo: texture_2d<f32>;
+@group(2) @binding(1)
+var t_normal: texture_2d<f32>;
+@group(2) @binding(2)
+var t_material: texture_2d<f32>;
+@group(2) @binding(3)
+var t_emissive: texture_2d<f32>;
+@group(2) @binding(4)
+var t_depth: texture_depth_2d;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// One triangle covering the screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// The G-buffer's depth goes into the scene's, so what is drawn after the lighting is hidden behind it
+struct LightingOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+@fragment
+fn fs_lighting(in: VertexOutput) -> LightingOutput {
+    let pixel = vec2<i32>(in.position.xy);
+    let albedo = textureLoad(t_albedo, pixel, 0);
+    let encoded_normal = textureLoad(t_normal, pixel, 0);
+    let material = textureLoad(t_material, pixel, 0);
+    let emissive = textureLoad(t_emissive, pixel, 0).rgb;
+    let depth = textureLoad(t_depth, pixel, 0);
+
+    let normal = normalize(encoded_normal.xyz);
+    let irradiance = environment_diffuse(normal);
+
+    // Nothing was drawn here, the background stays
+    if depth >= 1.0 {
+        discard;
+    }
+
+    let position = deferred.inverse_view_projection * vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth, 1.0);
+    let world_position = position.xyz / position.w;
+
+    var view = normalize(deferred.camera_position.xyz);
+    if deferred.camera_position.w > 0.5 {
+        view = normalize(deferred.camera_position.xyz - world_position);
+    }
+
+    let occlusion = albedo.a * screen_occlusion(in.position.xy);
+    let shininess = encoded_normal.w;
+
+    let cluster = light_cluster(world_position);
+    var color = emissive;
+    if shininess == 0.0 {
+        let metallic = material.r;
+        let roughness = material.g;
+
+        var surface: Surface;
+        surface.normal = normal;
+        surface.view = view;
+        surface.albedo = albedo.rgb;
+        surface.f0 = mix(vec3<f32>(0.04), albedo.rgb, metallic);
+        surface.metallic = metallic;
+        surface.alpha = roughness * roughness;
+
+        color += indirect_pbr(surface, roughness, irradiance) * occlusion;
+        for (var i = 0u; i < cluster.count; i++) {
+            color += shade_pbr(cluster_light(cluster, i), surface, world_position);
+        }
+    } else {
+        color += (lights.ambient.rgb + irradiance) * albedo.rgb * occlusion;
+        for (var i = 0u; i < cluster.count; i++) {
+            color += shade_phong(cluster_light(cluster, i), normal, view, world_position, albedo.rgb, material.rgb, abs(shininess), shininess > 0.0);
+        }
+    }
+
+    var out: LightingOutput;
+    out.color = vec4<f32>(color, 1.0);
+    out.depth = depth;
+    return out;
+}
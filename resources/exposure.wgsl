@@ -0,0 +1,24 @@
+// Tone mapping and exposure settings shared by resources/luminance.wgsl and resources/tonemap.wgsl,
+// see src/graphics/tonemap.rs
+
+struct ToneMapUniform {
+    // Log2 luminance covered by the histogram, from `min_log_luminance` over `log_luminance_range`
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    // How much of the way towards the measured luminance the adapted one moves this frame
+    adaptation: f32,
+    // In stops, on top of the auto exposure or on its own
+    exposure_compensation: f32,
+    // 0 Reinhard, 1 ACES, 2 AgX
+    curve: u32,
+    auto_exposure: u32,
+    pixel_count: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> tone_map: ToneMapUniform;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
@@ -0,0 +1,41 @@
+// G-buffer of the deferred path, see src/graphics/deferred.rs. Materials write it from an
+// `fs_gbuffer` entry point and resources/deferred.wgsl lights it.
+//
+// albedo: base color, a the material's own ambient occlusion
+// normal: world space normal, w the shading model: 0 for metallic-roughness, otherwise the
+//         Phong shininess, negative for plain Phong and positive for Blinn-Phong
+// material: metallic and roughness, or the Phong specular color
+// emissive: light given off by the surface itself
+
+struct GBufferOutput {
+    @location(0) albedo: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    @location(2) material: vec4<f32>,
+    @location(3) emissive: vec4<f32>,
+}
+
+fn gbuffer_pbr(albedo: vec3<f32>,
+    occlusion: f32,
+    normal: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    emissive: vec3<f32>) -> GBufferOutput {
+
+    var out: GBufferOutput;
+    out.albedo = vec4<f32>(albedo, occlusion);
+    out.normal = vec4<f32>(normal, 0.0);
+    out.material = vec4<f32>(metallic, roughness, 0.0, 0.0);
+    out.emissive = vec4<f32>(emissive, 0.0);
+    return out;
+}
+
+fn gbuffer_phong(albedo: vec3<f32>, normal: vec3<f32>, specular: vec3<f32>, shininess: f32, blinn: bool) -> GBufferOutput {
+    let exponent = max(shininess, 1.0);
+
+    var out: GBufferOutput;
+    out.albedo = vec4<f32>(albedo, 1.0);
+    out.normal = vec4<f32>(normal, select(-exponent, exponent, blinn));
+    out.material = vec4<f32>(specular, 0.0);
+    out.emissive = vec4<f32>(0.0);
+    return out;
+}
@@ -0,0 +1,211 @@
+// Image based lighting precomputation, run once by `Environment::from_equirectangular`.
+// Every entry point writes one texel per invocation, cube faces are the z dimension.
+
+const PI: f32 = 3.14159265359;
+const BRDF_SAMPLES: u32 = 1024u;
+
+struct FilterParams {
+    roughness: f32,
+    // Texels per face at mip 0 of t_environment, used to pick sampling levels
+    source_size: f32,
+    sample_count: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0)
+var s_linear: sampler;
+@group(0) @binding(1)
+var t_equirect: texture_2d<f32>;
+@group(0) @binding(2)
+var t_environment: texture_cube<f32>;
+@group(0) @binding(3)
+var t_source: texture_2d_array<f32>;
+@group(0) @binding(4)
+var out_cube: texture_storage_2d_array<rgba16float, write>;
+@group(0) @binding(5)
+var out_lut: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(6)
+var<uniform> params: FilterParams;
+
+// Same face layout as src/graphics/cubemap.rs, uv in [-1, 1]
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let u = uv.x;
+    let v = uv.y;
+    switch face {
+        case 0u: { return normalize(vec3<f32>(1.0, -v, -u)); }
+        case 1u: { return normalize(vec3<f32>(-1.0, -v, u)); }
+        case 2u: { return normalize(vec3<f32>(u, 1.0, v)); }
+        case 3u: { return normalize(vec3<f32>(u, -1.0, -v)); }
+        case 4u: { return normalize(vec3<f32>(u, -v, 1.0)); }
+        default: { return normalize(vec3<f32>(-u, -v, -1.0)); }
+    }
+}
+
+fn texel_direction(id: vec3<u32>, size: vec2<u32>) -> vec3<f32> {
+    let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size) * 2.0 - 1.0;
+    return face_direction(id.z, uv);
+}
+
+fn tangent_frame(normal: vec3<f32>) -> mat3x3<f32> {
+    var up = vec3<f32>(0.0, 0.0, 1.0);
+    if abs(normal.z) > 0.999 {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return mat3x3<f32>(tangent, bitangent, normal);
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// Half vector around `normal` distributed as GGX with roughness `alpha`
+fn importance_sample_ggx(xi: vec2<f32>, normal: vec3<f32>, alpha: f32) -> vec3<f32> {
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let half_vector = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+    return normalize(tangent_frame(normal) * half_vector);
+}
+
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn equirect_to_cube(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(out_cube);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+
+    let dir = texel_direction(id, size);
+    let uv = vec2<f32>(0.5 + atan2(dir.z, dir.x) / (2.0 * PI), 0.5 - asin(dir.y) / PI);
+    let color = textureSampleLevel(t_equirect, s_linear, uv, 0.0);
+    textureStore(out_cube, id.xy, id.z, vec4<f32>(color.rgb, 1.0));
+}
+
+// 2x2 box filter from t_source (one mip) into out_cube (the next mip)
+@compute @workgroup_size(8, 8, 1)
+fn downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(out_cube);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+
+    let source = vec2<i32>(id.xy * 2u);
+    let face = i32(id.z);
+    let color = textureLoad(t_source, source, face, 0)
+        + textureLoad(t_source, source + vec2<i32>(1, 0), face, 0)
+        + textureLoad(t_source, source + vec2<i32>(0, 1), face, 0)
+        + textureLoad(t_source, source + vec2<i32>(1, 1), face, 0);
+    textureStore(out_cube, id.xy, id.z, color * 0.25);
+}
+
+// Cosine weighted hemisphere convolution, scaled by PI so shaders multiply by albedo only
+@compute @workgroup_size(8, 8, 1)
+fn irradiance(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(out_cube);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+
+    let frame = tangent_frame(texel_direction(id, size));
+    // Read from a mip close to the output resolution to avoid aliasing
+    let lod = max(log2(params.source_size / f32(size.x)), 0.0);
+    let delta = 0.025;
+
+    var sum = vec3<f32>(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += delta) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += delta) {
+            let local = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let color = textureSampleLevel(t_environment, s_linear, frame * local, lod).rgb;
+            sum += color * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+
+    textureStore(out_cube, id.xy, id.z, vec4<f32>(PI * sum / count, 1.0));
+}
+
+// GGX prefiltered radiance for one roughness level, assuming view = normal.
+// Samples come from lower environment mips where the GGX lobe is wide (filtered importance sampling).
+@compute @workgroup_size(8, 8, 1)
+fn prefilter(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(out_cube);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+
+    let normal = texel_direction(id, size);
+    if params.roughness == 0.0 {
+        textureStore(out_cube, id.xy, id.z, textureSampleLevel(t_environment, s_linear, normal, 0.0));
+        return;
+    }
+
+    let alpha = params.roughness * params.roughness;
+    let texel_solid_angle = 4.0 * PI / (6.0 * params.source_size * params.source_size);
+
+    var sum = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < params.sample_count; i++) {
+        let half_vector = importance_sample_ggx(hammersley(i, params.sample_count), normal, alpha);
+        let to_light = normalize(2.0 * dot(normal, half_vector) * half_vector - normal);
+
+        let n_dot_l = dot(normal, to_light);
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(dot(normal, half_vector), 0.0);
+            let pdf = distribution_ggx(n_dot_h, alpha) * 0.25 + 0.0001;
+            let sample_solid_angle = 1.0 / (f32(params.sample_count) * pdf + 0.0001);
+            let lod = max(0.5 * log2(sample_solid_angle / texel_solid_angle), 0.0);
+
+            sum += textureSampleLevel(t_environment, s_linear, to_light, lod).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+
+    textureStore(out_cube, id.xy, id.z, vec4<f32>(sum / max(weight, 0.0001), 1.0));
+}
+
+// Split sum scale (r) and bias (g) applied to F0, indexed by n_dot_v (x) and roughness (y)
+@compute @workgroup_size(8, 8, 1)
+fn brdf_lut(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(out_lut);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+
+    let n_dot_v = (f32(id.x) + 0.5) / f32(size.x);
+    let roughness = (f32(id.y) + 0.5) / f32(size.y);
+    let alpha = roughness * roughness;
+    let k = alpha / 2.0;
+
+    let normal = vec3<f32>(0.0, 0.0, 1.0);
+    let view = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < BRDF_SAMPLES; i++) {
+        let half_vector = importance_sample_ggx(hammersley(i, BRDF_SAMPLES), normal, alpha);
+        let to_light = normalize(2.0 * dot(view, half_vector) * half_vector - view);
+
+        let n_dot_l = max(to_light.z, 0.0);
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(half_vector.z, 0.0);
+            let v_dot_h = max(dot(view, half_vector), 0.0);
+
+            let geometry = (n_dot_v / (n_dot_v * (1.0 - k) + k)) * (n_dot_l / (n_dot_l * (1.0 - k) + k));
+            let visibility = geometry * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+
+    textureStore(out_lut, id.xy, vec4<f32>(scale, bias, 0.0, 1.0) / vec4<f32>(f32(BRDF_SAMPLES), f32(BRDF_SAMPLES), 1.0, 1.0));
+}
@@ -0,0 +1,78 @@
+// Vertex inputs matching the `Instance` types in src/graphics/vertex.rs.
+// Include it in the shaders that use them.
+
+struct InstanceVertexIn {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+}
+
+struct ColorInstanceIn {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+    @location(9) color: vec4<f32>,
+}
+
+struct LayerInstanceIn {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+    @location(9) layer: u32,
+}
+
+struct NormalInstanceIn {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+    @location(9) normal_0: vec3<f32>,
+    @location(10) normal_1: vec3<f32>,
+    @location(11) normal_2: vec3<f32>,
+}
+
+struct SpriteInstanceIn {
+    @location(5) position: vec2<f32>,
+    @location(6) scale: vec2<f32>,
+    @location(7) rotation: f32,
+    @location(8) uv_rect: vec4<f32>,
+}
+
+fn instance_model(instance: InstanceVertexIn) -> mat4x4<f32> {
+    return mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+}
+
+fn color_instance_model(instance: ColorInstanceIn) -> mat4x4<f32> {
+    return mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+}
+
+fn layer_instance_model(instance: LayerInstanceIn) -> mat4x4<f32> {
+    return mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+}
+
+fn normal_instance_model(instance: NormalInstanceIn) -> mat4x4<f32> {
+    return mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+}
+
+fn normal_instance_normal(instance: NormalInstanceIn) -> mat3x3<f32> {
+    return mat3x3<f32>(instance.normal_0, instance.normal_1, instance.normal_2);
+}
+
+// Translation * rotation around z * scale
+fn sprite_instance_model(instance: SpriteInstanceIn) -> mat4x4<f32> {
+    let c = cos(instance.rotation);
+    let s = sin(instance.rotation);
+    return mat4x4<f32>(
+        vec4<f32>(c * instance.scale.x, s * instance.scale.x, 0.0, 0.0),
+        vec4<f32>(-s * instance.scale.y, c * instance.scale.y, 0.0, 0.0),
+        vec4<f32>(0.0, 0.0, 1.0, 0.0),
+        vec4<f32>(instance.position, 0.0, 1.0),
+    );
+}
+
+fn sprite_instance_uv(instance: SpriteInstanceIn, tex_coords: vec2<f32>) -> vec2<f32> {
+    return mix(instance.uv_rect.xy, instance.uv_rect.zw, tex_coords);
+}
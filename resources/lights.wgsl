@@ -0,0 +1,186 @@
+// Light definitions matching src/graphics/lights.rs, bound at group 1.
+// Include it in the shaders that use them.
+
+// MAX_LIGHTS, MAX_SHADOW_LAYERS and the cluster counts, generated from the Rust constants
+#include "light_constants.wgsl"
+
+const KIND_DIRECTIONAL: f32 = 0.0;
+const KIND_POINT: f32 = 1.0;
+
+// position.w is the kind, direction.w the range, color.w the intensity,
+// cone.xy the cosines of the inner and outer spot angles, cone.zw the first shadow layer and layer count
+struct Light {
+    position: vec4<f32>,
+    direction: vec4<f32>,
+    color: vec4<f32>,
+    cone: vec4<f32>,
+}
+
+// ambient.w is the environment intensity, count.y the highest prefiltered environment mip,
+// count.z 1 when screen space occlusion is bound,
+// shadow holds the depth bias, normal bias, PCF radius and shadow map texel size.
+// The camera's matrices place points in clusters, cluster.xy turn view depth into a slice,
+// of its logarithm when cluster.w is 1.
+struct LightsUniform {
+    ambient: vec4<f32>,
+    count: vec4<u32>,
+    shadow: vec4<f32>,
+    shadow_matrices: array<mat4x4<f32>, MAX_SHADOW_LAYERS>,
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    cluster: vec4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> lights: LightsUniform;
+@group(1) @binding(1)
+var t_irradiance: texture_cube<f32>;
+@group(1) @binding(2)
+var t_prefiltered: texture_cube<f32>;
+@group(1) @binding(3)
+var t_brdf_lut: texture_2d<f32>;
+@group(1) @binding(4)
+var s_environment: sampler;
+@group(1) @binding(5)
+var t_shadow: texture_depth_2d_array;
+@group(1) @binding(6)
+var s_shadow: sampler_comparison;
+@group(1) @binding(7)
+var t_screen_occlusion: texture_2d<f32>;
+@group(1) @binding(8)
+var<storage, read> light_list: array<Light>;
+@group(1) @binding(9)
+var<storage, read> cluster_light_counts: array<u32>;
+@group(1) @binding(10)
+var<storage, read> cluster_light_indices: array<u32>;
+
+struct LightSample {
+    to_light: vec3<f32>,
+    radiance: vec3<f32>,
+}
+
+// Every light, shaders normally only loop over those of their `light_cluster`
+fn light_count() -> u32 {
+    return min(lights.count.x, MAX_LIGHTS);
+}
+
+// Where a run of a cluster's lights starts in cluster_light_indices and how long it is
+struct LightCluster {
+    first: u32,
+    count: u32,
+}
+
+fn cluster_slice(depth: f32) -> u32 {
+    var slice_depth = depth;
+    if lights.cluster.w > 0.5 {
+        slice_depth = log(max(depth, 0.0001));
+    }
+    return u32(clamp(slice_depth * lights.cluster.x + lights.cluster.y, 0.0, f32(CLUSTERS_Z - 1u)));
+}
+
+// The lights that can reach a world space point, as culled by resources/clusters.wgsl
+fn light_cluster(world_position: vec3<f32>) -> LightCluster {
+    let view_position = lights.view * vec4<f32>(world_position, 1.0);
+    let clip = lights.projection * view_position;
+    let uv = vec2<f32>(clip.x / clip.w * 0.5 + 0.5, 0.5 - clip.y / clip.w * 0.5);
+    let tile = vec2<u32>(clamp(uv * vec2<f32>(f32(CLUSTERS_X), f32(CLUSTERS_Y)),
+        vec2<f32>(0.0),
+        vec2<f32>(f32(CLUSTERS_X - 1u), f32(CLUSTERS_Y - 1u))));
+    let index = tile.x + tile.y * CLUSTERS_X + cluster_slice(-view_position.z) * CLUSTERS_X * CLUSTERS_Y;
+    return LightCluster(index * MAX_CLUSTER_LIGHTS, min(cluster_light_counts[index], MAX_CLUSTER_LIGHTS));
+}
+
+fn cluster_light(cluster: LightCluster, i: u32) -> Light {
+    return light_list[cluster_light_indices[cluster.first + i]];
+}
+
+// Percentage closer filtering over (2 * radius + 1)^2 taps, each a bilinear 2x2 comparison
+fn filter_shadow(uv: vec2<f32>, layer: u32, depth: f32) -> f32 {
+    let radius = i32(lights.shadow.z);
+    let texel = lights.shadow.w;
+
+    var lit = 0.0;
+    var taps = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, i32(layer), depth);
+            taps += 1.0;
+        }
+    }
+    return lit / taps;
+}
+
+// 1 when fully lit. Directional lights use the first cascade containing the point.
+fn shadow_factor(light: Light, world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let layer_count = u32(light.cone.w);
+    if layer_count == 0u {
+        return 1.0;
+    }
+
+    let position = vec4<f32>(world_position + normal * lights.shadow.y, 1.0);
+    let first_layer = u32(light.cone.z);
+    for (var i = 0u; i < layer_count; i++) {
+        let layer = first_layer + i;
+        let clip = lights.shadow_matrices[layer] * position;
+        let ndc = clip.xyz / clip.w;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+
+        if all(uv >= vec2<f32>(0.0)) && all(uv <= vec2<f32>(1.0)) && ndc.z >= 0.0 && ndc.z <= 1.0 {
+            return filter_shadow(uv, layer, ndc.z - lights.shadow.x);
+        }
+    }
+    return 1.0;
+}
+
+// Direction towards the light and the light color reaching `world_position`, shadows included
+fn sample_light(light: Light, world_position: vec3<f32>, normal: vec3<f32>) -> LightSample {
+    var out: LightSample;
+    var attenuation = 1.0;
+
+    if light.position.w == KIND_DIRECTIONAL {
+        out.to_light = -light.direction.xyz;
+    } else {
+        let offset = light.position.xyz - world_position;
+        let distance = length(offset);
+        out.to_light = offset / max(distance, 0.0001);
+
+        let falloff = clamp(1.0 - distance / light.direction.w, 0.0, 1.0);
+        attenuation = falloff * falloff;
+
+        if light.position.w != KIND_POINT {
+            let cos_angle = dot(-out.to_light, light.direction.xyz);
+            attenuation *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+        }
+    }
+
+    out.radiance = light.color.rgb * light.color.w * attenuation * shadow_factor(light, world_position, normal);
+    return out;
+}
+
+// Screen space ambient occlusion at a fragment's @builtin(position), 1 when there is none
+fn screen_occlusion(frag_position: vec2<f32>) -> f32 {
+    if lights.count.z == 0u {
+        return 1.0;
+    }
+    let uv = frag_position / vec2<f32>(textureDimensions(t_screen_occlusion));
+    return textureSampleLevel(t_screen_occlusion, s_environment, uv, 0.0).r;
+}
+
+// Diffuse environment light arriving around `normal`, multiply by the albedo
+fn environment_diffuse(normal: vec3<f32>) -> vec3<f32> {
+    return textureSample(t_irradiance, s_environment, normal).rgb * lights.ambient.w;
+}
+
+// Split sum approximation of the GGX environment reflection
+fn environment_specular(normal: vec3<f32>, view: vec3<f32>, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let n_dot_v = max(dot(normal, view), 0.0001);
+    let reflected = reflect(-view, normal);
+
+    let lod = roughness * f32(lights.count.y);
+    let radiance = textureSampleLevel(t_prefiltered, s_environment, reflected, lod).rgb;
+    let brdf = textureSampleLevel(t_brdf_lut, s_environment, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+
+    return radiance * (f0 * brdf.x + brdf.y) * lights.ambient.w;
+}
@@ -0,0 +1,90 @@
+// Draw with `Material<PhongParams, NormalInstance>`
+
+#include "instance_inputs.wgsl"
+#include "lights.wgsl"
+#include "brdf.wgsl"
+#include "gbuffer.wgsl"
+
+struct VertexIn {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) tex_coords: vec2<f32>,
+    @location(3) normal: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) world_position: vec3<f32>,
+    @location(3) world_normal: vec3<f32>,
+};
+
+// position is a point (w = 1) or, for orthographic cameras, the direction towards the camera (w = 0)
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+}
+
+struct PhongParams {
+    diffuse: vec4<f32>,
+    specular: vec3<f32>,
+    shininess: f32,
+    blinn: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(2) @binding(0)
+var<uniform> params: PhongParams;
+
+@group(3) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(3) @binding(1)
+var s_diffuse: sampler;
+
+@vertex
+fn vs_main(
+    in: VertexIn,
+    instance: NormalInstanceIn
+) -> VertexOutput {
+    let world_position = normal_instance_model(instance) * vec4<f32>(in.position, 1.0);
+
+    var out: VertexOutput;
+    out.color = vec4<f32>(in.color, 1.0);
+    out.tex_coords = in.tex_coords;
+    out.world_position = world_position.xyz;
+    out.world_normal = normal_instance_normal(instance) * in.normal;
+    out.position = camera.view_projection * world_position;
+    return out;
+}
+
+fn view_direction(world_position: vec3<f32>) -> vec3<f32> {
+    if camera.position.w > 0.5 {
+        return normalize(camera.position.xyz - world_position);
+    }
+    return normalize(camera.position.xyz);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let base = params.diffuse * in.color * textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let normal = normalize(in.world_normal);
+    let view = view_direction(in.world_position);
+
+    var color = (lights.ambient.rgb + environment_diffuse(normal)) * base.rgb * screen_occlusion(in.position.xy);
+    let cluster = light_cluster(in.world_position);
+    for (var i = 0u; i < cluster.count; i++) {
+        color += shade_phong(cluster_light(cluster, i), normal, view, in.world_position, base.rgb, params.specular, params.shininess, params.blinn != 0u);
+    }
+
+    return vec4<f32>(color, base.a);
+}
+
+// The deferred path's G-buffer pass, alpha is ignored
+@fragment
+fn fs_gbuffer(in: VertexOutput) -> GBufferOutput {
+    let base = params.diffuse * in.color * textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    return gbuffer_phong(base.rgb, normalize(in.world_normal), params.specular, params.shininess, params.blinn != 0u);
+}
@@ -0,0 +1,70 @@
+// Auto exposure: a luminance histogram of the HDR scene, then its average adapted over time
+
+#include "exposure.wgsl"
+
+const BIN_COUNT: u32 = 256u;
+
+// Below this a pixel counts as black and goes to bin 0, which the average ignores
+const BLACK_LUMINANCE: f32 = 0.005;
+
+@group(1) @binding(0)
+var t_hdr: texture_2d<f32>;
+@group(1) @binding(1)
+var<storage, read_write> histogram: array<atomic<u32>, 256>;
+// The adapted luminance, kept between frames
+@group(1) @binding(2)
+var<storage, read_write> adapted: array<f32>;
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+var<workgroup> weighted: array<f32, 256>;
+
+fn bin_index(color: vec3<f32>) -> u32 {
+    let value = luminance(color);
+    if (value < BLACK_LUMINANCE) {
+        return 0u;
+    }
+
+    let position = clamp((log2(value) - tone_map.min_log_luminance) / tone_map.log_luminance_range, 0.0, 1.0);
+    return u32(position * f32(BIN_COUNT - 2u) + 1.0);
+}
+
+@compute @workgroup_size(16, 16)
+fn build_histogram(@builtin(global_invocation_id) id: vec3<u32>, @builtin(local_invocation_index) local: u32) {
+    atomicStore(&local_bins[local], 0u);
+    workgroupBarrier();
+
+    let size = textureDimensions(t_hdr);
+    if (id.x < size.x && id.y < size.y) {
+        let color = textureLoad(t_hdr, vec2<i32>(id.xy), 0).rgb;
+        atomicAdd(&local_bins[bin_index(color)], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram[local], atomicLoad(&local_bins[local]));
+}
+
+// One workgroup, one invocation per bin. Clears the histogram for the next frame.
+@compute @workgroup_size(256)
+fn average(@builtin(local_invocation_index) local: u32) {
+    let count = atomicLoad(&histogram[local]);
+    weighted[local] = f32(count) * f32(local);
+    atomicStore(&histogram[local], 0u);
+    workgroupBarrier();
+
+    for (var stride = BIN_COUNT / 2u; stride > 0u; stride = stride / 2u) {
+        if (local < stride) {
+            weighted[local] = weighted[local] + weighted[local + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if (local == 0u) {
+        // `count` is the number of black pixels here
+        let lit = max(f32(tone_map.pixel_count) - f32(count), 1.0);
+        let average_bin = weighted[0] / lit;
+        let average_log = (average_bin - 1.0) / f32(BIN_COUNT - 2u) * tone_map.log_luminance_range + tone_map.min_log_luminance;
+        let measured = exp2(average_log);
+
+        adapted[0] = adapted[0] + (measured - adapted[0]) * tone_map.adaptation;
+    }
+}
@@ -0,0 +1,168 @@
+// glTF metallic-roughness shading, see resources/brdf.wgsl.
+// Draw with `Material<PbrParams, NormalInstance>`
+
+#include "instance_inputs.wgsl"
+#include "lights.wgsl"
+#include "brdf.wgsl"
+#include "gbuffer.wgsl"
+
+struct VertexIn {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) tex_coords: vec2<f32>,
+    @location(3) normal: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) world_position: vec3<f32>,
+    @location(3) world_normal: vec3<f32>,
+};
+
+// position is a point (w = 1) or, for orthographic cameras, the direction towards the camera (w = 0)
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+}
+
+struct PbrParams {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(2) @binding(0)
+var<uniform> params: PbrParams;
+
+@group(3) @binding(0)
+var t_base_color: texture_2d<f32>;
+@group(3) @binding(1)
+var s_base_color: sampler;
+@group(3) @binding(2)
+var t_metallic_roughness: texture_2d<f32>;
+@group(3) @binding(3)
+var s_metallic_roughness: sampler;
+@group(3) @binding(4)
+var t_normal: texture_2d<f32>;
+@group(3) @binding(5)
+var s_normal: sampler;
+@group(3) @binding(6)
+var t_occlusion: texture_2d<f32>;
+@group(3) @binding(7)
+var s_occlusion: sampler;
+@group(3) @binding(8)
+var t_emissive: texture_2d<f32>;
+@group(3) @binding(9)
+var s_emissive: sampler;
+
+@vertex
+fn vs_main(
+    in: VertexIn,
+    instance: NormalInstanceIn
+) -> VertexOutput {
+    let world_position = normal_instance_model(instance) * vec4<f32>(in.position, 1.0);
+
+    var out: VertexOutput;
+    out.color = vec4<f32>(in.color, 1.0);
+    out.tex_coords = in.tex_coords;
+    out.world_position = world_position.xyz;
+    out.world_normal = normal_instance_normal(instance) * in.normal;
+    out.position = camera.view_projection * world_position;
+    return out;
+}
+
+fn view_direction(world_position: vec3<f32>) -> vec3<f32> {
+    if camera.position.w > 0.5 {
+        return normalize(camera.position.xyz - world_position);
+    }
+    return normalize(camera.position.xyz);
+}
+
+// Meshes have no tangents, the tangent frame is rebuilt from screen space derivatives.
+// glTF normal maps point +Y towards decreasing v, hence the flipped bitangent.
+fn perturb_normal(normal: vec3<f32>, world_position: vec3<f32>, uv: vec2<f32>, sampled: vec3<f32>) -> vec3<f32> {
+    let dp1 = dpdx(world_position);
+    let dp2 = dpdy(world_position);
+    let duv1 = dpdx(uv);
+    let duv2 = dpdy(uv);
+
+    let dp2_perp = cross(dp2, normal);
+    let dp1_perp = cross(normal, dp1);
+    let tangent = dp2_perp * duv1.x + dp1_perp * duv2.x;
+    let bitangent = -(dp2_perp * duv1.y + dp1_perp * duv2.y);
+
+    let scale = max(dot(tangent, tangent), dot(bitangent, bitangent));
+    if scale <= 0.0 {
+        return normal;
+    }
+
+    let inv_max = inverseSqrt(scale);
+    let tbn = mat3x3<f32>(tangent * inv_max, bitangent * inv_max, normal);
+
+    let mapped = (sampled * 2.0 - 1.0) * vec3<f32>(params.normal_scale, params.normal_scale, 1.0);
+    return normalize(tbn * mapped);
+}
+
+// The surface at a fragment with all textures applied, occlusion being the material's own
+struct PbrFragment {
+    surface: Surface,
+    roughness: f32,
+    occlusion: f32,
+    emissive: vec3<f32>,
+    alpha: f32,
+}
+
+fn pbr_fragment(in: VertexOutput) -> PbrFragment {
+    let base = params.base_color * in.color * textureSample(t_base_color, s_base_color, in.tex_coords);
+    let metallic_roughness = textureSample(t_metallic_roughness, s_metallic_roughness, in.tex_coords);
+    let sampled_normal = textureSample(t_normal, s_normal, in.tex_coords).rgb;
+    let occlusion = textureSample(t_occlusion, s_occlusion, in.tex_coords).r;
+
+    let metallic = clamp(params.metallic * metallic_roughness.b, 0.0, 1.0);
+    let roughness = clamp(params.roughness * metallic_roughness.g, 0.04, 1.0);
+
+    var out: PbrFragment;
+    out.surface.normal = perturb_normal(normalize(in.world_normal), in.world_position, in.tex_coords, sampled_normal);
+    out.surface.view = view_direction(in.world_position);
+    out.surface.albedo = base.rgb;
+    out.surface.f0 = mix(vec3<f32>(0.04), base.rgb, metallic);
+    out.surface.metallic = metallic;
+    out.surface.alpha = roughness * roughness;
+    out.roughness = roughness;
+    out.occlusion = mix(1.0, occlusion, params.occlusion_strength);
+    out.emissive = params.emissive * textureSample(t_emissive, s_emissive, in.tex_coords).rgb;
+    out.alpha = base.a;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let fragment = pbr_fragment(in);
+
+    // Occlusion only darkens indirect light
+    let ambient_occlusion = fragment.occlusion * screen_occlusion(in.position.xy);
+    let irradiance = environment_diffuse(fragment.surface.normal);
+    var color = indirect_pbr(fragment.surface, fragment.roughness, irradiance) * ambient_occlusion;
+    let cluster = light_cluster(in.world_position);
+    for (var i = 0u; i < cluster.count; i++) {
+        color += shade_pbr(cluster_light(cluster, i), fragment.surface, in.world_position);
+    }
+
+    return vec4<f32>(color + fragment.emissive, fragment.alpha);
+}
+
+// The deferred path's G-buffer pass, alpha is ignored
+@fragment
+fn fs_gbuffer(in: VertexOutput) -> GBufferOutput {
+    let fragment = pbr_fragment(in);
+    let surface = fragment.surface;
+    return gbuffer_pbr(surface.albedo, fragment.occlusion, surface.normal, surface.metallic, fragment.roughness, fragment.emissive);
+}
@@ -0,0 +1,46 @@
+// Shared part of the post processing effects, see src/graphics/post.rs.
+// Each effect file includes it and adds its `fs_main`, reading the previous result through `sample_input`.
+
+struct PostUniform {
+    // 1 / width, 1 / height, width, height of the input
+    texel: vec4<f32>,
+    // One parameter per slot in the order the effect declares them.
+    // Floats and toggles (0 or 1) are in x, colors in xyz.
+    values: array<vec4<f32>, 8>,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+@group(1) @binding(0)
+var<uniform> post: PostUniform;
+
+struct PostVertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// One triangle covering the screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> PostVertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: PostVertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn sample_input(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(t_input, s_input, uv, 0.0);
+}
+
+fn param(index: i32) -> vec4<f32> {
+    return post.values[index];
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
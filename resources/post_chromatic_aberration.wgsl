@@ -0,0 +1,14 @@
+// Splits red and blue apart towards the edges of the screen.
+// Parameters: 0 strength in pixels at the corners
+
+#include "post.wgsl"
+
+@fragment
+fn fs_main(in: PostVertexOutput) -> @location(0) vec4<f32> {
+    let offset = (in.uv - 0.5) * 2.0 * param(0).x * post.texel.xy;
+
+    let color = sample_input(in.uv);
+    let red = sample_input(in.uv + offset).r;
+    let blue = sample_input(in.uv - offset).b;
+    return vec4<f32>(red, color.g, blue, color.a);
+}
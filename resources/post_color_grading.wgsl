@@ -0,0 +1,32 @@
+// Color grading through a lookup table stored as a horizontal strip of `size` slices of size x size
+// texels, red along x within a slice, green along y and blue picking the slice.
+// Parameters: 0 intensity
+
+#include "post.wgsl"
+
+@group(2) @binding(0)
+var t_lut: texture_2d<f32>;
+@group(2) @binding(1)
+var s_lut: sampler;
+
+fn sample_lut(color: vec3<f32>, size: f32) -> vec3<f32> {
+    let scaled = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)) * (size - 1.0);
+    let slice = floor(scaled.b);
+    let blend = scaled.b - slice;
+
+    // Texel centers, so filtering only blends neighbours within a slice
+    let xy = (scaled.rg + 0.5) / vec2<f32>(size * size, size);
+    let first = xy + vec2<f32>(slice / size, 0.0);
+    let second = xy + vec2<f32>(min(slice + 1.0, size - 1.0) / size, 0.0);
+
+    return mix(textureSampleLevel(t_lut, s_lut, first, 0.0).rgb,
+        textureSampleLevel(t_lut, s_lut, second, 0.0).rgb,
+        blend);
+}
+
+@fragment
+fn fs_main(in: PostVertexOutput) -> @location(0) vec4<f32> {
+    let color = sample_input(in.uv);
+    let graded = sample_lut(color.rgb, f32(textureDimensions(t_lut).y));
+    return vec4<f32>(mix(color.rgb, graded, param(0).x), color.a);
+}
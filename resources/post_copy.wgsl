@@ -0,0 +1,8 @@
+// Copies the input as is, used when no effect is enabled
+
+#include "post.wgsl"
+
+@fragment
+fn fs_main(in: PostVertexOutput) -> @location(0) vec4<f32> {
+    return sample_input(in.uv);
+}
@@ -0,0 +1,48 @@
+// Fast approximate anti-aliasing, blurring along the edge direction found from luma.
+// Parameters: 0 maximum span in pixels, 1 direction reduction
+
+#include "post.wgsl"
+
+// Below this the direction reduction never goes
+const FXAA_REDUCE_MIN: f32 = 0.0078125; // 1 / 128
+
+// Perceptual luma, the input is linear
+fn fxaa_luma(color: vec3<f32>) -> f32 {
+    return sqrt(luminance(color));
+}
+
+@fragment
+fn fs_main(in: PostVertexOutput) -> @location(0) vec4<f32> {
+    let texel = post.texel.xy;
+    let center = sample_input(in.uv);
+
+    let luma_nw = fxaa_luma(sample_input(in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb);
+    let luma_ne = fxaa_luma(sample_input(in.uv + vec2<f32>(1.0, -1.0) * texel).rgb);
+    let luma_sw = fxaa_luma(sample_input(in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb);
+    let luma_se = fxaa_luma(sample_input(in.uv + vec2<f32>(1.0, 1.0) * texel).rgb);
+    let luma_m = fxaa_luma(center.rgb);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var direction = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se));
+
+    let reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * param(1).x, FXAA_REDUCE_MIN);
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    let span = param(0).x;
+    direction = clamp(direction * scale, vec2<f32>(-span), vec2<f32>(span)) * texel;
+
+    let near = 0.5 * (sample_input(in.uv + direction * (1.0 / 3.0 - 0.5)).rgb
+        + sample_input(in.uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+    let far = near * 0.5 + 0.25 * (sample_input(in.uv - direction * 0.5).rgb
+        + sample_input(in.uv + direction * 0.5).rgb);
+
+    // The wider blur is only kept if it didn't pick up colors from across another edge
+    let luma_far = fxaa_luma(far);
+    if (luma_far < luma_min || luma_far > luma_max) {
+        return vec4<f32>(near, center.a);
+    }
+    return vec4<f32>(far, center.a);
+}
@@ -0,0 +1,11 @@
+// Blends towards the luminance of the input, tinted brown when sepia is on.
+// Parameters: 0 amount, 1 sepia
+
+#include "post.wgsl"
+
+@fragment
+fn fs_main(in: PostVertexOutput) -> @location(0) vec4<f32> {
+    let color = sample_input(in.uv);
+    let tint = select(vec3<f32>(1.0), vec3<f32>(1.07, 0.74, 0.43), param(1).x > 0.5);
+    return vec4<f32>(mix(color.rgb, luminance(color.rgb) * tint, param(0).x), color.a);
+}
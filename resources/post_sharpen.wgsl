@@ -0,0 +1,17 @@
+// Unsharp mask over the four direct neighbours.
+// Parameters: 0 amount
+
+#include "post.wgsl"
+
+@fragment
+fn fs_main(in: PostVertexOutput) -> @location(0) vec4<f32> {
+    let texel = post.texel.xy;
+    let color = sample_input(in.uv);
+    let neighbours = sample_input(in.uv + vec2<f32>(texel.x, 0.0)).rgb
+        + sample_input(in.uv - vec2<f32>(texel.x, 0.0)).rgb
+        + sample_input(in.uv + vec2<f32>(0.0, texel.y)).rgb
+        + sample_input(in.uv - vec2<f32>(0.0, texel.y)).rgb;
+
+    let sharpened = color.rgb + (color.rgb * 4.0 - neighbours) * param(0).x;
+    return vec4<f32>(max(sharpened, vec3<f32>(0.0)), color.a);
+}
@@ -0,0 +1,17 @@
+// Darkens the corners towards a color.
+// Parameters: 0 intensity, 1 radius, 2 softness, 3 color
+
+#include "post.wgsl"
+
+@fragment
+fn fs_main(in: PostVertexOutput) -> @location(0) vec4<f32> {
+    let color = sample_input(in.uv);
+
+    // Round regardless of the aspect ratio
+    let aspect = post.texel.z / post.texel.w;
+    let offset = (in.uv - 0.5) * vec2<f32>(aspect, 1.0);
+    let radius = param(1).x;
+    let amount = smoothstep(radius, radius + param(2).x, length(offset)) * param(0).x;
+
+    return vec4<f32>(mix(color.rgb, param(3).rgb, amount), color.a);
+}
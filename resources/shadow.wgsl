@@ -0,0 +1,21 @@
+// Depth only pass rendering shadow casters from a light, see src/graphics/shadows.rs.
+// Also the SSAO depth prepass, bound to the camera uniform which starts with its view-projection.
+// Every `ModelInstance` starts with the model matrix at locations 5 to 8, so InstanceVertexIn
+// reads all of them.
+
+#include "instance_inputs.wgsl"
+
+struct ShadowUniform {
+    view_projection: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> shadow: ShadowUniform;
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    instance: InstanceVertexIn
+) -> @builtin(position) vec4<f32> {
+    return shadow.view_projection * instance_model(instance) * vec4<f32>(position, 1.0);
+}
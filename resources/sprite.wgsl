@@ -0,0 +1,50 @@
+// Draw with `Material<ColorParams, SpriteInstance>`
+
+#include "instance_inputs.wgsl"
+
+struct VertexIn {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) tex_coords: vec2<f32>,
+    @location(3) normal: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+}
+
+struct ColorParams {
+    color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var<uniform> params: ColorParams;
+
+@group(2) @binding(0)
+var t_sprite: texture_2d<f32>;
+@group(2) @binding(1)
+var s_sprite: sampler;
+
+@vertex
+fn vs_main(
+    in: VertexIn,
+    instance: SpriteInstanceIn
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = sprite_instance_uv(instance, in.tex_coords);
+    out.position = camera.view_projection * sprite_instance_model(instance) * vec4<f32>(in.position, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return params.color * textureSample(t_sprite, s_sprite, in.tex_coords);
+}
@@ -0,0 +1,160 @@
+// Screen space ambient occlusion, see src/graphics/ssao.rs. Positions are reconstructed in view
+// space from the depth prepass, normals from the neighbouring depths, or both come from the
+// G-buffer in the deferred path. The kernel is a hemisphere around the normal, rotated per pixel
+// and blurred afterwards to hide the noise.
+
+const MAX_SSAO_SAMPLES: u32 = 64u;
+
+// params holds the radius, depth bias, strength and sample count, blur.x the blur radius in pixels
+struct SsaoUniform {
+    projection: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    view: mat4x4<f32>,
+    params: vec4<f32>,
+    blur: vec4<f32>,
+    kernel: array<vec4<f32>, MAX_SSAO_SAMPLES>,
+}
+
+@group(0) @binding(0)
+var t_depth: texture_depth_2d;
+// World space normals of the G-buffer, only bound for `fs_occlusion_gbuffer`
+@group(0) @binding(1)
+var t_normal: texture_2d<f32>;
+
+@group(1) @binding(0)
+var<uniform> ssao: SsaoUniform;
+
+// Unblurred occlusion, only bound for the blur
+@group(2) @binding(0)
+var t_occlusion: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// One triangle covering the screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn depth_size() -> vec2<i32> {
+    return vec2<i32>(textureDimensions(t_depth));
+}
+
+fn load_depth(pixel: vec2<i32>) -> f32 {
+    return textureLoad(t_depth, clamp(pixel, vec2<i32>(0), depth_size() - 1), 0);
+}
+
+fn view_position(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    let position = ssao.inverse_projection * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+fn pixel_position(pixel: vec2<i32>) -> vec3<f32> {
+    let uv = (vec2<f32>(pixel) + 0.5) / vec2<f32>(depth_size());
+    return view_position(uv, load_depth(pixel));
+}
+
+// Takes the neighbour on the side with the smaller depth step, so normals don't bend over edges
+fn view_normal(pixel: vec2<i32>, center: vec3<f32>) -> vec3<f32> {
+    let left = pixel_position(pixel - vec2<i32>(1, 0));
+    let right = pixel_position(pixel + vec2<i32>(1, 0));
+    let up = pixel_position(pixel - vec2<i32>(0, 1));
+    let down = pixel_position(pixel + vec2<i32>(0, 1));
+
+    var dx = right - center;
+    if abs(center.z - left.z) < abs(right.z - center.z) {
+        dx = center - left;
+    }
+    var dy = up - center;
+    if abs(center.z - down.z) < abs(up.z - center.z) {
+        dy = center - down;
+    }
+    return normalize(cross(dx, dy));
+}
+
+// Interleaved gradient noise, turned into the kernel's rotation around the normal
+fn noise(pixel: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+// Occlusion of the view space `position` facing `normal`
+fn ambient_occlusion(frag_position: vec2<f32>, position: vec3<f32>, normal: vec3<f32>) -> vec4<f32> {
+    let angle = noise(frag_position) * 6.2831853;
+    let random = vec3<f32>(cos(angle), sin(angle), 0.0);
+    let tangent = normalize(random - normal * dot(random, normal));
+    let tbn = mat3x3<f32>(tangent, cross(normal, tangent), normal);
+
+    let radius = ssao.params.x;
+    let bias = ssao.params.y;
+    let count = min(u32(ssao.params.w), MAX_SSAO_SAMPLES);
+
+    var occlusion = 0.0;
+    for (var i = 0u; i < count; i++) {
+        let sample = position + tbn * ssao.kernel[i].xyz * radius;
+        let clip = ssao.projection * vec4<f32>(sample, 1.0);
+        let uv = vec2<f32>(clip.x / clip.w * 0.5 + 0.5, 0.5 - clip.y / clip.w * 0.5);
+        let scene = pixel_position(vec2<i32>(uv * vec2<f32>(depth_size())));
+
+        // Occluders far in front of the point are a different object and fade out
+        let range = smoothstep(0.0, 1.0, radius / abs(position.z - scene.z));
+        if scene.z >= sample.z + bias {
+            occlusion += range;
+        }
+    }
+
+    let visibility = 1.0 - occlusion / f32(max(count, 1u));
+    return vec4<f32>(pow(visibility, ssao.params.z), 0.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_occlusion(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let depth = load_depth(pixel);
+    // Nothing was drawn here
+    if depth >= 1.0 {
+        return vec4<f32>(1.0);
+    }
+
+    let position = view_position(in.uv, depth);
+    return ambient_occlusion(in.position.xy, position, view_normal(pixel, position));
+}
+
+@fragment
+fn fs_occlusion_gbuffer(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let depth = load_depth(pixel);
+    if depth >= 1.0 {
+        return vec4<f32>(1.0);
+    }
+
+    let normal = normalize((ssao.view * vec4<f32>(textureLoad(t_normal, pixel, 0).xyz, 0.0)).xyz);
+    return ambient_occlusion(in.position.xy, view_position(in.uv, depth), normal);
+}
+
+// Box blur ignoring neighbours further than the radius away in depth, so occlusion stays on its surface
+@fragment
+fn fs_blur(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let center = pixel_position(pixel).z;
+    let radius = i32(ssao.blur.x);
+
+    var total = 0.0;
+    var weights = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            let neighbour = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), depth_size() - 1);
+            let weight = max(1.0 - abs(pixel_position(neighbour).z - center) / ssao.params.x, 0.0);
+            total += textureLoad(t_occlusion, neighbour, 0).r * weight;
+            weights += weight;
+        }
+    }
+    return vec4<f32>(total / max(weights, 0.0001), 0.0, 0.0, 1.0);
+}
@@ -0,0 +1,78 @@
+// Temporal anti-aliasing, see src/graphics/antialiasing.rs. Every frame is drawn with a different
+// subpixel jitter and blended into a history, which is clamped to the current frame's neighbourhood
+// so that it can't keep colors that are no longer there.
+
+struct TaaUniform {
+    // Weight of the current frame
+    blend: f32,
+    // 1 when the history holds nothing usable yet
+    reset: u32,
+    _padding: vec2<u32>,
+}
+
+@group(0) @binding(0)
+var t_current: texture_2d<f32>;
+@group(0) @binding(1)
+var s_current: sampler;
+
+@group(1) @binding(0)
+var t_history: texture_2d<f32>;
+@group(1) @binding(1)
+var s_history: sampler;
+
+@group(2) @binding(0)
+var<uniform> taa: TaaUniform;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// One triangle covering the screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+@fragment
+fn fs_resolve(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(t_current));
+    let pixel = vec2<i32>(in.position.xy);
+    let current = textureLoad(t_current, pixel, 0);
+    if (taa.reset != 0u) {
+        return current;
+    }
+
+    var lowest = current.rgb;
+    var highest = current.rgb;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let neighbour = textureLoad(t_current, clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), size - 1), 0).rgb;
+            lowest = min(lowest, neighbour);
+            highest = max(highest, neighbour);
+        }
+    }
+
+    let history = clamp(textureSampleLevel(t_history, s_history, in.uv, 0.0).rgb, lowest, highest);
+
+    // Weighted by inverse luminance so single bright HDR samples don't dominate the blend
+    let current_weight = taa.blend / (1.0 + luminance(current.rgb));
+    let history_weight = (1.0 - taa.blend) / (1.0 + luminance(history));
+    let color = (current.rgb * current_weight + history * history_weight) / (current_weight + history_weight);
+    return vec4<f32>(color, current.a);
+}
+
+// Writes the resolved frame back
+@fragment
+fn fs_copy(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(t_current, s_current, in.uv, 0.0);
+}
@@ -0,0 +1,89 @@
+// Maps the HDR scene to display range, exposed manually or from resources/luminance.wgsl
+
+#include "exposure.wgsl"
+
+// Middle gray the average scene luminance is exposed to
+const KEY_VALUE: f32 = 0.18;
+
+@group(1) @binding(0)
+var t_hdr: texture_2d<f32>;
+@group(1) @binding(1)
+var s_hdr: sampler;
+@group(1) @binding(2)
+var<storage, read> adapted: array<f32>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// One triangle covering the screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let mapped = (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14);
+    return clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Polynomial fit of the AgX base contrast curve
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104));
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116));
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var value = inset * max(color, vec3<f32>(1e-10));
+    value = clamp(log2(value), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    value = agx_contrast((value - min_ev) / (max_ev - min_ev));
+
+    // The curve produces display encoded values, the sRGB target expects linear ones
+    value = outset * value;
+    return pow(max(value, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+fn exposure() -> f32 {
+    let compensation = exp2(tone_map.exposure_compensation);
+    if (tone_map.auto_exposure == 0u) {
+        return compensation;
+    }
+    return KEY_VALUE / max(adapted[0], 1e-4) * compensation;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let hdr = textureSampleLevel(t_hdr, s_hdr, in.uv, 0.0);
+    let color = hdr.rgb * exposure();
+
+    var mapped: vec3<f32>;
+    switch tone_map.curve {
+        case 1u: { mapped = aces(color); }
+        case 2u: { mapped = agx(color); }
+        default: { mapped = reinhard(color); }
+    }
+    return vec4<f32>(mapped, hdr.a);
+}
@@ -0,0 +1,270 @@
+use cgmath::Vector2;
+use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, Device, Queue, TextureView};
+
+use super::{RenderTarget, Shader, TargetFormat, Uniform, HDR_FORMAT};
+
+// Jitter positions cycled through before repeating
+const JITTER_SAMPLES: u32 = 8;
+
+// Post process anti-aliasing, on top of whatever MSAA the frame uses.
+// FXAA is a `PostEffect`, TAA is `TemporalAA` and needs the camera jittered every frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AntiAliasing {
+    None,
+    Fxaa,
+    Taa,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TaaUniform {
+    blend: f32,
+    reset: u32,
+    _padding: [u32; 2],
+}
+
+// Resolves a jittered HDR target against a history of previous frames, in place.
+// The history is clamped to each pixel's neighbourhood rather than reprojected, so moving
+// things lose their history instead of ghosting.
+pub struct TemporalAA {
+    pub enabled: bool,
+    // Weight of the newest frame, lower is smoother but slower to react
+    pub blend: f32,
+    frame: u32,
+    uniform: Uniform<TaaUniform>,
+    source_layout: BindGroupLayout,
+    sampler: wgpu::Sampler,
+    resolve_pipeline: wgpu::RenderPipeline,
+    copy_pipeline: wgpu::RenderPipeline,
+    history: Option<History>,
+}
+
+// Two targets taking turns being the history and the result, with the input's bind group
+struct History {
+    targets: [(RenderTarget, BindGroup); 2],
+    input_group: BindGroup,
+    input_generation: u32,
+    current: usize,
+    valid: bool,
+}
+
+impl TemporalAA {
+    pub fn new(device: &Device) -> Self {
+        let uniform = Uniform::new(device,
+            "TAA Uniform Buffer",
+            wgpu::ShaderStages::FRAGMENT,
+            TaaUniform {
+                blend: 0.1,
+                reset: 1,
+                _padding: [0; 2],
+            });
+
+        let source_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("TAA Source Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry { // texture entry
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry { // sampler entry
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("TAA Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let shader = Shader::new("resources/taa.wgsl", device);
+        let resolve_pipeline = TemporalAA::create_pipeline(device, &shader, "fs_resolve", &[&source_layout, &source_layout, &uniform.bind_layout]);
+        let copy_pipeline = TemporalAA::create_pipeline(device, &shader, "fs_copy", &[&source_layout]);
+
+        Self {
+            enabled: false,
+            blend: 0.1,
+            frame: 0,
+            uniform,
+            source_layout,
+            sampler,
+            resolve_pipeline,
+            copy_pipeline,
+            history: None,
+        }
+    }
+
+    // The next offset to give `Camera::set_jitter`, zero while disabled.
+    // Follows the Halton (2, 3) sequence, within one pixel of a `size` sized target.
+    pub fn next_jitter(&mut self, size: (u32, u32)) -> Vector2<f32> {
+        if !self.enabled {
+            return Vector2::new(0.0, 0.0);
+        }
+
+        self.frame = (self.frame + 1) % JITTER_SAMPLES;
+        let index = self.frame + 1;
+        Vector2::new(
+            (halton(index, 2) - 0.5) * 2.0 / size.0 as f32,
+            (halton(index, 3) - 0.5) * 2.0 / size.1 as f32)
+    }
+
+    // Forgets the history, e.g. after a camera cut
+    pub fn reset(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.valid = false;
+        }
+    }
+
+    // Blends `input` into the history and writes the result back into its single sampled view
+    pub fn render(&mut self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, input: &RenderTarget) {
+        if !self.enabled {
+            self.reset();
+            return;
+        }
+
+        if !matches!(&self.history, Some(history) if history.input_generation == input.generation()) {
+            self.history = Some(self.create_history(device, input));
+        }
+        let history = self.history.as_mut().unwrap();
+
+        self.uniform.set(TaaUniform {
+            blend: self.blend,
+            reset: !history.valid as u32,
+            _padding: [0; 2],
+        });
+        self.uniform.update_buffer(queue);
+
+        let previous = &history.targets[1 - history.current];
+        let current = &history.targets[history.current];
+
+        TemporalAA::draw(encoder, "TAA Resolve", &self.resolve_pipeline, &current.0.color_view,
+            &[&history.input_group, &previous.1, &self.uniform.bind_group]);
+        TemporalAA::draw(encoder, "TAA Copy", &self.copy_pipeline, input.sampled_view(), &[&current.1]);
+
+        history.current = 1 - history.current;
+        history.valid = true;
+    }
+
+    fn draw(encoder: &mut CommandEncoder,
+        label: &str,
+        pipeline: &wgpu::RenderPipeline,
+        destination: &TextureView,
+        groups: &[&BindGroup]) {
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: destination,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(pipeline);
+        for (index, group) in groups.iter().enumerate() {
+            render_pass.set_bind_group(index as u32, group, &[]);
+        }
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_history(&self, device: &Device, input: &RenderTarget) -> History {
+        let format = TargetFormat {
+            color: HDR_FORMAT,
+            depth: None,
+            sample_count: 1,
+        };
+
+        let targets = [0, 1].map(|i| {
+            let target = RenderTarget::new(device, &format!("TAA History {i}"), format, 1.0, input.size());
+            let group = self.create_source_group(device, target.sampled_view());
+            (target, group)
+        });
+
+        History {
+            targets,
+            input_group: self.create_source_group(device, input.sampled_view()),
+            input_generation: input.generation(),
+            current: 0,
+            valid: false,
+        }
+    }
+
+    fn create_source_group(&self, device: &Device, view: &TextureView) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("TAA Source Bind Group"),
+            layout: &self.source_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+
+    fn create_pipeline(device: &Device, shader: &Shader, entry_point: &str, bind_layouts: &[&BindGroupLayout]) -> wgpu::RenderPipeline {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("TAA Pipeline Layout"),
+            bind_group_layouts: bind_layouts,
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader.module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader.module,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+}
+
+// Low discrepancy sequence in [0, 1), `index` starting at 1
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use image::RgbaImage;
+use wgpu::{Device, Queue};
+
+use super::Texture2D;
+
+#[derive(Copy, Clone, Debug)]
+pub struct AtlasRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+}
+
+pub struct TextureAtlas {
+    pub texture: Rc<Texture2D>,
+    pub width: u32,
+    pub height: u32,
+    padding: u32,
+    extrude: u32,
+    skyline: Skyline,
+    regions: HashMap<String, AtlasRegion>,
+}
+
+impl TextureAtlas {
+    // `images` are (name, file) pairs. Every image is surrounded by `extrude` texels
+    // copied from its edges, then `padding` empty texels, to stop filtering from bleeding.
+    pub fn new(device: &Device,
+        queue: &Queue,
+        width: u32,
+        height: u32,
+        padding: u32,
+        extrude: u32,
+        images: &[(&str, &str)]) -> Self {
+
+        let loaded: Vec<(&str, RgbaImage)> = images.iter()
+            .map(|(name, file)| {
+                let image = image::open(file)
+                    .expect("Cannot load atlas image")
+                    .to_rgba8();
+                (*name, image)
+            })
+            .collect();
+
+        TextureAtlas::from_images(device, queue, width, height, padding, extrude, loaded)
+    }
+
+    // Like `new` with images already in memory, names must be unique
+    pub fn from_images(device: &Device,
+        queue: &Queue,
+        width: u32,
+        height: u32,
+        padding: u32,
+        extrude: u32,
+        mut images: Vec<(&str, RgbaImage)>) -> Self {
+
+        // Taller images first packs a skyline noticeably tighter
+        images.sort_by_key(|(_, image)| std::cmp::Reverse(image.height()));
+
+        let mut skyline = Skyline::new(width, height);
+        let mut regions = HashMap::new();
+        let mut pixels = vec![0u8; (width * height * 4) as usize];
+
+        for (name, image) in images.iter() {
+            assert!(!regions.contains_key(*name), "Atlas image {name} is given twice");
+
+            let (region, block) = TextureAtlas::place(&mut skyline, width, height, padding, extrude, image)
+                .unwrap_or_else(|| panic!("Atlas is too small to fit image {name}"));
+
+            let (block_w, block_h) = block.dimensions();
+            let (block_x, block_y) = (region.x - extrude, region.y - extrude);
+            for row in 0..block_h {
+                let src = (row * block_w * 4) as usize;
+                let dst = (((block_y + row) * width + block_x) * 4) as usize;
+                pixels[dst..dst + (block_w * 4) as usize].copy_from_slice(&block.as_raw()[src..src + (block_w * 4) as usize]);
+            }
+
+            regions.insert(name.to_string(), region);
+        }
+
+        let texture = Rc::new(Texture2D::from_rgba(device, queue, width, height, pixels));
+
+        Self {
+            texture,
+            width,
+            height,
+            padding,
+            extrude,
+            skyline,
+            regions,
+        }
+    }
+
+    pub fn region(&self, name: &str) -> Option<&AtlasRegion> {
+        self.regions.get(name)
+    }
+
+    pub fn regions(&self) -> &HashMap<String, AtlasRegion> {
+        &self.regions
+    }
+
+    pub fn add_image(&mut self, queue: &Queue, name: &str, file: &str) -> Result<AtlasRegion, String> {
+        let image = image::open(file)
+            .map_err(|e| format!("Cannot load atlas image {file}: {e}"))?
+            .to_rgba8();
+
+        self.add_rgba(queue, name, &image)
+    }
+
+    // Packs an image into the remaining space. An image of the same size as the one already
+    // under `name` replaces it in place, one of another size is an error as its space can't be reused.
+    pub fn add_rgba(&mut self, queue: &Queue, name: &str, image: &RgbaImage) -> Result<AtlasRegion, String> {
+        let (region, block) = match self.regions.get(name) {
+            Some(region) if (region.width, region.height) == image.dimensions() => (*region, extrude_edges(image, self.extrude)),
+            Some(_) => return Err(format!("Atlas image {name} already exists with another size")),
+            None => TextureAtlas::place(&mut self.skyline, self.width, self.height, self.padding, self.extrude, image)
+                .ok_or_else(|| format!("Atlas has no room left for image {name}"))?,
+        };
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: region.x - self.extrude, y: region.y - self.extrude, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            block.as_raw(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * block.width()),
+                rows_per_image: Some(block.height()),
+            },
+            wgpu::Extent3d {
+                width: block.width(),
+                height: block.height(),
+                depth_or_array_layers: 1,
+            }
+        );
+
+        self.regions.insert(name.to_string(), region);
+        Ok(region)
+    }
+
+    fn place(skyline: &mut Skyline,
+        width: u32,
+        height: u32,
+        padding: u32,
+        extrude: u32,
+        image: &RgbaImage) -> Option<(AtlasRegion, RgbaImage)> {
+
+        let border = padding + extrude;
+        let (x, y) = skyline.insert(image.width() + 2 * border, image.height() + 2 * border)?;
+
+        let region = AtlasRegion {
+            x: x + border,
+            y: y + border,
+            width: image.width(),
+            height: image.height(),
+            uv_min: [(x + border) as f32 / width as f32, (y + border) as f32 / height as f32],
+            uv_max: [(x + border + image.width()) as f32 / width as f32, (y + border + image.height()) as f32 / height as f32],
+        };
+
+        Some((region, extrude_edges(image, extrude)))
+    }
+}
+
+// Grows the image by `extrude` texels on every side, repeating the outermost texels.
+// An empty image has no edges to repeat, so it grows into a transparent block.
+fn extrude_edges(image: &RgbaImage, extrude: u32) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    if w == 0 || h == 0 {
+        return RgbaImage::new(w + 2 * extrude, h + 2 * extrude);
+    }
+
+    RgbaImage::from_fn(w + 2 * extrude, h + 2 * extrude, |x, y| {
+        let sx = x.saturating_sub(extrude).min(w - 1);
+        let sy = y.saturating_sub(extrude).min(h - 1);
+        *image.get_pixel(sx, sy)
+    })
+}
+
+#[derive(Copy, Clone)]
+struct SkylineNode {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+// Bottom-left skyline bin packer
+struct Skyline {
+    width: u32,
+    height: u32,
+    nodes: Vec<SkylineNode>,
+}
+
+impl Skyline {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            nodes: vec![SkylineNode { x: 0, y: 0, width }],
+        }
+    }
+
+    // Lowest y a rect can sit at when its left edge starts at node `index`
+    fn fit(&self, index: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.nodes[index].x;
+        if x + width > self.width {
+            return None;
+        }
+
+        let mut y = 0;
+        let mut remaining = width as i64;
+        let mut i = index;
+        while remaining > 0 {
+            y = y.max(self.nodes[i].y);
+            if y + height > self.height {
+                return None;
+            }
+            remaining -= self.nodes[i].width as i64;
+            i += 1;
+        }
+
+        Some(y)
+    }
+
+    fn insert(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let mut best: Option<(usize, u32)> = None;
+        for index in 0..self.nodes.len() {
+            if let Some(y) = self.fit(index, width, height) {
+                let better = match best {
+                    Some((best_index, best_y)) => y < best_y || (y == best_y && self.nodes[index].width < self.nodes[best_index].width),
+                    None => true,
+                };
+                if better {
+                    best = Some((index, y));
+                }
+            }
+        }
+
+        let (index, y) = best?;
+        let x = self.nodes[index].x;
+        self.nodes.insert(index, SkylineNode { x, y: y + height, width });
+
+        // Trim the nodes now covered by the new one
+        let i = index + 1;
+        while i < self.nodes.len() {
+            let prev_end = self.nodes[i - 1].x + self.nodes[i - 1].width;
+            if self.nodes[i].x >= prev_end {
+                break;
+            }
+            let overlap = prev_end - self.nodes[i].x;
+            if self.nodes[i].width <= overlap {
+                self.nodes.remove(i);
+            } else {
+                self.nodes[i].x += overlap;
+                self.nodes[i].width -= overlap;
+                break;
+            }
+        }
+
+        // Merge neighbours at the same height
+        let mut i = 0;
+        while i + 1 < self.nodes.len() {
+            if self.nodes[i].y == self.nodes[i + 1].y {
+                self.nodes[i].width += self.nodes[i + 1].width;
+                self.nodes.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+
+        Some((x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn skyline_places_bottom_left() {
+        let mut skyline = Skyline::new(10, 10);
+        assert_eq!(skyline.insert(4, 4), Some((0, 0)));
+        assert_eq!(skyline.insert(4, 4), Some((4, 0)));
+        // Only 2 texels are left at the bottom, so the next one goes on top of the first
+        assert_eq!(skyline.insert(4, 4), Some((0, 4)));
+        assert_eq!(skyline.insert(2, 10), Some((8, 0)));
+    }
+
+    #[test]
+    fn skyline_rejects_what_does_not_fit() {
+        let mut skyline = Skyline::new(10, 10);
+        assert_eq!(skyline.insert(11, 1), None);
+        assert_eq!(skyline.insert(1, 11), None);
+
+        assert_eq!(skyline.insert(10, 8), Some((0, 0)));
+        assert_eq!(skyline.insert(5, 3), None);
+        assert_eq!(skyline.insert(5, 2), Some((0, 8)));
+    }
+
+    #[test]
+    fn skyline_rects_do_not_overlap() {
+        let mut skyline = Skyline::new(64, 64);
+        let mut placed: Vec<(u32, u32, u32, u32)> = Vec::new();
+        for i in 0..40u32 {
+            let (w, h) = (3 + i * 7 % 9, 2 + i * 5 % 11);
+            if let Some((x, y)) = skyline.insert(w, h) {
+                assert!(x + w <= 64 && y + h <= 64);
+                for &(px, py, pw, ph) in &placed {
+                    assert!(x >= px + pw || px >= x + w || y >= py + ph || py >= y + h,
+                        "{w}x{h} at ({x}, {y}) overlaps {pw}x{ph} at ({px}, {py})");
+                }
+                placed.push((x, y, w, h));
+            }
+        }
+        assert!(placed.len() > 20);
+    }
+
+    #[test]
+    fn extrusion_repeats_edges() {
+        let (red, blue) = (Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255]));
+        let image = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { red } else { blue });
+
+        let extruded = extrude_edges(&image, 1);
+        assert_eq!(extruded.dimensions(), (4, 3));
+        for y in 0..3 {
+            assert_eq!(*extruded.get_pixel(0, y), red);
+            assert_eq!(*extruded.get_pixel(1, y), red);
+            assert_eq!(*extruded.get_pixel(2, y), blue);
+            assert_eq!(*extruded.get_pixel(3, y), blue);
+        }
+
+        assert_eq!(extrude_edges(&image, 0), image);
+    }
+
+    #[test]
+    fn extrusion_of_empty_image_is_transparent() {
+        let extruded = extrude_edges(&RgbaImage::new(0, 0), 2);
+        assert_eq!(extruded.dimensions(), (4, 4));
+        assert!(extruded.pixels().all(|pixel| pixel.0 == [0; 4]));
+    }
+}
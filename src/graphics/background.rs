@@ -0,0 +1,214 @@
+use std::rc::Rc;
+
+use cgmath::{Deg, SquareMatrix};
+use wgpu::{BindGroup, BindGroupLayout, Device, Queue, RenderPass};
+
+use super::camera::OPENGL_TO_WGPU_MATRIX;
+use super::{Camera, Projection, Shader, TargetFormat, TextureCube, Uniform};
+
+// Orthographic cameras look along a single direction, so skyboxes use this field of view for them
+const ORTHOGRAPHIC_SKY_FOV: Deg<f32> = Deg(60.0);
+
+// What fills the frame behind the scene. Gradient colors are linear, top to bottom of the screen.
+#[derive(Clone)]
+pub enum Background {
+    Color(wgpu::Color),
+    Gradient { top: [f32; 3], bottom: [f32; 3] },
+    Skybox(Rc<TextureCube>),
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BackgroundUniform {
+    inverse_view_projection: [[f32; 4]; 4],
+    top: [f32; 4],
+    bottom: [f32; 4],
+}
+
+// Clears to the background color or draws a full screen gradient or skybox before the scene.
+// Skyboxes follow the camera's rotation only, so they never move with it.
+pub struct BackgroundRenderer {
+    background: Background,
+    uniform: Uniform<BackgroundUniform>,
+    shader: Shader,
+    sky_layout: BindGroupLayout,
+    sky_group: Option<BindGroup>,
+    target: TargetFormat,
+    gradient_pipeline: wgpu::RenderPipeline,
+    skybox_pipeline: wgpu::RenderPipeline,
+}
+
+impl BackgroundRenderer {
+    pub fn new(device: &Device, target: &TargetFormat, background: Background) -> Self {
+        let uniform = Uniform::new(device,
+            "Background Uniform Buffer",
+            wgpu::ShaderStages::FRAGMENT,
+            BackgroundUniform {
+                inverse_view_projection: cgmath::Matrix4::identity().into(),
+                top: [0.0; 4],
+                bottom: [0.0; 4],
+            });
+
+        let sky_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Skybox Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry { // texture entry
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry { // sampler entry
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = Shader::new("resources/background.wgsl", device);
+        let gradient_pipeline = create_pipeline(device, target, &shader, "fs_gradient", &[&uniform.bind_layout]);
+        let skybox_pipeline = create_pipeline(device, target, &shader, "fs_skybox", &[&uniform.bind_layout, &sky_layout]);
+
+        let mut renderer = Self {
+            background: Background::Color(wgpu::Color::BLACK),
+            uniform,
+            shader,
+            sky_layout,
+            sky_group: None,
+            target: *target,
+            gradient_pipeline,
+            skybox_pipeline,
+        };
+        renderer.set_background(device, background);
+        renderer
+    }
+
+    pub fn set_target(&mut self, device: &Device, target: &TargetFormat) {
+        if self.target != *target {
+            self.gradient_pipeline = create_pipeline(device, target, &self.shader, "fs_gradient", &[&self.uniform.bind_layout]);
+            self.skybox_pipeline = create_pipeline(device, target, &self.shader, "fs_skybox", &[&self.uniform.bind_layout, &self.sky_layout]);
+            self.target = *target;
+        }
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    pub fn set_background(&mut self, device: &Device, background: Background) {
+        self.sky_group = match &background {
+            Background::Skybox(cube) => Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Skybox Bind Group"),
+                layout: &self.sky_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&cube.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&cube.sampler),
+                    },
+                ],
+            })),
+            _ => None,
+        };
+
+        if let Background::Gradient { top, bottom } = background {
+            let uniform = self.uniform.get_mut();
+            uniform.top = [top[0], top[1], top[2], 1.0];
+            uniform.bottom = [bottom[0], bottom[1], bottom[2], 1.0];
+        }
+
+        self.background = background;
+    }
+
+    // What the render pass should clear to, the background's own color or black under a full screen draw
+    pub fn clear_color(&self) -> wgpu::Color {
+        match self.background {
+            Background::Color(color) => color,
+            _ => wgpu::Color::BLACK,
+        }
+    }
+
+    pub fn update_buffer(&mut self, queue: &Queue, camera: &Camera) {
+        if let Background::Skybox(_) = self.background {
+            let projection = match camera.projection() {
+                Projection::Perspective { .. } => camera.projection_matrix(),
+                Projection::Orthographic => 
+                    OPENGL_TO_WGPU_MATRIX * cgmath::perspective(ORTHOGRAPHIC_SKY_FOV, camera.aspect(), 0.1, 10.0),
+            };
+
+            let inverse = (projection * camera.rotation()).invert()
+                .unwrap_or_else(cgmath::Matrix4::identity);
+            self.uniform.get_mut().inverse_view_projection = inverse.into();
+        }
+
+        self.uniform.update_buffer(queue);
+    }
+
+    // Call first in the render pass, plain colors are handled by `clear_color`
+    pub fn draw<'a>(&'a self, rp: &mut RenderPass<'a>) {
+        match self.background {
+            Background::Color(_) => {},
+            Background::Gradient { .. } => {
+                rp.set_pipeline(&self.gradient_pipeline);
+                rp.set_bind_group(0, &self.uniform.bind_group, &[]);
+                rp.draw(0..3, 0..1);
+            },
+            Background::Skybox(_) => {
+                rp.set_pipeline(&self.skybox_pipeline);
+                rp.set_bind_group(0, &self.uniform.bind_group, &[]);
+                rp.set_bind_group(1, self.sky_group.as_ref().expect("Skybox bind group is missing"), &[]);
+                rp.draw(0..3, 0..1);
+            },
+        }
+    }
+}
+
+fn create_pipeline(device: &Device,
+    target: &TargetFormat,
+    shader: &Shader,
+    fragment_entry: &str,
+    bind_layouts: &[&BindGroupLayout]) -> wgpu::RenderPipeline {
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Background Pipeline Layout"),
+        bind_group_layouts: bind_layouts,
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Background Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader.module,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader.module,
+            entry_point: fragment_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format: target.color,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            cull_mode: None,
+            ..Default::default()
+        },
+        // Drawn first on the far plane, so anything else ends up in front
+        depth_stencil: target.depth_stencil(false, wgpu::CompareFunction::LessEqual),
+        multisample: target.multisample(),
+        multiview: None,
+    })
+}
@@ -0,0 +1,312 @@
+use imgui::Ui;
+use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, Device, Queue, TextureView};
+
+use super::{RenderTarget, Shader, Uniform, HDR_FORMAT};
+
+// Levels are never made smaller than this
+const MIN_MIP_SIZE: u32 = 4;
+
+// Pixels brighter than `threshold` bloom, fading in over `knee` below it. `radius` is the upsampling
+// filter's reach in uv units, `mip_count` how many halvings the glow spreads over.
+#[derive(Clone, Debug)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    pub intensity: f32,
+    pub threshold: f32,
+    pub knee: f32,
+    pub radius: f32,
+    pub mip_count: u32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            intensity: 0.3,
+            threshold: 1.0,
+            knee: 0.5,
+            radius: 0.005,
+            mip_count: 6,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    radius: f32,
+    intensity: f32,
+}
+
+// A mip chain at half the input size and above, with one bind group per level
+struct MipChain {
+    _texture: wgpu::Texture,
+    views: Vec<TextureView>,
+    groups: Vec<BindGroup>,
+    input_group: BindGroup,
+    // Generation of the input and mip count it was made for
+    key: (u32, u32),
+}
+
+// Adds a glow around the bright parts of an HDR target: the input is thresholded into the first
+// mip, downsampled to the last, upsampled back and finally added onto the input itself.
+pub struct Bloom {
+    settings: BloomSettings,
+    uniform: Uniform<BloomUniform>,
+    source_layout: BindGroupLayout,
+    sampler: wgpu::Sampler,
+    prefilter_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
+    upsample_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    chain: Option<MipChain>,
+}
+
+impl Bloom {
+    pub fn new(device: &Device, settings: BloomSettings) -> Self {
+        let uniform = Uniform::new(device,
+            "Bloom Uniform Buffer",
+            wgpu::ShaderStages::FRAGMENT,
+            BloomUniform {
+                threshold: 0.0,
+                knee: 0.0,
+                radius: 0.0,
+                intensity: 0.0,
+            });
+
+        let source_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom Source Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry { // texture entry
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry { // sampler entry
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bloom Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let shader = Shader::new("resources/bloom.wgsl", device);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom Pipeline Layout"),
+            bind_group_layouts: &[&source_layout, &uniform.bind_layout],
+            push_constant_ranges: &[],
+        });
+
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::OVER,
+        };
+
+        let create_pipeline = |entry_point: &str, blend: wgpu::BlendState| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader.module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader.module,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            settings,
+            uniform,
+            source_layout,
+            sampler,
+            prefilter_pipeline: create_pipeline("fs_prefilter", wgpu::BlendState::REPLACE),
+            downsample_pipeline: create_pipeline("fs_downsample", wgpu::BlendState::REPLACE),
+            upsample_pipeline: create_pipeline("fs_upsample", additive),
+            composite_pipeline: create_pipeline("fs_composite", additive),
+            chain: None,
+        }
+    }
+
+    pub fn settings(&self) -> &BloomSettings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut BloomSettings {
+        &mut self.settings
+    }
+
+    pub fn update_buffer(&mut self, queue: &Queue) {
+        let settings = &self.settings;
+        self.uniform.set(BloomUniform {
+            threshold: settings.threshold,
+            knee: settings.knee.max(0.0),
+            radius: settings.radius,
+            intensity: settings.intensity,
+        });
+        self.uniform.update_buffer(queue);
+    }
+
+    // Blooms `input` in place. It must be an HDR_FORMAT target, its single sampled view is
+    // read and then drawn onto, so with MSAA this has to come after the last pass resolving into it.
+    pub fn render(&mut self, device: &Device, encoder: &mut CommandEncoder, input: &RenderTarget) {
+        if !self.settings.enabled {
+            return;
+        }
+
+        let key = (input.generation(), self.settings.mip_count.max(1));
+        if !matches!(&self.chain, Some(chain) if chain.key == key) {
+            self.chain = Some(self.create_chain(device, input, key));
+        }
+        let chain = self.chain.as_ref().unwrap();
+        let last = chain.views.len() - 1;
+
+        self.draw(encoder, "Bloom Prefilter", &self.prefilter_pipeline, &chain.input_group, &chain.views[0], wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+        for level in 1..=last {
+            self.draw(encoder, "Bloom Downsample", &self.downsample_pipeline, &chain.groups[level - 1], &chain.views[level], wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+        }
+        for level in (1..=last).rev() {
+            self.draw(encoder, "Bloom Upsample", &self.upsample_pipeline, &chain.groups[level], &chain.views[level - 1], wgpu::LoadOp::Load);
+        }
+        self.draw(encoder, "Bloom Composite", &self.composite_pipeline, &chain.groups[0], input.sampled_view(), wgpu::LoadOp::Load);
+    }
+
+    pub fn draw_gui(&mut self, ui: &Ui) {
+        let settings = &mut self.settings;
+
+        ui.window("Bloom")
+            .size([300.0, 160.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.checkbox("Enabled", &mut settings.enabled);
+                ui.slider("Intensity", 0.0, 2.0, &mut settings.intensity);
+                ui.slider("Threshold", 0.0, 10.0, &mut settings.threshold);
+                ui.slider("Knee", 0.0, 2.0, &mut settings.knee);
+                ui.slider("Radius", 0.001, 0.02, &mut settings.radius);
+                ui.slider("Mip Count", 1, 10, &mut settings.mip_count);
+            });
+    }
+
+    fn draw(&self,
+        encoder: &mut CommandEncoder,
+        label: &str,
+        pipeline: &wgpu::RenderPipeline,
+        source: &BindGroup,
+        destination: &TextureView,
+        load: wgpu::LoadOp<wgpu::Color>) {
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: destination,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, source, &[]);
+        render_pass.set_bind_group(1, &self.uniform.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_chain(&self, device: &Device, input: &RenderTarget, key: (u32, u32)) -> MipChain {
+        let (width, height) = input.size();
+        let (width, height) = ((width / 2).max(1), (height / 2).max(1));
+
+        // Stop before a level would get smaller than MIN_MIP_SIZE
+        let fitting = (width.min(height) / MIN_MIP_SIZE).max(1).ilog2() + 1;
+        let mip_count = key.1.min(fitting);
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Bloom Mip Chain"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: mip_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let views: Vec<TextureView> = (0..mip_count)
+            .map(|level| texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Bloom Mip View"),
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            }))
+            .collect();
+
+        let groups = views.iter()
+            .map(|view| self.create_source_group(device, view))
+            .collect();
+
+        MipChain {
+            _texture: texture,
+            input_group: self.create_source_group(device, input.sampled_view()),
+            views,
+            groups,
+            key,
+        }
+    }
+
+    fn create_source_group(&self, device: &Device, view: &TextureView) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bloom Source Bind Group"),
+            layout: &self.source_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+}
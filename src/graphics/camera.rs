@@ -1,14 +1,25 @@
-use cgmath::{Point3, Vector3, SquareMatrix};
-use wgpu::{Device, util::DeviceExt, Buffer, BindGroupLayout, BindGroup, SurfaceConfiguration, Queue};
+use cgmath::{Point3, Vector2, Vector3, Vector4, SquareMatrix, InnerSpace, Rad};
+use wgpu::{Device, SurfaceConfiguration, Queue};
 
-const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+use super::Uniform;
+
+pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
     0.0, 0.0, 0.5, 0.5,
     0.0, 0.0, 0.0, 1.0,
 );
 
+// Orthographic cameras cover `width` by `height` world units,
+// perspective cameras use the same ratio as their aspect
+#[derive(Copy, Clone, Debug)]
+pub enum Projection {
+    Orthographic,
+    Perspective { fovy: Rad<f32> },
+}
+
 pub struct Camera {
+    projection: Projection,
     eye: cgmath::Point3<f32>,
     target: cgmath::Point3<f32>,
     up: cgmath::Vector3<f32>,
@@ -16,10 +27,8 @@ pub struct Camera {
     height: f32,
     znear: f32,
     zfar: f32,
-    u_buffer: UniformBuffer,
-    pub buffer: Buffer,
-    pub bind_layout: BindGroupLayout,
-    pub bind_group: BindGroup,
+    jitter: Vector2<f32>,
+    pub uniform: Uniform<CameraUniform>,
 }
 
 impl Camera {
@@ -28,95 +37,158 @@ impl Camera {
          near: f32, 
          far: f32) -> Self {
 
-        let u_buffer = UniformBuffer::new();
-        let buffer = u_buffer.init_buffer(device);
-
-        let bind_layout = Camera::create_layout(device);
-        let bind_group = Camera::create_bind_group(device, &bind_layout, &buffer);
+        let uniform = Uniform::new(device, 
+            "Camera Uniform Buffer", 
+            wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT, 
+            CameraUniform::new());
 
         Self 
         { 
-            eye: Point3::new(0.0, 0.0, -1.0),
+            projection: Projection::Orthographic,
+            eye: Point3::new(0.0, 0.0, 1.0),
             target: Point3::new(0.0, 0.0, 0.0), 
             up: Vector3::unit_y(), 
             width: config.width as f32,
             height: config.width as f32, 
             znear: near, 
             zfar: far, 
-            u_buffer,
-            buffer,
-            bind_layout,
-            bind_group,
+            jitter: Vector2::new(0.0, 0.0),
+            uniform,
         }
     }
 
-    fn create_layout(device: &Device) -> BindGroupLayout {
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Camera Bind Group Layout"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }
-            ]
-        })
-    }
-
-    fn create_bind_group(device: &Device, layout: &BindGroupLayout, buffer: &Buffer) -> BindGroup {
-        device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Camera Bind Group"),
-            layout: &layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: buffer.as_entire_binding(),
-                }
-            ],
-        })
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    // Perspective projections need a positive `near`
+    pub fn set_depth_range(&mut self, near: f32, far: f32) {
+        self.znear = near;
+        self.zfar = far;
+    }
+
+    pub fn look_at(&mut self, eye: Point3<f32>, target: Point3<f32>) {
+        self.eye = eye;
+        self.target = target;
     }
 
+    pub fn view(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    // The view without its translation, for things infinitely far away such as skyboxes
+    pub fn rotation(&self) -> cgmath::Matrix4<f32> {
+        let mut rotation = self.view();
+        rotation.w = Vector4::unit_w();
+        rotation
+    }
+
+    pub fn projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        let proj = match self.projection {
+            Projection::Orthographic => cgmath::ortho(-self.width / 2.0, 
+                self.width / 2.0, 
+                -self.height / 2.0, 
+                self.height / 2.0, 
+                self.znear, 
+                self.zfar),
+            Projection::Perspective { fovy } => cgmath::perspective(fovy, 
+                self.aspect(), 
+                self.znear, 
+                self.zfar),
+        };
+
+        OPENGL_TO_WGPU_MATRIX * proj
+    }
+
+    // Includes the jitter, see `set_jitter`
     pub fn view_projection(&self) -> cgmath::Matrix4<f32> {
-        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-        let proj = cgmath::ortho(self.width / 2.0, 
-            -self.width / 2.0, 
-            -self.height / 2.0, 
-            self.height / 2.0, 
-            self.znear, 
-            self.zfar);
+        let jitter = cgmath::Matrix4::from_translation(self.jitter.extend(0.0));
+        jitter * self.projection_matrix() * self.view()
+    }
+
+    pub fn jitter(&self) -> Vector2<f32> {
+        self.jitter
+    }
+
+    // Subpixel offset of the whole image in normalized device coordinates, for temporal anti-aliasing
+    pub fn set_jitter(&mut self, jitter: Vector2<f32>) {
+        self.jitter = jitter;
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
 
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+    pub fn aspect(&self) -> f32 {
+        self.width / self.height
+    }
+
+    pub fn near(&self) -> f32 {
+        self.znear
+    }
+
+    pub fn far(&self) -> f32 {
+        self.zfar
+    }
+
+    // World space corners of the view volume between `near` and `far` along the view direction,
+    // near corners first. Used to fit shadow cascades to slices of the view.
+    pub fn frustum_corners(&self, near: f32, far: f32) -> [Point3<f32>; 8] {
+        let forward = (self.target - self.eye).normalize();
+        let right = forward.cross(self.up).normalize();
+        let up = right.cross(forward).normalize();
+
+        let mut corners = [self.eye; 8];
+        for (i, distance) in [near, far].into_iter().enumerate() {
+            let half_height = match self.projection {
+                Projection::Orthographic => self.height / 2.0,
+                Projection::Perspective { fovy } => distance * (fovy / 2.0).0.tan(),
+            };
+            let up = up * half_height;
+            let right = right * half_height * self.aspect();
+
+            let center = self.eye + forward * distance;
+            corners[i * 4] = center - right - up;
+            corners[i * 4 + 1] = center + right - up;
+            corners[i * 4 + 2] = center + right + up;
+            corners[i * 4 + 3] = center - right + up;
+        }
+        corners
+    }
+
+    // Orthographic cameras are given as the direction towards them (w = 0) rather than a point
+    pub fn position(&self) -> cgmath::Vector4<f32> {
+        match self.projection {
+            Projection::Orthographic => (self.eye - self.target).normalize().extend(0.0),
+            Projection::Perspective { .. } => self.eye.to_homogeneous(),
+        }
     }
 
     pub fn update_buffer(&mut self, queue: &Queue) {
-        self.u_buffer.view_projection = self.view_projection().into();
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.u_buffer]));
+        self.uniform.set(CameraUniform {
+            view_projection: self.view_projection().into(),
+            position: self.position().into(),
+        });
+        self.uniform.update_buffer(queue);
     }
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct UniformBuffer {
+pub struct CameraUniform {
     view_projection: [[f32; 4]; 4],
+    position: [f32; 4],
 }
 
-impl UniformBuffer {
+impl CameraUniform {
     fn new() -> Self {
         Self { 
-            view_projection: cgmath::Matrix4::identity().into() 
+            view_projection: cgmath::Matrix4::identity().into(),
+            position: [0.0, 0.0, 1.0, 0.0],
         }
     }
-
-    fn init_buffer(&self, device: &Device) -> Buffer {
-        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Camera Uniform Buffer"),
-            contents: bytemuck::cast_slice(&[*self]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        })
-    }
 }
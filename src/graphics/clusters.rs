@@ -0,0 +1,140 @@
+use wgpu::{BindGroup, Buffer, CommandEncoder, Device};
+
+use super::lights::LightRaw;
+use super::{ComputePass, Readback, Shader, MAX_LIGHTS};
+
+// Screen tiles and depth slices the view is split into, the shaders get them from light_constants.wgsl
+pub const CLUSTERS_X: u32 = 16;
+pub const CLUSTERS_Y: u32 = 9;
+pub const CLUSTERS_Z: u32 = 24;
+pub const CLUSTER_COUNT: u32 = CLUSTERS_X * CLUSTERS_Y * CLUSTERS_Z;
+
+// Lights past this many in one cluster are dropped from it, with a warning
+pub const MAX_CLUSTER_LIGHTS: u32 = 64;
+
+const LIGHT_SIZE: u64 = std::mem::size_of::<LightRaw>() as u64;
+// Min and max corner of a cluster's view space bounds
+const BOUNDS_SIZE: u64 = 32;
+// @workgroup_size of the entry points in resources/clusters.wgsl
+const WORKGROUP_SIZE: (u32, u32, u32) = (16, 9, 1);
+
+// Clustered light culling for `Lights`. A compute pass splits the view into clusters and lists the
+// lights reaching into each, so lit shaders only loop over those of their fragment's cluster.
+// The light list and the per cluster counts and indices are bound to the lit shaders by `Lights`.
+pub struct LightClusters {
+    pub light_list: Buffer,
+    pub light_counts: Buffer,
+    pub light_indices: Buffer,
+    _bounds: Buffer,
+    max_lights: Buffer,
+    max_lights_readback: Readback<u32>,
+    // The most lights in one cluster last read back, to warn once per change
+    max_lights_seen: u32,
+    bind_group: BindGroup,
+    build_pass: ComputePass,
+    assign_pass: ComputePass,
+}
+
+impl LightClusters {
+    // `lights_uniform` is the buffer of `Lights::uniform`, which holds the camera the clusters follow
+    pub fn new(device: &Device, lights_uniform: &Buffer) -> Self {
+        let create_buffer = |label, size, usage| device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+            mapped_at_creation: false,
+        });
+
+        let light_list = create_buffer("Light List Buffer",
+            MAX_LIGHTS as u64 * LIGHT_SIZE,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST);
+        let bounds = create_buffer("Cluster Bounds Buffer",
+            CLUSTER_COUNT as u64 * BOUNDS_SIZE,
+            wgpu::BufferUsages::STORAGE);
+        let light_counts = create_buffer("Cluster Light Count Buffer",
+            CLUSTER_COUNT as u64 * 4,
+            wgpu::BufferUsages::STORAGE);
+        let light_indices = create_buffer("Cluster Light Index Buffer",
+            (CLUSTER_COUNT * MAX_CLUSTER_LIGHTS) as u64 * 4,
+            wgpu::BufferUsages::STORAGE);
+        let max_lights = create_buffer("Cluster Max Lights Buffer",
+            4,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST);
+
+        let bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Light Clusters Bind Group Layout"),
+            entries: &[
+                ComputePass::uniform_entry(0),
+                ComputePass::storage_entry(1, true),
+                ComputePass::storage_entry(2, false),
+                ComputePass::storage_entry(3, false),
+                ComputePass::storage_entry(4, false),
+                ComputePass::storage_entry(5, false),
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Light Clusters Bind Group"),
+            layout: &bind_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: lights_uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: light_list.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: bounds.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: light_counts.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: light_indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: max_lights.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = Shader::new("resources/clusters.wgsl", device);
+
+        Self {
+            light_list,
+            light_counts,
+            light_indices,
+            _bounds: bounds,
+            max_lights,
+            max_lights_readback: Readback::new(device, "Cluster Max Lights Readback Buffer", 1),
+            max_lights_seen: 0,
+            bind_group,
+            build_pass: ComputePass::new(device, &shader, "build_clusters", &[&bind_layout], WORKGROUP_SIZE),
+            assign_pass: ComputePass::new(device, &shader, "assign_lights", &[&bind_layout], WORKGROUP_SIZE),
+        }
+    }
+
+    // Rebuilds the clusters for the camera in the lights uniform and assigns the lights to them.
+    // Warns when a cluster had more lights reaching into it than MAX_CLUSTER_LIGHTS, a few frames late.
+    pub fn cull(&mut self, device: &Device, encoder: &mut CommandEncoder) {
+        // The copy of an earlier frame, which has been submitted by now
+        if let Some(max_lights) = self.max_lights_readback.try_read(device).and_then(|data| data.first().copied()) {
+            if max_lights > MAX_CLUSTER_LIGHTS && max_lights != self.max_lights_seen {
+                log::warn!("{} lights reach into one cluster, only {} of them are shaded", max_lights, MAX_CLUSTER_LIGHTS);
+            }
+            self.max_lights_seen = max_lights;
+        }
+
+        let size = (CLUSTERS_X, CLUSTERS_Y, CLUSTERS_Z);
+        encoder.clear_buffer(&self.max_lights, 0, None);
+        self.build_pass.dispatch_size(encoder, &[&self.bind_group], size);
+        self.assign_pass.dispatch_size(encoder, &[&self.bind_group], size);
+        self.max_lights_readback.copy(encoder, &self.max_lights, 0);
+    }
+}
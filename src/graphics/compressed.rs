@@ -0,0 +1,1159 @@
+use half::f16;
+use wgpu::TextureFormat;
+
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+const DDS_HEADER_END: usize = 128;
+const DDS_DX10_HEADER_END: usize = 148;
+
+// Decodes one 4x4 block into 16 texels of `N` bytes each, row by row
+type BlockDecoder<const N: usize> = fn(&[u8]) -> [[u8; N]; 16];
+
+// Texture data read from a container, with the full mip chain (level 0 first)
+pub struct ImageLevels {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl ImageLevels {
+    fn level_size(&self, level: u32) -> (u32, u32) {
+        mip_size(self.width, self.height, level)
+    }
+
+    // Block compressed textures can only be created with a size that is a multiple of their blocks
+    pub fn is_block_aligned(&self) -> bool {
+        let (block_w, block_h) = self.format.block_dimensions();
+        self.width.is_multiple_of(block_w) && self.height.is_multiple_of(block_h)
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    bytes.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| format!("Unexpected end of file at byte {offset}"))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, String> {
+    let low = read_u32(bytes, offset)? as u64;
+    let high = read_u32(bytes, offset + 4)? as u64;
+    Ok(low | (high << 32))
+}
+
+fn read_offset(bytes: &[u8], offset: usize) -> Result<usize, String> {
+    usize::try_from(read_u64(bytes, offset)?)
+        .map_err(|_| format!("Offset at byte {offset} is too large"))
+}
+
+fn mip_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    (width.checked_shr(level).unwrap_or(0).max(1), height.checked_shr(level).unwrap_or(0).max(1))
+}
+
+// Headers are untrusted, so the level count is checked against the full mip chain of the image
+fn check_level_count(level_count: u32, width: u32, height: u32) -> Result<(), String> {
+    let max_levels = 32 - width.max(height).leading_zeros();
+    if level_count > max_levels {
+        return Err(format!("{level_count} mip levels don't fit a {width}x{height} image"));
+    }
+    Ok(())
+}
+
+// None when the size doesn't fit in memory
+fn level_byte_size(format: TextureFormat, width: u32, height: u32) -> Option<usize> {
+    let (block_w, block_h) = format.block_dimensions();
+    let block_size = format.block_size(None).unwrap_or(4);
+    let blocks_x = width.div_ceil(block_w) as usize;
+    let blocks_y = height.div_ceil(block_h) as usize;
+    blocks_x.checked_mul(blocks_y)?.checked_mul(block_size as usize)
+}
+
+pub fn parse_ktx2(bytes: &[u8]) -> Result<ImageLevels, String> {
+    if bytes.len() < 80 || bytes[0..12] != KTX2_IDENTIFIER {
+        return Err("Not a KTX2 file".to_string());
+    }
+
+    let vk_format = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 20)?;
+    let height = read_u32(bytes, 24)?;
+    let depth = read_u32(bytes, 28)?;
+    let layer_count = read_u32(bytes, 32)?;
+    let face_count = read_u32(bytes, 36)?;
+    let level_count = read_u32(bytes, 40)?.max(1);
+    let supercompression = read_u32(bytes, 44)?;
+
+    if depth > 1 || layer_count > 1 || face_count != 1 {
+        return Err("Only single 2D KTX2 images are supported".to_string());
+    }
+    if supercompression != 0 {
+        return Err(format!("Unsupported KTX2 supercompression scheme {supercompression}"));
+    }
+
+    let format = match vk_format {
+        37 => TextureFormat::Rgba8Unorm,
+        43 => TextureFormat::Rgba8UnormSrgb,
+        97 => TextureFormat::Rgba16Float,
+        109 => TextureFormat::Rgba32Float,
+        131 | 133 => TextureFormat::Bc1RgbaUnorm,
+        132 | 134 => TextureFormat::Bc1RgbaUnormSrgb,
+        135 => TextureFormat::Bc2RgbaUnorm,
+        136 => TextureFormat::Bc2RgbaUnormSrgb,
+        137 => TextureFormat::Bc3RgbaUnorm,
+        138 => TextureFormat::Bc3RgbaUnormSrgb,
+        139 => TextureFormat::Bc4RUnorm,
+        140 => TextureFormat::Bc4RSnorm,
+        141 => TextureFormat::Bc5RgUnorm,
+        142 => TextureFormat::Bc5RgSnorm,
+        143 => TextureFormat::Bc6hRgbUfloat,
+        144 => TextureFormat::Bc6hRgbFloat,
+        145 => TextureFormat::Bc7RgbaUnorm,
+        146 => TextureFormat::Bc7RgbaUnormSrgb,
+        _ => return Err(format!("Unsupported KTX2 vkFormat {vk_format}")),
+    };
+    check_level_count(level_count, width, height)?;
+
+    // The level index follows the 80 byte header, largest level first
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count {
+        let entry = 80 + level as usize * 24;
+        let offset = read_offset(bytes, entry)?;
+        let length = read_offset(bytes, entry + 8)?;
+
+        let (level_width, level_height) = mip_size(width, height, level);
+        if level_byte_size(format, level_width, level_height) != Some(length) {
+            return Err(format!("KTX2 level {level} has {length} bytes, not those of a {level_width}x{level_height} level"));
+        }
+        let data = offset.checked_add(length)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or_else(|| format!("KTX2 level {level} is out of bounds"))?;
+        levels.push(data.to_vec());
+    }
+
+    Ok(filterable(ImageLevels { format, width, height, levels }))
+}
+
+pub fn parse_dds(bytes: &[u8]) -> Result<ImageLevels, String> {
+    if bytes.len() < DDS_HEADER_END || bytes[0..4] != DDS_MAGIC {
+        return Err("Not a DDS file".to_string());
+    }
+
+    let height = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 16)?;
+    let level_count = read_u32(bytes, 28)?.max(1);
+    let four_cc = &bytes[84..88];
+
+    let (format, data_start) = match four_cc {
+        b"DXT1" => (TextureFormat::Bc1RgbaUnorm, DDS_HEADER_END),
+        b"DXT2" | b"DXT3" => (TextureFormat::Bc2RgbaUnorm, DDS_HEADER_END),
+        b"DXT4" | b"DXT5" => (TextureFormat::Bc3RgbaUnorm, DDS_HEADER_END),
+        b"ATI1" | b"BC4U" => (TextureFormat::Bc4RUnorm, DDS_HEADER_END),
+        b"BC4S" => (TextureFormat::Bc4RSnorm, DDS_HEADER_END),
+        b"ATI2" | b"BC5U" => (TextureFormat::Bc5RgUnorm, DDS_HEADER_END),
+        b"BC5S" => (TextureFormat::Bc5RgSnorm, DDS_HEADER_END),
+        b"DX10" => {
+            let dxgi_format = read_u32(bytes, DDS_HEADER_END)?;
+            let format = match dxgi_format {
+                2 => TextureFormat::Rgba32Float,
+                10 => TextureFormat::Rgba16Float,
+                28 => TextureFormat::Rgba8Unorm,
+                29 => TextureFormat::Rgba8UnormSrgb,
+                71 => TextureFormat::Bc1RgbaUnorm,
+                72 => TextureFormat::Bc1RgbaUnormSrgb,
+                74 => TextureFormat::Bc2RgbaUnorm,
+                75 => TextureFormat::Bc2RgbaUnormSrgb,
+                77 => TextureFormat::Bc3RgbaUnorm,
+                78 => TextureFormat::Bc3RgbaUnormSrgb,
+                80 => TextureFormat::Bc4RUnorm,
+                81 => TextureFormat::Bc4RSnorm,
+                83 => TextureFormat::Bc5RgUnorm,
+                84 => TextureFormat::Bc5RgSnorm,
+                95 => TextureFormat::Bc6hRgbUfloat,
+                96 => TextureFormat::Bc6hRgbFloat,
+                98 => TextureFormat::Bc7RgbaUnorm,
+                99 => TextureFormat::Bc7RgbaUnormSrgb,
+                _ => return Err(format!("Unsupported DXGI format {dxgi_format}")),
+            };
+            let array_size = read_u32(bytes, DDS_HEADER_END + 12)?;
+            if array_size > 1 {
+                return Err("DDS texture arrays are not supported".to_string());
+            }
+            (format, DDS_DX10_HEADER_END)
+        },
+        _ => return Err(format!("Unsupported DDS FourCC {:?}", String::from_utf8_lossy(four_cc))),
+    };
+    check_level_count(level_count, width, height)?;
+
+    // Mip levels are stored back to back, largest first
+    let mut levels = Vec::with_capacity(level_count as usize);
+    let mut offset = data_start;
+    for level in 0..level_count {
+        let (level_width, level_height) = mip_size(width, height, level);
+        let end = level_byte_size(format, level_width, level_height)
+            .and_then(|size| offset.checked_add(size))
+            .filter(|end| *end <= bytes.len())
+            .ok_or_else(|| format!("DDS level {level} is out of bounds"))?;
+        levels.push(bytes[offset..end].to_vec());
+        offset = end;
+    }
+
+    Ok(filterable(ImageLevels { format, width, height, levels }))
+}
+
+// Rgba32Float isn't filterable on every adapter, so it is narrowed to Rgba16Float like decoded HDR images
+fn filterable(image: ImageLevels) -> ImageLevels {
+    if image.format != TextureFormat::Rgba32Float {
+        return image;
+    }
+
+    let levels = image.levels.iter()
+        .map(|data| data.chunks_exact(4)
+            .flat_map(|v| f16::from_f32(f32::from_le_bytes([v[0], v[1], v[2], v[3]])).to_bits().to_le_bytes())
+            .collect())
+        .collect();
+
+    ImageLevels { format: TextureFormat::Rgba16Float, levels, ..image }
+}
+
+// Decodes BCn data into an uncompressed format: 8-bit RGBA for BC1-BC5 and BC7, half float RGBA for BC6H.
+// Used when the device does not support `wgpu::Features::TEXTURE_COMPRESSION_BC`,
+// or the image size is not a multiple of 4 so it can't be created as a BCn texture.
+pub fn decompress(image: &ImageLevels) -> Result<ImageLevels, String> {
+    let (format, levels) = match image.format {
+        TextureFormat::Bc1RgbaUnorm => (TextureFormat::Rgba8Unorm, decode_levels(image, 8, decode_bc1)),
+        TextureFormat::Bc1RgbaUnormSrgb => (TextureFormat::Rgba8UnormSrgb, decode_levels(image, 8, decode_bc1)),
+        TextureFormat::Bc2RgbaUnorm => (TextureFormat::Rgba8Unorm, decode_levels(image, 16, decode_bc2)),
+        TextureFormat::Bc2RgbaUnormSrgb => (TextureFormat::Rgba8UnormSrgb, decode_levels(image, 16, decode_bc2)),
+        TextureFormat::Bc3RgbaUnorm => (TextureFormat::Rgba8Unorm, decode_levels(image, 16, decode_bc3)),
+        TextureFormat::Bc3RgbaUnormSrgb => (TextureFormat::Rgba8UnormSrgb, decode_levels(image, 16, decode_bc3)),
+        TextureFormat::Bc4RUnorm => (TextureFormat::Rgba8Unorm, decode_levels(image, 8, decode_bc4_unorm)),
+        TextureFormat::Bc4RSnorm => (TextureFormat::Rgba8Snorm, decode_levels(image, 8, decode_bc4_snorm)),
+        TextureFormat::Bc5RgUnorm => (TextureFormat::Rgba8Unorm, decode_levels(image, 16, decode_bc5_unorm)),
+        TextureFormat::Bc5RgSnorm => (TextureFormat::Rgba8Snorm, decode_levels(image, 16, decode_bc5_snorm)),
+        TextureFormat::Bc6hRgbUfloat => (TextureFormat::Rgba16Float, decode_levels(image, 16, decode_bc6h_unsigned)),
+        TextureFormat::Bc6hRgbFloat => (TextureFormat::Rgba16Float, decode_levels(image, 16, decode_bc6h_signed)),
+        TextureFormat::Bc7RgbaUnorm => (TextureFormat::Rgba8Unorm, decode_levels(image, 16, decode_bc7)),
+        TextureFormat::Bc7RgbaUnormSrgb => (TextureFormat::Rgba8UnormSrgb, decode_levels(image, 16, decode_bc7)),
+        other => return Err(format!("No CPU decoder for {other:?}")),
+    };
+
+    Ok(ImageLevels { format, width: image.width, height: image.height, levels })
+}
+
+fn decode_levels<const N: usize>(image: &ImageLevels, block_size: usize, decode: BlockDecoder<N>) -> Vec<Vec<u8>> {
+    image.levels.iter()
+        .enumerate()
+        .map(|(level, data)| {
+            let (width, height) = image.level_size(level as u32);
+            decode_blocks(data, width, height, block_size, decode)
+        })
+        .collect()
+}
+
+fn decode_blocks<const N: usize>(data: &[u8], width: u32, height: u32, block_size: usize, decode: BlockDecoder<N>) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let blocks_x = width.div_ceil(4);
+    let mut texels_out = vec![0u8; width * height * N];
+
+    for (i, block) in data.chunks_exact(block_size).enumerate() {
+        let texels = decode(block);
+        let (bx, by) = (i % blocks_x * 4, i / blocks_x * 4);
+
+        for (t, texel) in texels.iter().enumerate() {
+            let (x, y) = (bx + t % 4, by + t / 4);
+            if x < width && y < height {
+                let p = (y * width + x) * N;
+                texels_out[p..p + N].copy_from_slice(texel);
+            }
+        }
+    }
+
+    texels_out
+}
+
+fn rgb565(c: u16) -> [u8; 3] {
+    let r = (c >> 11) & 0x1F;
+    let g = (c >> 5) & 0x3F;
+    let b = c & 0x1F;
+    [((r << 3) | (r >> 2)) as u8, ((g << 2) | (g >> 4)) as u8, ((b << 3) | (b >> 2)) as u8]
+}
+
+fn mix(a: [u8; 3], b: [u8; 3], wa: u32, wb: u32) -> [u8; 3] {
+    let f = |x: u8, y: u8| ((x as u32 * wa + y as u32 * wb) / (wa + wb)) as u8;
+    [f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2])]
+}
+
+fn decode_color_block(block: &[u8], allow_punch_through: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (a, b) = (rgb565(c0), rgb565(c1));
+
+    let palette: [[u8; 4]; 4] = if c0 > c1 || !allow_punch_through {
+        let c2 = mix(a, b, 2, 1);
+        let c3 = mix(a, b, 1, 2);
+        [[a[0], a[1], a[2], 255], [b[0], b[1], b[2], 255], [c2[0], c2[1], c2[2], 255], [c3[0], c3[1], c3[2], 255]]
+    } else {
+        let c2 = mix(a, b, 1, 1);
+        [[a[0], a[1], a[2], 255], [b[0], b[1], b[2], 255], [c2[0], c2[1], c2[2], 255], [0, 0, 0, 0]]
+    };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let mut texels = [[0u8; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indices >> (i * 2)) & 0x3) as usize];
+    }
+    texels
+}
+
+// Shared by the BC3 alpha channel and the BC4/BC5 channels
+fn decode_channel_block(block: &[u8], signed: bool) -> [u8; 16] {
+    let (e0, e1) = if signed {
+        ((block[0] as i8).max(-127) as i32, (block[1] as i8).max(-127) as i32)
+    } else {
+        (block[0] as i32, block[1] as i32)
+    };
+    let (min, max) = if signed { (-127, 127) } else { (0, 255) };
+
+    let mut palette = [e0, e1, 0, 0, 0, 0, 0, 0];
+    if e0 > e1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as i32) * e0 + i as i32 * e1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as i32) * e0 + i as i32 * e1) / 5;
+        }
+        palette[6] = min;
+        palette[7] = max;
+    }
+
+    let mut bits = 0u64;
+    for (i, byte) in block[2..8].iter().enumerate() {
+        bits |= (*byte as u64) << (i * 8);
+    }
+
+    let mut values = [0u8; 16];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = palette[((bits >> (i * 3)) & 0x7) as usize] as u8;
+    }
+    values
+}
+
+fn decode_bc1(block: &[u8]) -> [[u8; 4]; 16] {
+    decode_color_block(block, true)
+}
+
+fn decode_bc2(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut texels = decode_color_block(&block[8..16], false);
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let alpha = (block[i / 2] >> ((i % 2) * 4)) & 0xF;
+        texel[3] = alpha * 17;
+    }
+    texels
+}
+
+fn decode_bc3(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut texels = decode_color_block(&block[8..16], false);
+    let alpha = decode_channel_block(&block[0..8], false);
+    for (texel, alpha) in texels.iter_mut().zip(alpha) {
+        texel[3] = alpha;
+    }
+    texels
+}
+
+fn decode_bc4(block: &[u8], signed: bool) -> [[u8; 4]; 16] {
+    let one = if signed { 127 } else { 255 };
+    decode_channel_block(block, signed).map(|r| [r, 0, 0, one])
+}
+
+fn decode_bc5(block: &[u8], signed: bool) -> [[u8; 4]; 16] {
+    let one = if signed { 127 } else { 255 };
+    let red = decode_channel_block(&block[0..8], signed);
+    let green = decode_channel_block(&block[8..16], signed);
+    let mut texels = [[0u8; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = [red[i], green[i], 0, one];
+    }
+    texels
+}
+
+fn decode_bc4_unorm(block: &[u8]) -> [[u8; 4]; 16] {
+    decode_bc4(block, false)
+}
+
+fn decode_bc4_snorm(block: &[u8]) -> [[u8; 4]; 16] {
+    decode_bc4(block, true)
+}
+
+fn decode_bc5_unorm(block: &[u8]) -> [[u8; 4]; 16] {
+    decode_bc5(block, false)
+}
+
+fn decode_bc5_snorm(block: &[u8]) -> [[u8; 4]; 16] {
+    decode_bc5(block, true)
+}
+
+// Reads the bits of a 16 byte BC6H or BC7 block, least significant first
+struct BlockBits {
+    bits: u128,
+    position: u32,
+}
+
+impl BlockBits {
+    fn new(block: &[u8]) -> Self {
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&block[..16]);
+        Self { bits: u128::from_le_bytes(bytes), position: 0 }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        let value = (self.bits >> self.position) as u32 & ((1u64 << count) - 1) as u32;
+        self.position += count;
+        value
+    }
+}
+
+// Subset of every texel for the 64 two subset partitions of BC6H and BC7, one bit per texel
+const PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80,
+    0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00, 0xFFF0, 0xF000,
+    0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C,
+    0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A,
+    0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C,
+    0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+// Subset of every texel for the 64 three subset partitions of BC7
+const PARTITIONS_3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2], [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1], [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2], [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2], [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2], [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2], [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2], [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0], [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0], [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2], [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1], [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2], [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0], [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0], [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1], [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1], [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1], [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1], [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2], [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2], [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2], [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1], [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2], [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+// Anchor texel of the second subset of two subset partitions, its index has one bit less
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+// Anchor texels of the second and third subsets of three subset partitions
+const ANCHORS_3: [[u8; 2]; 64] = [
+    [3, 15], [3, 8], [15, 8], [15, 3], [8, 15], [3, 15], [15, 3], [15, 8],
+    [8, 15], [8, 15], [6, 15], [6, 15], [6, 15], [5, 15], [3, 15], [3, 8],
+    [3, 15], [3, 8], [8, 15], [15, 3], [3, 15], [3, 8], [6, 15], [10, 8],
+    [5, 3], [8, 15], [8, 6], [6, 10], [8, 15], [5, 15], [15, 10], [15, 8],
+    [8, 15], [15, 3], [3, 15], [5, 10], [6, 10], [10, 8], [8, 9], [15, 10],
+    [15, 6], [3, 15], [15, 8], [5, 15], [15, 3], [15, 6], [15, 6], [15, 8],
+    [3, 15], [15, 3], [5, 15], [5, 15], [5, 15], [8, 15], [5, 15], [10, 15],
+    [5, 15], [10, 15], [8, 15], [13, 15], [15, 3], [12, 15], [3, 15], [3, 8],
+];
+
+const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn index_weights(bits: u32) -> &'static [u32] {
+    match bits {
+        2 => &WEIGHTS_2,
+        3 => &WEIGHTS_3,
+        _ => &WEIGHTS_4,
+    }
+}
+
+fn subset_of(subsets: usize, partition: usize, texel: usize) -> usize {
+    match subsets {
+        2 => (PARTITIONS_2[partition] >> texel) as usize & 1,
+        3 => PARTITIONS_3[partition][texel] as usize,
+        _ => 0,
+    }
+}
+
+fn is_anchor(subsets: usize, partition: usize, texel: usize) -> bool {
+    match subsets {
+        2 => texel == 0 || texel == ANCHORS_2[partition] as usize,
+        3 => texel == 0 || ANCHORS_3[partition].contains(&(texel as u8)),
+        _ => texel == 0,
+    }
+}
+
+// Reads one index per texel, anchor texels have their top bit implied to be 0
+fn read_indices(bits: &mut BlockBits, index_bits: u32, subsets: usize, partition: usize) -> [u32; 16] {
+    let mut indices = [0u32; 16];
+    for (texel, index) in indices.iter_mut().enumerate() {
+        let count = if is_anchor(subsets, partition, texel) { index_bits - 1 } else { index_bits };
+        *index = bits.read(count);
+    }
+    indices
+}
+
+// Subsets, partition bits, rotation bits, index selection bits, color bits, alpha bits,
+// endpoint p-bits, shared p-bits, index bits and second index bits of the BC7 modes
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    index_bits_2: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, selection_bits: 0, color_bits: 4, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 3, index_bits_2: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, selection_bits: 0, color_bits: 6, alpha_bits: 0, endpoint_pbits: false, shared_pbits: true, index_bits: 3, index_bits_2: 0 },
+    Bc7Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, selection_bits: 0, color_bits: 5, alpha_bits: 0, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index_bits_2: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, selection_bits: 0, color_bits: 7, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 2, index_bits_2: 0 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, selection_bits: 1, color_bits: 5, alpha_bits: 6, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index_bits_2: 3 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, selection_bits: 0, color_bits: 7, alpha_bits: 8, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index_bits_2: 2 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, selection_bits: 0, color_bits: 7, alpha_bits: 7, endpoint_pbits: true, shared_pbits: false, index_bits: 4, index_bits_2: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, selection_bits: 0, color_bits: 5, alpha_bits: 5, endpoint_pbits: true, shared_pbits: false, index_bits: 2, index_bits_2: 0 },
+];
+
+fn interpolate(e0: u32, e1: u32, weight: u32) -> u32 {
+    ((64 - weight) * e0 + weight * e1 + 32) >> 6
+}
+
+fn decode_bc7(block: &[u8]) -> [[u8; 4]; 16] {
+    // The mode is the number of zero bits before the first set one, blocks without one are reserved
+    let mode_index = block[0].trailing_zeros() as usize;
+    let Some(mode) = BC7_MODES.get(mode_index) else {
+        return [[0; 4]; 16];
+    };
+
+    let mut bits = BlockBits::new(block);
+    bits.read(mode_index as u32 + 1);
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let selection = bits.read(mode.selection_bits);
+
+    // endpoints[subset * 2 + end] in RGBA, stored channel by channel
+    let mut endpoints = [[0u32; 4]; 6];
+    let endpoint_count = mode.subsets * 2;
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        endpoint[3] = bits.read(mode.alpha_bits);
+    }
+
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+    if mode.endpoint_pbits || mode.shared_pbits {
+        let mut pbits = [0u32; 6];
+        if mode.endpoint_pbits {
+            for pbit in pbits.iter_mut().take(endpoint_count) {
+                *pbit = bits.read(1);
+            }
+        } else {
+            for subset in 0..mode.subsets {
+                let pbit = bits.read(1);
+                pbits[subset * 2] = pbit;
+                pbits[subset * 2 + 1] = pbit;
+            }
+        }
+
+        for (endpoint, pbit) in endpoints.iter_mut().zip(pbits).take(endpoint_count) {
+            for value in endpoint.iter_mut() {
+                *value = (*value << 1) | pbit;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+
+    // Expand to 8 bits by repeating the top bits, blocks without alpha are opaque
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        for value in endpoint.iter_mut().take(3) {
+            *value = (*value << (8 - color_bits)) | (*value >> (2 * color_bits - 8));
+        }
+        endpoint[3] = if alpha_bits == 0 {
+            255
+        } else {
+            (endpoint[3] << (8 - alpha_bits)) | (endpoint[3] >> (2 * alpha_bits - 8))
+        };
+    }
+
+    let color_indices = read_indices(&mut bits, mode.index_bits, mode.subsets, partition);
+    let alpha_indices = if mode.index_bits_2 > 0 {
+        read_indices(&mut bits, mode.index_bits_2, 1, 0)
+    } else {
+        color_indices
+    };
+
+    // Mode 4 can swap which index set colors and alpha use
+    let (color_indices, color_index_bits, alpha_indices, alpha_index_bits) = if selection == 1 {
+        (alpha_indices, mode.index_bits_2, color_indices, mode.index_bits)
+    } else {
+        (color_indices, mode.index_bits, alpha_indices, mode.index_bits_2.max(mode.index_bits))
+    };
+
+    let mut texels = [[0u8; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let subset = subset_of(mode.subsets, partition, i);
+        let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+
+        let color_weight = index_weights(color_index_bits)[color_indices[i] as usize];
+        let alpha_weight = index_weights(alpha_index_bits)[alpha_indices[i] as usize];
+        for channel in 0..3 {
+            texel[channel] = interpolate(e0[channel], e1[channel], color_weight) as u8;
+        }
+        texel[3] = interpolate(e0[3], e1[3], alpha_weight) as u8;
+
+        // Rotation swaps alpha with red, green or blue
+        if rotation > 0 {
+            texel.swap(3, rotation as usize - 1);
+        }
+    }
+    texels
+}
+
+// Endpoint fields of BC6H, the channel times 4 plus w, x, y or z (first and second endpoint
+// of the first region, then of the second region)
+const RW: u8 = 0;
+const RX: u8 = 1;
+const RY: u8 = 2;
+const RZ: u8 = 3;
+const GW: u8 = 4;
+const GX: u8 = 5;
+const GY: u8 = 6;
+const GZ: u8 = 7;
+const BW: u8 = 8;
+const BX: u8 = 9;
+const BY: u8 = 10;
+const BZ: u8 = 11;
+
+// A BC6H mode: its mode bits, regions, whether endpoints are deltas from the first one,
+// the endpoint precision, delta precision per channel and where each endpoint bit is stored.
+// Bit runs are (field, first bit, last bit), read in that order so a few run backwards.
+struct Bc6hMode {
+    mode: u32,
+    regions: usize,
+    transformed: bool,
+    precision: u32,
+    delta_bits: [u32; 3],
+    layout: &'static [(u8, u32, u32)],
+}
+
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode { mode: 0x00, regions: 2, transformed: true, precision: 10, delta_bits: [5, 5, 5], layout: &[
+        (GY, 4, 4), (BY, 4, 4), (BZ, 4, 4), (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 4), (GZ, 4, 4),
+        (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 4),
+        (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3),
+    ] },
+    Bc6hMode { mode: 0x01, regions: 2, transformed: true, precision: 7, delta_bits: [6, 6, 6], layout: &[
+        (GY, 5, 5), (GZ, 4, 5), (RW, 0, 6), (BZ, 0, 1), (BY, 4, 4), (GW, 0, 6), (BY, 5, 5), (BZ, 2, 2),
+        (GY, 4, 4), (BW, 0, 6), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4), (RX, 0, 5), (GY, 0, 3), (GX, 0, 5),
+        (GZ, 0, 3), (BX, 0, 5), (BY, 0, 3), (RY, 0, 5), (RZ, 0, 5),
+    ] },
+    Bc6hMode { mode: 0x02, regions: 2, transformed: true, precision: 11, delta_bits: [5, 4, 4], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 4), (RW, 10, 10), (GY, 0, 3), (GX, 0, 3), (GW, 10, 10),
+        (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 3), (BW, 10, 10), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2),
+        (RZ, 0, 4), (BZ, 3, 3),
+    ] },
+    Bc6hMode { mode: 0x06, regions: 2, transformed: true, precision: 11, delta_bits: [4, 5, 4], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 10, 10), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 4),
+        (GW, 10, 10), (GZ, 0, 3), (BX, 0, 3), (BW, 10, 10), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 3), (BZ, 0, 0),
+        (BZ, 2, 2), (RZ, 0, 3), (GY, 4, 4), (BZ, 3, 3),
+    ] },
+    Bc6hMode { mode: 0x0A, regions: 2, transformed: true, precision: 11, delta_bits: [4, 4, 5], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 10, 10), (BY, 4, 4), (GY, 0, 3), (GX, 0, 3),
+        (GW, 10, 10), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BW, 10, 10), (BY, 0, 3), (RY, 0, 3), (BZ, 1, 1),
+        (BZ, 2, 2), (RZ, 0, 3), (BZ, 4, 4), (BZ, 3, 3),
+    ] },
+    Bc6hMode { mode: 0x0E, regions: 2, transformed: true, precision: 9, delta_bits: [5, 5, 5], layout: &[
+        (RW, 0, 8), (BY, 4, 4), (GW, 0, 8), (GY, 4, 4), (BW, 0, 8), (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4),
+        (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 4),
+        (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3),
+    ] },
+    Bc6hMode { mode: 0x12, regions: 2, transformed: true, precision: 8, delta_bits: [6, 5, 5], layout: &[
+        (RW, 0, 7), (GZ, 4, 4), (BY, 4, 4), (GW, 0, 7), (BZ, 2, 2), (GY, 4, 4), (BW, 0, 7), (BZ, 3, 3),
+        (BZ, 4, 4), (RX, 0, 5), (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1),
+        (BY, 0, 3), (RY, 0, 5), (RZ, 0, 5),
+    ] },
+    Bc6hMode { mode: 0x16, regions: 2, transformed: true, precision: 8, delta_bits: [5, 6, 5], layout: &[
+        (RW, 0, 7), (BZ, 0, 0), (BY, 4, 4), (GW, 0, 7), (GY, 5, 5), (GY, 4, 4), (BW, 0, 7), (GZ, 5, 5),
+        (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 5), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1),
+        (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3),
+    ] },
+    Bc6hMode { mode: 0x1A, regions: 2, transformed: true, precision: 8, delta_bits: [5, 5, 6], layout: &[
+        (RW, 0, 7), (BZ, 1, 1), (BY, 4, 4), (GW, 0, 7), (BY, 5, 5), (GY, 4, 4), (BW, 0, 7), (BZ, 5, 5),
+        (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 5),
+        (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3),
+    ] },
+    Bc6hMode { mode: 0x1E, regions: 2, transformed: false, precision: 6, delta_bits: [6, 6, 6], layout: &[
+        (RW, 0, 5), (GZ, 4, 4), (BZ, 0, 1), (BY, 4, 4), (GW, 0, 5), (GY, 5, 5), (BY, 5, 5), (BZ, 2, 2),
+        (GY, 4, 4), (BW, 0, 5), (GZ, 5, 5), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4), (RX, 0, 5), (GY, 0, 3),
+        (GX, 0, 5), (GZ, 0, 3), (BX, 0, 5), (BY, 0, 3), (RY, 0, 5), (RZ, 0, 5),
+    ] },
+    Bc6hMode { mode: 0x03, regions: 1, transformed: false, precision: 10, delta_bits: [10, 10, 10], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 9), (GX, 0, 9), (BX, 0, 9),
+    ] },
+    Bc6hMode { mode: 0x07, regions: 1, transformed: true, precision: 11, delta_bits: [9, 9, 9], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 8), (RW, 10, 10), (GX, 0, 8), (GW, 10, 10), (BX, 0, 8),
+        (BW, 10, 10),
+    ] },
+    Bc6hMode { mode: 0x0B, regions: 1, transformed: true, precision: 12, delta_bits: [8, 8, 8], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 7), (RW, 11, 10), (GX, 0, 7), (GW, 11, 10), (BX, 0, 7),
+        (BW, 11, 10),
+    ] },
+    Bc6hMode { mode: 0x0F, regions: 1, transformed: true, precision: 16, delta_bits: [4, 4, 4], layout: &[
+        (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 15, 10), (GX, 0, 3), (GW, 15, 10), (BX, 0, 3),
+        (BW, 15, 10),
+    ] },
+];
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
+
+// Endpoint values to 16 bits, see the BC6H specification
+fn unquantize(value: i32, precision: u32, signed: bool) -> i32 {
+    if !signed {
+        if precision >= 15 || value == 0 {
+            value
+        } else if value == (1 << precision) - 1 {
+            0xFFFF
+        } else {
+            ((value << 16) + 0x8000) >> precision
+        }
+    } else if precision >= 16 {
+        value
+    } else {
+        let magnitude = value.abs();
+        let unquantized = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (precision - 1)) - 1 {
+            0x7FFF
+        } else {
+            ((magnitude << 15) + 0x4000) >> (precision - 1)
+        };
+        if value < 0 { -unquantized } else { unquantized }
+    }
+}
+
+// Interpolated 16 bit values to half float bits
+fn finish_unquantize(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        (((-value * 31) >> 5) as u16) | 0x8000
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+fn decode_bc6h(block: &[u8], signed: bool) -> [[u8; 8]; 16] {
+    let mut bits = BlockBits::new(block);
+    let mut mode_bits = bits.read(2);
+    if mode_bits > 1 {
+        mode_bits |= bits.read(3) << 2;
+    }
+
+    // Reserved modes decode to black
+    let Some(mode) = BC6H_MODES.iter().find(|mode| mode.mode == mode_bits) else {
+        return [[0; 8]; 16];
+    };
+
+    let mut fields = [0i32; 12];
+    for &(field, first, last) in mode.layout {
+        let mut bit = first;
+        loop {
+            fields[field as usize] |= (bits.read(1) as i32) << bit;
+            if bit == last {
+                break;
+            }
+            bit = if first < last { bit + 1 } else { bit - 1 };
+        }
+    }
+    let partition = if mode.regions == 2 { bits.read(5) as usize } else { 0 };
+
+    let endpoint_count = mode.regions * 2;
+    let mut endpoints = [[0i32; 3]; 4];
+    for channel in 0..3 {
+        let base = fields[channel * 4];
+        let base = if signed { sign_extend(base, mode.precision) } else { base };
+        endpoints[0][channel] = base;
+
+        for endpoint in 1..endpoint_count {
+            let value = fields[channel * 4 + endpoint];
+            endpoints[endpoint][channel] = if mode.transformed {
+                let delta = sign_extend(value, mode.delta_bits[channel]);
+                let value = (base + delta) & ((1 << mode.precision) - 1);
+                if signed { sign_extend(value, mode.precision) } else { value }
+            } else if signed {
+                sign_extend(value, mode.precision)
+            } else {
+                value
+            };
+        }
+    }
+
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        for value in endpoint.iter_mut() {
+            *value = unquantize(*value, mode.precision, signed);
+        }
+    }
+
+    let index_bits = if mode.regions == 2 { 3 } else { 4 };
+    let indices = read_indices(&mut bits, index_bits, mode.regions, partition);
+    let weights = index_weights(index_bits);
+
+    let one = f16::ONE.to_bits().to_le_bytes();
+    let mut texels = [[0u8; 8]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let region = subset_of(mode.regions, partition, i);
+        let (e0, e1) = (endpoints[region * 2], endpoints[region * 2 + 1]);
+        let weight = weights[indices[i] as usize] as i32;
+
+        for channel in 0..3 {
+            let value = ((64 - weight) * e0[channel] + weight * e1[channel] + 32) >> 6;
+            texel[channel * 2..channel * 2 + 2].copy_from_slice(&finish_unquantize(value, signed).to_le_bytes());
+        }
+        texel[6..8].copy_from_slice(&one);
+    }
+    texels
+}
+
+fn decode_bc6h_unsigned(block: &[u8]) -> [[u8; 8]; 16] {
+    decode_bc6h(block, false)
+}
+
+fn decode_bc6h_signed(block: &[u8]) -> [[u8; 8]; 16] {
+    decode_bc6h(block, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Packs values least significant bit first, like BC6H and BC7 blocks are read
+    struct BitWriter {
+        bits: u128,
+        position: u32,
+    }
+
+    impl BitWriter {
+        fn new() -> Self {
+            Self { bits: 0, position: 0 }
+        }
+
+        fn write(&mut self, value: u128, count: u32) -> &mut Self {
+            self.bits |= value << self.position;
+            self.position += count;
+            self
+        }
+
+        fn block(&self) -> [u8; 16] {
+            self.bits.to_le_bytes()
+        }
+    }
+
+    fn ktx2(vk_format: u32, width: u32, height: u32, levels: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        for value in [vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, 0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.resize(80, 0);
+
+        let mut offset = 80 + levels.len() * 24;
+        for level in levels {
+            for value in [offset as u64, level.len() as u64, level.len() as u64] {
+                bytes.extend(value.to_le_bytes());
+            }
+            offset += level.len();
+        }
+        for level in levels {
+            bytes.extend(*level);
+        }
+        bytes
+    }
+
+    fn dds(four_cc: &[u8; 4], dxgi_format: u32, width: u32, height: u32, level_count: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0u8; DDS_HEADER_END];
+        bytes[0..4].copy_from_slice(&DDS_MAGIC);
+        bytes[12..16].copy_from_slice(&height.to_le_bytes());
+        bytes[16..20].copy_from_slice(&width.to_le_bytes());
+        bytes[28..32].copy_from_slice(&level_count.to_le_bytes());
+        bytes[84..88].copy_from_slice(four_cc);
+        if four_cc == b"DX10" {
+            bytes.resize(DDS_DX10_HEADER_END, 0);
+            bytes[128..132].copy_from_slice(&dxgi_format.to_le_bytes());
+            bytes[140..144].copy_from_slice(&1u32.to_le_bytes());
+        }
+        bytes.extend(data);
+        bytes
+    }
+
+    // Red and blue endpoints, indices 0, 1, 2, 3 in the first row and 0 elsewhere
+    const BC1_BLOCK: [u8; 8] = [0x00, 0xF8, 0x1F, 0x00, 0b1110_0100, 0, 0, 0];
+
+    #[test]
+    fn parses_ktx2_levels() {
+        let image = parse_ktx2(&ktx2(145, 8, 4, &[&[1; 32], &[2; 16], &[3; 16]])).unwrap();
+        assert_eq!(image.format, TextureFormat::Bc7RgbaUnorm);
+        assert_eq!((image.width, image.height), (8, 4));
+        assert_eq!(image.levels, vec![vec![1; 32], vec![2; 16], vec![3; 16]]);
+    }
+
+    #[test]
+    fn rejects_invalid_ktx2() {
+        assert!(parse_ktx2(b"not a texture").is_err());
+        assert!(parse_ktx2(&ktx2(1000, 4, 4, &[&[0; 16]])).is_err());
+
+        let mut supercompressed = ktx2(145, 4, 4, &[&[0; 16]]);
+        supercompressed[44] = 1;
+        assert!(parse_ktx2(&supercompressed).is_err());
+
+        let mut truncated = ktx2(145, 4, 4, &[&[0; 16]]);
+        truncated.truncate(truncated.len() - 1);
+        assert!(parse_ktx2(&truncated).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        // More levels than a 4x4 image has, which would shift its size past 32 bits
+        let mut too_many_levels = ktx2(145, 4, 4, &[&[0; 16]]);
+        too_many_levels[40..44].copy_from_slice(&40u32.to_le_bytes());
+        assert!(parse_ktx2(&too_many_levels).is_err());
+        assert!(parse_dds(&dds(b"DXT1", 0, 4, 4, 64, &[0; 8])).is_err());
+        assert!(parse_dds(&dds(b"DXT1", 0, 4, 4, u32::MAX, &[0; 8])).is_err());
+        assert!(parse_dds(&dds(b"DXT1", 0, 0, 0, 1, &[0; 8])).is_err());
+
+        let mut overflowing_offset = ktx2(145, 4, 4, &[&[0; 16]]);
+        overflowing_offset[80..88].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(parse_ktx2(&overflowing_offset).is_err());
+
+        // An 8x8 BC7 level needs 64 bytes
+        assert!(parse_ktx2(&ktx2(145, 8, 8, &[&[0; 16]])).is_err());
+        assert!(parse_dds(&dds(b"DXT1", 0, u32::MAX, u32::MAX, 1, &[0; 8])).is_err());
+    }
+
+    #[test]
+    fn narrows_rgba32_float() {
+        let texel: Vec<u8> = [1.0f32, 0.5, 0.0, 2.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let image = parse_ktx2(&ktx2(109, 1, 1, &[&texel])).unwrap();
+        assert_eq!(image.format, TextureFormat::Rgba16Float);
+
+        let expected: Vec<u8> = [0x3C00u16, 0x3800, 0x0000, 0x4000].iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(image.levels[0], expected);
+    }
+
+    #[test]
+    fn parses_dds_mip_chain() {
+        // 8x8 BC1 is 4 blocks, then one block for each of 4x4, 2x2 and 1x1
+        let image = parse_dds(&dds(b"DXT1", 0, 8, 8, 4, &[0; 56])).unwrap();
+        assert_eq!(image.format, TextureFormat::Bc1RgbaUnorm);
+        let sizes: Vec<usize> = image.levels.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![32, 8, 8, 8]);
+
+        assert!(parse_dds(&dds(b"DXT1", 0, 8, 8, 4, &[0; 55])).is_err());
+    }
+
+    #[test]
+    fn parses_dds_dx10() {
+        let image = parse_dds(&dds(b"DX10", 98, 4, 4, 1, &[7; 16])).unwrap();
+        assert_eq!(image.format, TextureFormat::Bc7RgbaUnorm);
+        assert_eq!(image.levels, vec![vec![7; 16]]);
+
+        assert!(parse_dds(&dds(b"DX10", 1000, 4, 4, 1, &[0; 16])).is_err());
+        assert!(parse_dds(&dds(b"ABCD", 0, 4, 4, 1, &[0; 16])).is_err());
+    }
+
+    #[test]
+    fn decodes_bc1() {
+        let texels = decode_bc1(&BC1_BLOCK);
+        assert_eq!(texels[0], [255, 0, 0, 255]);
+        assert_eq!(texels[1], [0, 0, 255, 255]);
+        assert_eq!(texels[2], [170, 0, 85, 255]);
+        assert_eq!(texels[3], [85, 0, 170, 255]);
+        assert_eq!(texels[15], [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn decodes_bc1_punch_through() {
+        // c0 <= c1 selects three colors and transparent black
+        let block = [0x1F, 0x00, 0x00, 0xF8, 0b1110_0100, 0, 0, 0];
+        let texels = decode_bc1(&block);
+        assert_eq!(texels[2], [127, 0, 127, 255]);
+        assert_eq!(texels[3], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn decodes_bc3_alpha() {
+        // Alpha indices 0, 1, 2 in the first three texels
+        let mut block = [255, 0, 0b1000_1000, 0, 0, 0, 0, 0].to_vec();
+        block.extend(BC1_BLOCK);
+        let texels = decode_bc3(&block);
+        assert_eq!([texels[0][3], texels[1][3], texels[2][3], texels[3][3]], [255, 0, 218, 255]);
+        assert_eq!(texels[0][..3], [255, 0, 0]);
+    }
+
+    #[test]
+    fn decodes_signed_bc4_and_bc5() {
+        // -128 is clamped to -127, index 1 selects the second endpoint
+        let red = [0x7F, 0x80, 0b1000, 0, 0, 0, 0, 0];
+        let texels = decode_bc4_snorm(&red);
+        assert_eq!(texels[0], [127, 0, 0, 127]);
+        assert_eq!(texels[1][0] as i8, -127);
+
+        let green = [0x81, 0x7F, 0, 0, 0, 0, 0, 0];
+        let texels = decode_bc5_snorm(&[red, green].concat());
+        assert_eq!(texels[1][0] as i8, -127);
+        assert_eq!(texels[1][1] as i8, -127);
+    }
+
+    #[test]
+    fn clips_blocks_past_the_edges() {
+        let decoded = decode_blocks(&BC1_BLOCK, 3, 1, 8, decode_bc1);
+        assert_eq!(decoded, [255, 0, 0, 255, 0, 0, 255, 255, 170, 0, 85, 255]);
+    }
+
+    #[test]
+    fn decompresses_unaligned_images() {
+        let image = ImageLevels { format: TextureFormat::Bc1RgbaUnorm, width: 6, height: 5, levels: vec![BC1_BLOCK.repeat(4)] };
+        assert!(!image.is_block_aligned());
+
+        let decoded = decompress(&image).unwrap();
+        assert_eq!(decoded.format, TextureFormat::Rgba8Unorm);
+        assert_eq!(decoded.levels[0].len(), 6 * 5 * 4);
+        assert_eq!(decoded.levels[0][4 * 4..5 * 4], [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn partition_anchors_are_in_their_subsets() {
+        for partition in 0..64 {
+            assert_eq!(subset_of(2, partition, 0), 0);
+            assert_eq!(subset_of(2, partition, ANCHORS_2[partition] as usize), 1);
+
+            assert_eq!(subset_of(3, partition, 0), 0);
+            assert_eq!(subset_of(3, partition, ANCHORS_3[partition][0] as usize), 1);
+            assert_eq!(subset_of(3, partition, ANCHORS_3[partition][1] as usize), 2);
+        }
+    }
+
+    #[test]
+    fn decodes_reserved_bc7_mode_as_zero() {
+        assert_eq!(decode_bc7(&[0; 16]), [[0; 4]; 16]);
+    }
+
+    #[test]
+    fn decodes_bc7_mode_6() {
+        // Black and white endpoints, the p-bits complete their 8th bit
+        let mut bits = BitWriter::new();
+        bits.write(1 << 6, 7);
+        for _ in 0..4 {
+            bits.write(0, 7).write(127, 7);
+        }
+        bits.write(0, 1).write(1, 1);
+
+        // Anchor texel 0 has a 3 bit index
+        bits.write(0, 3).write(15, 4).write(8, 4);
+        for _ in 3..16 {
+            bits.write(15, 4);
+        }
+
+        let texels = decode_bc7(&bits.block());
+        assert_eq!(texels[0], [0, 0, 0, 0]);
+        assert_eq!(texels[1], [255, 255, 255, 255]);
+        assert_eq!(texels[2], [135, 135, 135, 135]);
+    }
+
+    fn half(texel: [u8; 8], channel: usize) -> u16 {
+        u16::from_le_bytes([texel[channel * 2], texel[channel * 2 + 1]])
+    }
+
+    // Mode 11 stores two 10 bit endpoints as is, the anchor index is capped to its 3 bits
+    fn bc6h_mode_11(e0: u128, e1: u128, index: u128) -> [u8; 16] {
+        let mut bits = BitWriter::new();
+        bits.write(0x03, 5);
+        for endpoint in [e0, e1] {
+            bits.write(endpoint, 10).write(endpoint, 10).write(endpoint, 10);
+        }
+        bits.write(index.min(7), 3);
+        for _ in 1..16 {
+            bits.write(index, 4);
+        }
+        bits.block()
+    }
+
+    #[test]
+    fn decodes_bc6h_single_region() {
+        let texels = decode_bc6h_unsigned(&bc6h_mode_11(0, 1023, 15));
+        // The largest finite half float
+        assert_eq!(half(texels[1], 0), 0x7BFF);
+        assert_eq!(half(texels[1], 3), 0x3C00);
+        // The anchor index can only reach 7 of 15
+        assert!(half(texels[0], 0) < 0x7BFF);
+
+        let texels = decode_bc6h_unsigned(&bc6h_mode_11(0, 1023, 0));
+        assert_eq!(half(texels[5], 0), 0);
+    }
+
+    #[test]
+    fn decodes_bc6h_deltas() {
+        // Mode 1, endpoint 512 with a delta of -1 to the other end of the first region
+        let mut bits = BitWriter::new();
+        bits.write(0, 2).write(0, 3);
+        bits.write(512, 10).write(512, 10).write(512, 10);
+        bits.write(0x1F, 5).write(0, 1).write(0, 4).write(0x1F, 5);
+        bits.write(0, 1).write(0, 4).write(0x1F, 5);
+        bits.write(0, 1).write(0, 4).write(0, 5).write(0, 1).write(0, 5).write(0, 1);
+        // Partition 0, texel 1 is the second endpoint of region 0
+        bits.write(0, 5).write(0, 2).write(7, 3);
+        let texels = decode_bc6h_unsigned(&bits.block());
+
+        let explicit = decode_bc6h_unsigned(&bc6h_mode_11(512, 511, 15));
+        assert_eq!(texels[1][..6], explicit[1][..6]);
+        // Texel 0 is the first endpoint
+        let explicit = decode_bc6h_unsigned(&bc6h_mode_11(512, 511, 0));
+        assert_eq!(texels[0][..6], explicit[0][..6]);
+    }
+
+    #[test]
+    fn decodes_reserved_bc6h_mode_as_zero() {
+        assert_eq!(decode_bc6h_signed(&[0x13; 16]), [[0; 8]; 16]);
+    }
+}
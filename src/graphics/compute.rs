@@ -0,0 +1,227 @@
+use std::marker::PhantomData;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use wgpu::{BindGroup, BindGroupLayout, Buffer, CommandEncoder, ComputePipeline, Device};
+
+use super::Shader;
+
+// One entry point of a WGSL compute shader and its pipeline. The bind group layouts are either
+// given, so bind groups can be shared with other passes, or derived from what the entry point uses.
+// `workgroup_size` must match the entry point's @workgroup_size, `dispatch_size` divides by it.
+pub struct ComputePass {
+    entry_point: String,
+    workgroup_size: (u32, u32, u32),
+    pipeline: ComputePipeline,
+}
+
+impl ComputePass {
+    // Empty `bind_layouts` derives them from the shader, get them with `bind_layout`
+    pub fn new(device: &Device,
+        shader: &Shader,
+        entry_point: &str,
+        bind_layouts: &[&BindGroupLayout],
+        workgroup_size: (u32, u32, u32)) -> Self {
+
+        let layout = (!bind_layouts.is_empty()).then(|| device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(entry_point),
+            bind_group_layouts: bind_layouts,
+            push_constant_ranges: &[],
+        }));
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: layout.as_ref(),
+            module: &shader.module,
+            entry_point,
+        });
+
+        Self {
+            entry_point: entry_point.to_string(),
+            workgroup_size,
+            pipeline,
+        }
+    }
+
+    pub fn uniform_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+
+    pub fn storage_entry(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+
+    pub fn texture_entry(binding: u32, view_dimension: wgpu::TextureViewDimension, sample_type: wgpu::TextureSampleType) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension,
+                sample_type,
+            },
+            count: None,
+        }
+    }
+
+    pub fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        }
+    }
+
+    // Write only, the access every format supports
+    pub fn storage_texture_entry(binding: u32, view_dimension: wgpu::TextureViewDimension, format: wgpu::TextureFormat) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format,
+                view_dimension,
+            },
+            count: None,
+        }
+    }
+
+    pub fn bind_layout(&self, index: u32) -> BindGroupLayout {
+        self.pipeline.get_bind_group_layout(index)
+    }
+
+    // Bind group `index` of this pass, `entries` are the bindings the entry point uses
+    pub fn bind_group(&self, device: &Device, index: u32, entries: &[wgpu::BindGroupEntry]) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&self.entry_point),
+            layout: &self.bind_layout(index),
+            entries,
+        })
+    }
+
+    // Runs `workgroups` workgroups in a compute pass of their own, `bind_groups` are set in order from group 0
+    pub fn dispatch(&self, encoder: &mut CommandEncoder, bind_groups: &[&BindGroup], workgroups: (u32, u32, u32)) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some(&self.entry_point),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            compute_pass.set_bind_group(index as u32, bind_group, &[]);
+        }
+
+        let (x, y, z) = workgroups;
+        compute_pass.dispatch_workgroups(x, y, z);
+    }
+
+    // Runs at least one invocation per element of `size`, the shader must skip those past its edges
+    pub fn dispatch_size(&self, encoder: &mut CommandEncoder, bind_groups: &[&BindGroup], size: (u32, u32, u32)) {
+        let (x, y, z) = self.workgroup_size;
+        self.dispatch(encoder, bind_groups, (size.0.div_ceil(x), size.1.div_ceil(y), size.2.div_ceil(z)));
+    }
+}
+
+enum ReadbackState {
+    Idle,
+    Copied,
+    Mapping(Receiver<Result<(), wgpu::BufferAsyncError>>),
+}
+
+// Reads `len` `T`s of a GPU buffer back on the CPU, e.g. compute results. `copy` records the copy
+// into an encoder, then once that is submitted `try_read` returns the data the frame the GPU is done,
+// without waiting for it. The source buffer needs COPY_SRC usage, and only one read is in flight at a time.
+pub struct Readback<T: bytemuck::Pod> {
+    staging: Buffer,
+    state: ReadbackState,
+    _data: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> Readback<T> {
+    pub fn new(device: &Device, label: &str, len: usize) -> Self {
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (len * std::mem::size_of::<T>()) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            staging,
+            state: ReadbackState::Idle,
+            _data: PhantomData,
+        }
+    }
+
+    // True while a copy is waiting to be read
+    pub fn is_pending(&self) -> bool {
+        !matches!(self.state, ReadbackState::Idle)
+    }
+
+    // Copies from `offset` bytes into `source`, skipped and false while the previous copy is still pending
+    pub fn copy(&mut self, encoder: &mut CommandEncoder, source: &Buffer, offset: u64) -> bool {
+        if self.is_pending() {
+            return false;
+        }
+
+        encoder.copy_buffer_to_buffer(source, offset, &self.staging, 0, self.staging.size());
+        self.state = ReadbackState::Copied;
+        true
+    }
+
+    // The copied data once the GPU has finished it, only call after the encoder with the copy was submitted
+    pub fn try_read(&mut self, device: &Device) -> Option<Vec<T>> {
+        self.read_with(device, wgpu::Maintain::Poll)
+    }
+
+    // Like `try_read` but blocks until the GPU is done, None only when nothing was copied or mapping failed
+    pub fn read(&mut self, device: &Device) -> Option<Vec<T>> {
+        self.read_with(device, wgpu::Maintain::Wait)
+    }
+
+    fn read_with(&mut self, device: &Device, maintain: wgpu::Maintain) -> Option<Vec<T>> {
+        if matches!(self.state, ReadbackState::Copied) {
+            let (sender, receiver) = mpsc::channel();
+            self.staging.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                // The receiver is gone when the readback was dropped meanwhile
+                let _ = sender.send(result);
+            });
+            self.state = ReadbackState::Mapping(receiver);
+        }
+
+        let ReadbackState::Mapping(receiver) = &self.state else {
+            return None;
+        };
+        device.poll(maintain);
+
+        match receiver.try_recv() {
+            Ok(Ok(())) => {
+                let data = bytemuck::cast_slice(&self.staging.slice(..).get_mapped_range()).to_vec();
+                self.staging.unmap();
+                self.state = ReadbackState::Idle;
+                Some(data)
+            },
+            Ok(Err(_)) | Err(TryRecvError::Disconnected) => {
+                self.state = ReadbackState::Idle;
+                None
+            },
+            Err(TryRecvError::Empty) => None,
+        }
+    }
+}
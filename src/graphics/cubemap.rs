@@ -0,0 +1,198 @@
+use wgpu::{Device, Queue, BindGroupLayout, BindGroup};
+
+use super::compressed::ImageLevels;
+use super::texture;
+use super::{ComputePass, Shader, Texture2D};
+
+// @workgroup_size of every entry point in resources/ibl.wgsl
+pub const IBL_WORKGROUP: (u32, u32, u32) = (8, 8, 1);
+
+pub struct TextureCube {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+}
+
+impl TextureCube {
+    // Format of the cubes compute shaders write, see `storage`
+    pub const STORAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    // Faces are given in +X, -X, +Y, -Y, +Z, -Z order
+    pub fn from_faces(device: &Device, queue: &Queue, files: [&str; 6]) -> Self {
+        let faces: Vec<ImageLevels> = files.iter()
+            .map(|file| texture::load_image(file))
+            .collect();
+
+        TextureCube::from_layers(device, queue, &faces)
+    }
+
+    // Projects an equirectangular (latitude/longitude) image onto six faces of `face_size` texels
+    pub fn from_equirectangular(device: &Device, queue: &Queue, file: &str, face_size: u32) -> Self {
+        let equirect = Texture2D::new(device, queue, file);
+        TextureCube::from_equirectangular_texture(device, queue, &equirect, face_size)
+    }
+
+    // Like `from_equirectangular`, with the projection and the mip chain below it computed on the GPU
+    // by resources/ibl.wgsl. `face_size` is at least one.
+    pub fn from_equirectangular_texture(device: &Device, queue: &Queue, equirect: &Texture2D, face_size: u32) -> Self {
+        let face_size = face_size.max(1);
+        let mips = TextureCube::mip_count(face_size);
+        let cube = TextureCube::storage(device, "Environment Cube", face_size, mips);
+
+        let shader = Shader::new("resources/ibl.wgsl", device);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Equirectangular Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let create_pass = |entry_point, entries: &[wgpu::BindGroupLayoutEntry]| {
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(entry_point),
+                entries,
+            });
+            ComputePass::new(device, &shader, entry_point, &[&layout], IBL_WORKGROUP)
+        };
+        let cube_output = ComputePass::storage_texture_entry(4, wgpu::TextureViewDimension::D2Array, TextureCube::STORAGE_FORMAT);
+        let equirect_pass = create_pass("equirect_to_cube", &[
+            ComputePass::sampler_entry(0),
+            ComputePass::texture_entry(1, wgpu::TextureViewDimension::D2, wgpu::TextureSampleType::Float { filterable: true }),
+            cube_output,
+        ]);
+        let downsample_pass = create_pass("downsample", &[
+            ComputePass::texture_entry(3, wgpu::TextureViewDimension::D2Array, wgpu::TextureSampleType::Float { filterable: false }),
+            cube_output,
+        ]);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Equirectangular Encoder"),
+        });
+
+        let bind_group = equirect_pass.bind_group(device, 0, &[
+            wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::Sampler(&sampler) },
+            wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&equirect.view) },
+            wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::TextureView(&cube.mip_view(0)) },
+        ]);
+        equirect_pass.dispatch_size(&mut encoder, &[&bind_group], (face_size, face_size, 6));
+
+        for mip in 1..mips {
+            let size = (face_size >> mip).max(1);
+            let bind_group = downsample_pass.bind_group(device, 0, &[
+                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&cube.mip_view(mip - 1)) },
+                wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::TextureView(&cube.mip_view(mip)) },
+            ]);
+            downsample_pass.dispatch_size(&mut encoder, &[&bind_group], (size, size, 6));
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+        cube
+    }
+
+    // An empty cube of `size` texels with `mips` levels that compute shaders write one mip at a time
+    // through `mip_view`. wgpu zero initializes it.
+    pub fn storage(device: &Device, label: &str, size: u32, mips: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: mips,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TextureCube::STORAGE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(label),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    // One mip as a 2D array of the six faces, the view dimension storage textures need
+    pub fn mip_view(&self, mip: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Cube Mip View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            base_mip_level: mip,
+            mip_level_count: Some(1),
+            ..Default::default()
+        })
+    }
+
+    // Levels of a full mip chain of `size` texel faces, down to 1x1
+    fn mip_count(size: u32) -> u32 {
+        size.max(1).ilog2() + 1
+    }
+
+    fn from_layers(device: &Device, queue: &Queue, faces: &[ImageLevels]) -> Self {
+        assert!(faces.len() == 6, "Cube textures need six faces");
+        let first = &faces[0];
+        assert!(first.width == first.height, "Cube faces must be square");
+        assert!(faces.iter().all(|face| face.width == first.width && face.height == first.height && face.format == first.format),
+            "Cube faces must share their size and format");
+
+        let texture = texture::create_layered_texture(device, queue, "Cube Texture", faces);
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Cube Texture View"),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    pub fn create_binding(&self, device: &Device, index: u32) -> (BindGroupLayout, BindGroup) {
+        texture::create_texture_binding(device, index, &self.view, &self.sampler, wgpu::TextureViewDimension::Cube)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_chain_ends_at_one_texel() {
+        assert_eq!(TextureCube::mip_count(256), 9);
+        assert_eq!(TextureCube::mip_count(255), 8);
+        assert_eq!(TextureCube::mip_count(1), 1);
+        assert_eq!(TextureCube::mip_count(0), 1);
+    }
+}
@@ -0,0 +1,297 @@
+use cgmath::SquareMatrix;
+use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, Device, Queue, RenderPass, TextureView};
+
+use super::{Camera, Shader, TargetFormat, Uniform, DEPTH_FORMAT};
+
+// Albedo, normal, material and emissive, see resources/gbuffer.wgsl for what they hold
+pub const GBUFFER_FORMATS: [wgpu::TextureFormat; 4] = [
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    wgpu::TextureFormat::Rgba16Float,
+    wgpu::TextureFormat::Rgba8Unorm,
+    wgpu::TextureFormat::Rgba16Float,
+];
+
+// How opaque geometry gets its lighting. Forward shades while drawing, deferred draws
+// a G-buffer first and shades every pixel once, however many lights and how much overdraw.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderPath {
+    Forward,
+    Deferred,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DeferredUniform {
+    inverse_view_projection: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+}
+
+// The G-buffer textures with their own single sampled depth, and the lighting pass's bind group for them
+pub struct GBuffer {
+    size: (u32, u32),
+    _textures: Vec<wgpu::Texture>,
+    views: Vec<TextureView>,
+    _depth: wgpu::Texture,
+    depth_view: TextureView,
+    bind_group: BindGroup,
+}
+
+impl GBuffer {
+    // What pipelines drawing into the G-buffer write, one target per GBUFFER_FORMATS entry
+    pub fn color_targets() -> [Option<wgpu::ColorTargetState>; 4] {
+        GBUFFER_FORMATS.map(|format| Some(wgpu::ColorTargetState {
+            format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::ALL,
+        }))
+    }
+
+    pub fn depth_stencil() -> Option<wgpu::DepthStencilState> {
+        Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    // World space normals, see resources/gbuffer.wgsl
+    pub fn normal_view(&self) -> &TextureView {
+        &self.views[1]
+    }
+
+    pub fn depth_view(&self) -> &TextureView {
+        &self.depth_view
+    }
+
+    fn new(device: &Device, layout: &BindGroupLayout, size: (u32, u32)) -> Self {
+        let create_texture = |label, format| device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let textures: Vec<wgpu::Texture> = ["G-Buffer Albedo", "G-Buffer Normal", "G-Buffer Material", "G-Buffer Emissive"].into_iter()
+            .zip(GBUFFER_FORMATS)
+            .map(|(label, format)| create_texture(label, format))
+            .collect();
+        let views: Vec<TextureView> = textures.iter()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+            .collect();
+
+        let depth = create_texture("G-Buffer Depth", DEPTH_FORMAT);
+        let depth_view = depth.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut entries: Vec<wgpu::BindGroupEntry> = views.iter()
+            .enumerate()
+            .map(|(i, view)| wgpu::BindGroupEntry {
+                binding: i as u32,
+                resource: wgpu::BindingResource::TextureView(view),
+            })
+            .collect();
+        entries.push(wgpu::BindGroupEntry {
+            binding: GBUFFER_FORMATS.len() as u32,
+            resource: wgpu::BindingResource::TextureView(&depth_view),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("G-Buffer Bind Group"),
+            layout,
+            entries: &entries,
+        });
+
+        Self {
+            size,
+            _textures: textures,
+            views,
+            _depth: depth,
+            depth_view,
+            bind_group,
+        }
+    }
+}
+
+// The deferred render path. Materials draw into the G-buffer with `Material::draw_gbuffer` in the
+// pass `begin_gbuffer_pass` starts, then `draw_lighting` shades it with every light in a full screen
+// pass over the scene target, leaving pixels nothing was drawn on (e.g. the background) alone.
+// The lit pixels also get the G-buffer's depth, so sprites and whatever else follows depth test
+// against the geometry. With MSAA every sample of a pixel gets the same color and depth, the
+// G-buffer itself has one sample.
+pub struct DeferredRenderer {
+    uniform: Uniform<DeferredUniform>,
+    gbuffer_layout: BindGroupLayout,
+    shader: Shader,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    target: TargetFormat,
+    gbuffer: Option<GBuffer>,
+}
+
+impl DeferredRenderer {
+    // `target` is what the lighting pass draws into, `lights_layout` that of `Lights`
+    pub fn new(device: &Device, target: &TargetFormat, lights_layout: &BindGroupLayout) -> Self {
+        let uniform = Uniform::new(device,
+            "Deferred Uniform Buffer",
+            wgpu::ShaderStages::FRAGMENT,
+            DeferredUniform {
+                inverse_view_projection: cgmath::Matrix4::identity().into(),
+                camera_position: [0.0, 0.0, 1.0, 0.0],
+            });
+
+        let texture = |binding, sample_type| wgpu::BindGroupLayoutEntry { // texture entry
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        };
+        let mut entries: Vec<wgpu::BindGroupLayoutEntry> = (0..GBUFFER_FORMATS.len() as u32)
+            .map(|binding| texture(binding, wgpu::TextureSampleType::Float { filterable: false }))
+            .collect();
+        entries.push(texture(GBUFFER_FORMATS.len() as u32, wgpu::TextureSampleType::Depth));
+
+        let gbuffer_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("G-Buffer Bind Group Layout"),
+            entries: &entries,
+        });
+
+        let shader = Shader::new("resources/deferred.wgsl", device);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Deferred Lighting Pipeline Layout"),
+            bind_group_layouts: &[&uniform.bind_layout, lights_layout, &gbuffer_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = DeferredRenderer::create_pipeline(device, target, &shader, &pipeline_layout);
+
+        Self {
+            uniform,
+            gbuffer_layout,
+            shader,
+            pipeline_layout,
+            pipeline,
+            target: *target,
+            gbuffer: None,
+        }
+    }
+
+    fn create_pipeline(device: &Device,
+        target: &TargetFormat,
+        shader: &Shader,
+        layout: &wgpu::PipelineLayout) -> wgpu::RenderPipeline {
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Deferred Lighting Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &shader.module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader.module,
+                entry_point: "fs_lighting",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target.color,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: target.depth_stencil(true, wgpu::CompareFunction::Always),
+            multisample: target.multisample(),
+            multiview: None,
+        })
+    }
+
+    // Rebuilds the lighting pipeline when the target differs from the one it was built for
+    pub fn set_target(&mut self, device: &Device, target: &TargetFormat) {
+        if self.target != *target {
+            self.pipeline = DeferredRenderer::create_pipeline(device, target, &self.shader, &self.pipeline_layout);
+            self.target = *target;
+        }
+    }
+
+    // None before the first `resize`
+    pub fn gbuffer(&self) -> Option<&GBuffer> {
+        self.gbuffer.as_ref()
+    }
+
+    // Recreates the G-buffer at `size`, which must be that of the target the lighting pass draws into
+    pub fn resize(&mut self, device: &Device, size: (u32, u32)) {
+        if !matches!(&self.gbuffer, Some(gbuffer) if gbuffer.size == size) {
+            self.gbuffer = Some(GBuffer::new(device, &self.gbuffer_layout, size));
+        }
+    }
+
+    pub fn update_buffer(&mut self, queue: &Queue, camera: &Camera) {
+        let inverse_view_projection = camera.view_projection().invert()
+            .unwrap_or_else(cgmath::Matrix4::identity);
+
+        self.uniform.set(DeferredUniform {
+            inverse_view_projection: inverse_view_projection.into(),
+            camera_position: camera.position().into(),
+        });
+        self.uniform.update_buffer(queue);
+    }
+
+    // Clears the G-buffer, draw the materials into the returned pass with `Material::draw_gbuffer`
+    pub fn begin_gbuffer_pass<'a>(&'a self, encoder: &'a mut CommandEncoder) -> RenderPass<'a> {
+        let gbuffer = self.gbuffer.as_ref().expect("The G-buffer is created by resize");
+
+        let color_attachments: Vec<Option<wgpu::RenderPassColorAttachment>> = gbuffer.views.iter()
+            .map(|view| Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            }))
+            .collect();
+
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("G-Buffer Pass"),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &gbuffer.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        })
+    }
+
+    // Shades the G-buffer into the current pass, after the background
+    pub fn draw_lighting<'a>(&'a self, rp: &mut RenderPass<'a>, lights_group: &'a BindGroup) {
+        let gbuffer = self.gbuffer.as_ref().expect("The G-buffer is created by resize");
+
+        rp.set_pipeline(&self.pipeline);
+        rp.set_bind_group(0, &self.uniform.bind_group, &[]);
+        rp.set_bind_group(1, lights_group, &[]);
+        rp.set_bind_group(2, &gbuffer.bind_group, &[]);
+        rp.draw(0..3, 0..1);
+    }
+}
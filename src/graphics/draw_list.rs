@@ -0,0 +1,175 @@
+use std::any::TypeId;
+use std::rc::Rc;
+
+use cgmath::Matrix4;
+use wgpu::{BindGroup, Device, Queue, RenderPass, VertexBufferLayout};
+
+use super::{Instance, InstanceBuffer, InstanceHandle, Material, Mesh, ModelInstance, NormalInstance, ShadowCaster, TargetFormat};
+
+// A lit material of any parameter type, so materials with different uniform blocks
+// (e.g. `PhongParams` and `PbrParams`) can share a `DrawList`
+pub trait LitMaterial {
+    fn prepare(&mut self, device: &Device, queue: &Queue);
+    fn set_target(&mut self, device: &Device, target: &TargetFormat);
+    fn draw<'a>(&'a self,
+        rp: &mut RenderPass<'a>,
+        shared_groups: &[&'a BindGroup],
+        mesh: &'a Mesh,
+        instances: &'a InstanceBuffer<NormalInstance>);
+    fn draw_gbuffer<'a>(&'a self,
+        rp: &mut RenderPass<'a>,
+        shared_groups: &[&'a BindGroup],
+        mesh: &'a Mesh,
+        instances: &'a InstanceBuffer<NormalInstance>);
+}
+
+impl<U: bytemuck::Pod> LitMaterial for Material<U, NormalInstance> {
+    fn prepare(&mut self, device: &Device, queue: &Queue) {
+        Material::prepare(self, device, queue);
+    }
+
+    fn set_target(&mut self, device: &Device, target: &TargetFormat) {
+        Material::set_target(self, device, target);
+    }
+
+    fn draw<'a>(&'a self,
+        rp: &mut RenderPass<'a>,
+        shared_groups: &[&'a BindGroup],
+        mesh: &'a Mesh,
+        instances: &'a InstanceBuffer<NormalInstance>) {
+
+        Material::draw(self, rp, shared_groups, mesh, instances);
+    }
+
+    fn draw_gbuffer<'a>(&'a self,
+        rp: &mut RenderPass<'a>,
+        shared_groups: &[&'a BindGroup],
+        mesh: &'a Mesh,
+        instances: &'a InstanceBuffer<NormalInstance>) {
+
+        Material::draw_gbuffer(self, rp, shared_groups, mesh, instances);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialId(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DrawItemId(usize);
+
+// One instance of a draw item, e.g. what a `SceneGraph` node drives
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DrawInstance {
+    pub item: DrawItemId,
+    pub handle: InstanceHandle,
+}
+
+struct DrawItem {
+    material: usize,
+    mesh: Rc<Mesh>,
+    instances: InstanceBuffer<NormalInstance>,
+}
+
+impl ShadowCaster for DrawItem {
+    fn instance_type(&self) -> TypeId {
+        TypeId::of::<NormalInstance>()
+    }
+
+    fn instance_layout(&self) -> VertexBufferLayout<'static> {
+        NormalInstance::layout()
+    }
+
+    fn draw_depth<'a>(&'a self, rp: &mut RenderPass<'a>) {
+        rp.set_vertex_buffer(0, self.mesh.vertex_buffer.slice(..));
+        rp.set_vertex_buffer(1, self.instances.buffer.slice(..));
+        rp.set_index_buffer(self.mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        rp.draw_indexed(0..self.mesh.num_indices, 0, 0..self.instances.len() as u32);
+    }
+}
+
+// The lit geometry of a frame: meshes with their instances, each drawn with one of the list's materials.
+// The same list feeds the forward pass, the G-buffer pass, the shadow maps and the SSAO depth prepass,
+// so every path draws the same objects. Materials drawn with `draw_gbuffer` need `Material::enable_deferred`.
+#[derive(Default)]
+pub struct DrawList {
+    materials: Vec<Box<dyn LitMaterial>>,
+    items: Vec<DrawItem>,
+}
+
+impl DrawList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_material(&mut self, material: impl LitMaterial + 'static) -> MaterialId {
+        self.materials.push(Box::new(material));
+        MaterialId(self.materials.len() - 1)
+    }
+
+    pub fn add_item(&mut self, device: &Device, material: MaterialId, mesh: Rc<Mesh>) -> DrawItemId {
+        self.items.push(DrawItem {
+            material: material.0,
+            mesh,
+            instances: InstanceBuffer::new(device, &[]),
+        });
+        DrawItemId(self.items.len() - 1)
+    }
+
+    pub fn add_instance(&mut self, item: DrawItemId, data: NormalInstance) -> DrawInstance {
+        let handle = self.items[item.0].instances.add_instance(data);
+        DrawInstance { item, handle }
+    }
+
+    pub fn remove_instance(&mut self, instance: DrawInstance) -> NormalInstance {
+        self.items[instance.item.0].instances.remove_instance(instance.handle)
+    }
+
+    pub fn instances(&self, item: DrawItemId) -> &InstanceBuffer<NormalInstance> {
+        &self.items[item.0].instances
+    }
+
+    pub fn instances_mut(&mut self, item: DrawItemId) -> &mut InstanceBuffer<NormalInstance> {
+        &mut self.items[item.0].instances
+    }
+
+    pub fn set_model(&mut self, instance: DrawInstance, model: Matrix4<f32>) {
+        let instances = &mut self.items[instance.item.0].instances;
+        let mut data = *instances.get(instance.handle);
+        data.set_model(model);
+        instances.set_instance(instance.handle, data);
+    }
+
+    // Uploads the materials' parameters and the changed instances, call once per frame before drawing
+    pub fn prepare(&mut self, device: &Device, queue: &Queue) {
+        for material in &mut self.materials {
+            material.prepare(device, queue);
+        }
+        for item in &mut self.items {
+            item.instances.update_buffer(device, queue);
+        }
+    }
+
+    pub fn set_target(&mut self, device: &Device, target: &TargetFormat) {
+        for material in &mut self.materials {
+            material.set_target(device, target);
+        }
+    }
+
+    pub fn draw<'a>(&'a self, rp: &mut RenderPass<'a>, shared_groups: &[&'a BindGroup]) {
+        for item in self.items.iter().filter(|item| !item.instances.is_empty()) {
+            self.materials[item.material].draw(rp, shared_groups, &item.mesh, &item.instances);
+        }
+    }
+
+    // Draws into the G-buffer pass begun by `DeferredRenderer::begin_gbuffer_pass`
+    pub fn draw_gbuffer<'a>(&'a self, rp: &mut RenderPass<'a>, shared_groups: &[&'a BindGroup]) {
+        for item in self.items.iter().filter(|item| !item.instances.is_empty()) {
+            self.materials[item.material].draw_gbuffer(rp, shared_groups, &item.mesh, &item.instances);
+        }
+    }
+
+    // Every item casts shadows and occludes in SSAO
+    pub fn casters(&self) -> Vec<&dyn ShadowCaster> {
+        self.items.iter().map(|item| item as &dyn ShadowCaster).collect()
+    }
+}
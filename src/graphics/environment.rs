@@ -0,0 +1,194 @@
+use std::rc::Rc;
+
+use wgpu::{util::DeviceExt, Device, Queue};
+
+use super::cubemap::IBL_WORKGROUP;
+use super::{ComputePass, Shader, Texture2D, TextureCube};
+
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+const PREFILTERED_MIPS: u32 = 5;
+const PREFILTER_SAMPLES: u32 = 512;
+const BRDF_LUT_SIZE: u32 = 256;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct FilterParams {
+    roughness: f32,
+    source_size: f32,
+    sample_count: u32,
+    _padding: u32,
+}
+
+// Image based lighting data for the PBR and lit shaders, set with `Lights::set_environment`.
+// Prefiltered mip i holds the radiance for roughness i / (prefiltered_mips - 1).
+// The cube is shared so it can also be drawn as a `Background::Skybox`.
+pub struct Environment {
+    pub cube: Rc<TextureCube>,
+    pub irradiance: TextureCube,
+    pub prefiltered: TextureCube,
+    pub brdf_lut: Texture2D,
+    pub prefiltered_mips: u32,
+}
+
+impl Environment {
+    // Loads an equirectangular image (usually .hdr or .exr), see `from_equirectangular_texture`
+    pub fn from_equirectangular(device: &Device, queue: &Queue, file: &str, face_size: u32) -> Self {
+        let equirect = Texture2D::new(device, queue, file);
+        Environment::from_equirectangular_texture(device, queue, &equirect, face_size)
+    }
+
+    // Converts an equirectangular (latitude/longitude) texture to a cubemap with `face_size` texels,
+    // at least one, see `TextureCube::from_equirectangular_texture`, and precomputes the irradiance,
+    // prefiltered specular and BRDF lookup textures from it on the GPU
+    pub fn from_equirectangular_texture(device: &Device, queue: &Queue, equirect: &Texture2D, face_size: u32) -> Self {
+        let cube = TextureCube::from_equirectangular_texture(device, queue, equirect, face_size);
+        let face_size = cube.texture.width();
+
+        let shader = Shader::new("resources/ibl.wgsl", device);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("IBL Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let irradiance = TextureCube::storage(device, "Irradiance Cube", IRRADIANCE_SIZE, 1);
+        let prefiltered = TextureCube::storage(device, "Prefiltered Cube", PREFILTERED_SIZE, PREFILTERED_MIPS);
+        let brdf_lut = create_brdf_lut(device);
+
+        // One layout per set of bindings in resources/ibl.wgsl the entry points use
+        let create_pass = |entry_point, entries: &[wgpu::BindGroupLayoutEntry]| {
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(entry_point),
+                entries,
+            });
+            ComputePass::new(device, &shader, entry_point, &[&layout], IBL_WORKGROUP)
+        };
+        let filter_entries = [
+            ComputePass::sampler_entry(0),
+            ComputePass::texture_entry(2, wgpu::TextureViewDimension::Cube, wgpu::TextureSampleType::Float { filterable: true }),
+            ComputePass::storage_texture_entry(4, wgpu::TextureViewDimension::D2Array, TextureCube::STORAGE_FORMAT),
+            ComputePass::uniform_entry(6),
+        ];
+        let irradiance_pass = create_pass("irradiance", &filter_entries);
+        let prefilter_pass = create_pass("prefilter", &filter_entries);
+        let brdf_lut_pass = create_pass("brdf_lut", &[
+            ComputePass::storage_texture_entry(5, wgpu::TextureViewDimension::D2, wgpu::TextureFormat::Rgba16Float),
+        ]);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("IBL Encoder"),
+        });
+
+        let filter_params = |roughness: f32, sample_count: u32| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("IBL Filter Params"),
+                contents: bytemuck::cast_slice(&[FilterParams {
+                    roughness,
+                    source_size: face_size as f32,
+                    sample_count,
+                    _padding: 0,
+                }]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            })
+        };
+
+        let params = filter_params(0.0, 0);
+        dispatch(device, &mut encoder, &irradiance_pass, (IRRADIANCE_SIZE, IRRADIANCE_SIZE, 6), &[
+            wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::Sampler(&sampler) },
+            wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&cube.view) },
+            wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::TextureView(&irradiance.mip_view(0)) },
+            wgpu::BindGroupEntry { binding: 6, resource: params.as_entire_binding() },
+        ]);
+
+        for mip in 0..PREFILTERED_MIPS {
+            let size = PREFILTERED_SIZE >> mip;
+            let params = filter_params(mip as f32 / (PREFILTERED_MIPS - 1) as f32, PREFILTER_SAMPLES);
+            dispatch(device, &mut encoder, &prefilter_pass, (size, size, 6), &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::Sampler(&sampler) },
+                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&cube.view) },
+                wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::TextureView(&prefiltered.mip_view(mip)) },
+                wgpu::BindGroupEntry { binding: 6, resource: params.as_entire_binding() },
+            ]);
+        }
+
+        dispatch(device, &mut encoder, &brdf_lut_pass, (BRDF_LUT_SIZE, BRDF_LUT_SIZE, 1), &[
+            wgpu::BindGroupEntry { binding: 5, resource: wgpu::BindingResource::TextureView(&brdf_lut.view) },
+        ]);
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        Self {
+            cube: Rc::new(cube),
+            irradiance,
+            prefiltered,
+            brdf_lut,
+            prefiltered_mips: PREFILTERED_MIPS,
+        }
+    }
+
+    // Black environment that adds no light, wgpu zero initializes the textures
+    pub fn empty(device: &Device) -> Self {
+        Self {
+            cube: Rc::new(TextureCube::storage(device, "Environment Cube", 1, 1)),
+            irradiance: TextureCube::storage(device, "Irradiance Cube", 1, 1),
+            prefiltered: TextureCube::storage(device, "Prefiltered Cube", 1, 1),
+            brdf_lut: create_brdf_lut(device),
+            prefiltered_mips: 1,
+        }
+    }
+}
+
+fn create_sampler(device: &Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Environment Sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+fn create_brdf_lut(device: &Device) -> Texture2D {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("BRDF LUT"),
+        size: wgpu::Extent3d {
+            width: BRDF_LUT_SIZE,
+            height: BRDF_LUT_SIZE,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+        view_formats: &[],
+    });
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    Texture2D {
+        texture,
+        view,
+        sampler: create_sampler(device),
+    }
+}
+
+// Runs `pass` once per texel, `entries` are the bindings of its layout
+fn dispatch(device: &Device,
+    encoder: &mut wgpu::CommandEncoder,
+    pass: &ComputePass,
+    size: (u32, u32, u32),
+    entries: &[wgpu::BindGroupEntry]) {
+
+    let bind_group = pass.bind_group(device, 0, entries);
+    pass.dispatch_size(encoder, &[&bind_group], size);
+}
@@ -4,16 +4,27 @@ use imgui::*;
 use imgui_wgpu::{Renderer, RendererConfig};
 use imgui_winit_support::WinitPlatform;
 
-use wgpu::{Device, Queue, SurfaceConfiguration, RenderPass};
+use wgpu::{Device, Queue, RenderPass};
 use winit::event::Event;
 use winit::window::Window;
 
 use std::time::Duration;
 
+use super::TargetFormat;
+
 const DOCKSPACE_ROUNDING: f32 = 0.0;
 const DOCKSPACE_BORDER: f32 = 0.0;
 const DOCKSPACE_PADDING: [f32; 2] = [0.0, 0.0];
 
+// What the GUI needs to draw a frame, besides the pass it draws into
+pub struct GuiFrame<'a> {
+    pub dt: f32,
+    pub window: &'a Window,
+    pub device: &'a Device,
+    pub queue: &'a Queue,
+    pub dock_size: [f32; 2],
+}
+
 pub struct GUI {
     pub imgui: Context,
     pub platform: WinitPlatform,
@@ -24,7 +35,7 @@ impl GUI {
     pub fn new(window: &Window, 
         device: &Device, 
         queue: &Queue, 
-        config: &SurfaceConfiguration) -> Self {
+        target: &TargetFormat) -> Self {
 
         let mut imgui = imgui::Context::create();
         imgui.io_mut().config_flags = ConfigFlags::DOCKING_ENABLE | ConfigFlags::VIEWPORTS_ENABLE;
@@ -45,12 +56,7 @@ impl GUI {
             })
         }]);
 
-        let renderer_config = RendererConfig {
-            texture_format: config.format,
-            ..Default::default()
-        };
-
-        let renderer = Renderer::new(&mut imgui, &device, &queue, renderer_config);
+        let renderer = Renderer::new(&mut imgui, &device, &queue, GUI::renderer_config(target));
 
         Self {
             imgui,
@@ -59,13 +65,17 @@ impl GUI {
         }
     }
 
-    pub fn render<'a>(&'a mut self, 
-        dt: f32, 
-        window: &Window, 
-        device: &Device, 
-        queue: &Queue, 
-        rp: &mut RenderPass<'a>, 
-        dock_size: [f32; 2]) {
+    fn renderer_config(target: &TargetFormat) -> RendererConfig<'static> {
+        RendererConfig {
+            texture_format: target.color,
+            depth_format: target.depth,
+            sample_count: target.sample_count,
+            ..Default::default()
+        }
+    }
+
+    pub fn render<'a>(&'a mut self, frame: GuiFrame, rp: &mut RenderPass<'a>, panels: impl FnOnce(&Ui)) {
+        let GuiFrame { dt, window, device, queue, dock_size } = frame;
 
         self.imgui.io_mut().update_delta_time(Duration::from_secs_f32(dt));
 
@@ -122,6 +132,9 @@ impl GUI {
                     ui.separator();
                     ui.text("Hello again");
                 });
+
+            // Windows of whoever owns the GUI, e.g. the post processing settings
+            panels(ui);
         }
 
         self.platform.prepare_render(ui, window);
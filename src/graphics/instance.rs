@@ -0,0 +1,300 @@
+use std::ops::Range;
+
+use wgpu::{BufferAddress, Device, Queue};
+
+use super::{Instance, InstanceVertex};
+
+const MIN_CAPACITY: usize = 16;
+const NO_SLOT: u32 = u32::MAX;
+
+// Stays valid for its instance until it is removed, even when other instances move around.
+// Handles of removed instances are rejected, also once their index is reused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InstanceHandle {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Copy, Clone)]
+struct HandleSlot {
+    slot: u32,
+    generation: u32,
+}
+
+// Instance data kept on the CPU and mirrored in a vertex buffer that grows by doubling.
+// Changes are only uploaded, as one dirty range, by `update_buffer`.
+pub struct InstanceBuffer<T: Instance = InstanceVertex> {
+    pub buffer: wgpu::Buffer,
+    capacity: usize,
+    instances: Instances<T>,
+}
+
+impl<T: Instance> InstanceBuffer<T> {
+    pub fn new(device: &Device, instances: &[T]) -> Self {
+        let capacity = instances.len().next_power_of_two().max(MIN_CAPACITY);
+
+        let mut instance_buffer = Self {
+            buffer: InstanceBuffer::<T>::create_buffer(device, capacity),
+            capacity,
+            instances: Instances::new(),
+        };
+        instance_buffer.set_instances(instances);
+        instance_buffer
+    }
+
+    fn create_buffer(device: &Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Buffer"),
+            size: (capacity * std::mem::size_of::<T>()) as BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.data.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn instances(&self) -> &[T] {
+        &self.instances.data
+    }
+
+    pub fn add_instance(&mut self, data: T) -> InstanceHandle {
+        self.instances.add(data)
+    }
+
+    // Swap-removes the instance, the last instance moves into its slot
+    pub fn remove_instance(&mut self, handle: InstanceHandle) -> T {
+        self.instances.remove(handle)
+    }
+
+    pub fn get(&self, handle: InstanceHandle) -> &T {
+        self.instances.get(handle)
+    }
+
+    pub fn set_instance(&mut self, handle: InstanceHandle, data: T) {
+        self.instances.set(handle, data);
+    }
+
+    // Removes every instance, invalidating all handles
+    pub fn clear(&mut self) {
+        self.instances.clear();
+    }
+
+    // Replaces every instance, invalidating all previous handles
+    pub fn set_instances(&mut self, instances: &[T]) -> Vec<InstanceHandle> {
+        self.instances.clear();
+        instances.iter()
+            .map(|data| self.instances.add(*data))
+            .collect()
+    }
+
+    // Grows the GPU buffer if needed, then uploads the dirty range
+    pub fn update_buffer(&mut self, device: &Device, queue: &Queue) {
+        let len = self.instances.data.len();
+        if len > self.capacity {
+            self.capacity = len.next_power_of_two();
+            self.buffer = InstanceBuffer::<T>::create_buffer(device, self.capacity);
+            self.instances.dirty = Some(0..len);
+        }
+
+        let Some(range) = self.instances.dirty.take() else {
+            return;
+        };
+        let range = range.start..range.end.min(len);
+        if range.is_empty() {
+            return;
+        }
+
+        queue.write_buffer(
+            &self.buffer,
+            (range.start * std::mem::size_of::<T>()) as BufferAddress,
+            bytemuck::cast_slice(&self.instances.data[range]));
+    }
+}
+
+// The CPU side of an `InstanceBuffer`: the data packed without gaps, the handle of each slot,
+// the slot and generation of each handle index, and the range not uploaded yet
+struct Instances<T> {
+    data: Vec<T>,
+    slot_handles: Vec<u32>,
+    handles: Vec<HandleSlot>,
+    free_handles: Vec<u32>,
+    dirty: Option<Range<usize>>,
+}
+
+impl<T: Copy> Instances<T> {
+    fn new() -> Self {
+        Self {
+            data: Vec::new(),
+            slot_handles: Vec::new(),
+            handles: Vec::new(),
+            free_handles: Vec::new(),
+            dirty: None,
+        }
+    }
+
+    fn mark_dirty(&mut self, range: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(range.start)..dirty.end.max(range.end),
+            None => range,
+        });
+    }
+
+    fn slot(&self, handle: InstanceHandle) -> usize {
+        let entry = self.handles[handle.index as usize];
+        assert!(entry.slot != NO_SLOT && entry.generation == handle.generation, "Instance handle was removed");
+        entry.slot as usize
+    }
+
+    fn add(&mut self, data: T) -> InstanceHandle {
+        let slot = self.data.len() as u32;
+        let index = match self.free_handles.pop() {
+            Some(index) => {
+                self.handles[index as usize].slot = slot;
+                index
+            },
+            None => {
+                self.handles.push(HandleSlot { slot, generation: 0 });
+                self.handles.len() as u32 - 1
+            },
+        };
+
+        self.data.push(data);
+        self.slot_handles.push(index);
+        self.mark_dirty(slot as usize..slot as usize + 1);
+
+        InstanceHandle { index, generation: self.handles[index as usize].generation }
+    }
+
+    fn remove(&mut self, handle: InstanceHandle) -> T {
+        let slot = self.slot(handle);
+
+        let removed = self.data.swap_remove(slot);
+        self.slot_handles.swap_remove(slot);
+
+        if slot < self.data.len() {
+            let moved = self.slot_handles[slot];
+            self.handles[moved as usize].slot = slot as u32;
+            self.mark_dirty(slot..slot + 1);
+        }
+
+        self.release(handle.index);
+        removed
+    }
+
+    // The index is reused by a later `add` with the next generation
+    fn release(&mut self, index: u32) {
+        let entry = &mut self.handles[index as usize];
+        entry.slot = NO_SLOT;
+        entry.generation += 1;
+        self.free_handles.push(index);
+    }
+
+    fn get(&self, handle: InstanceHandle) -> &T {
+        &self.data[self.slot(handle)]
+    }
+
+    fn set(&mut self, handle: InstanceHandle, data: T) {
+        let slot = self.slot(handle);
+        self.data[slot] = data;
+        self.mark_dirty(slot..slot + 1);
+    }
+
+    fn clear(&mut self) {
+        for index in std::mem::take(&mut self.slot_handles) {
+            self.release(index);
+        }
+        self.data.clear();
+        self.dirty = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handles_follow_swap_removed_instances() {
+        let mut instances = Instances::new();
+        let a = instances.add(1);
+        let b = instances.add(2);
+        let c = instances.add(3);
+
+        // The last instance moves into the removed slot
+        assert_eq!(instances.remove(a), 1);
+        assert_eq!(instances.data, vec![3, 2]);
+        assert_eq!(*instances.get(b), 2);
+        assert_eq!(*instances.get(c), 3);
+
+        instances.set(c, 30);
+        assert_eq!(instances.data, vec![30, 2]);
+        assert_eq!(instances.remove(b), 2);
+        assert_eq!(instances.data, vec![30]);
+        assert_eq!(*instances.get(c), 30);
+    }
+
+    #[test]
+    fn marks_moved_slots_dirty() {
+        let mut instances = Instances::new();
+        let handles: Vec<InstanceHandle> = (0..4).map(|i| instances.add(i)).collect();
+        assert_eq!(instances.dirty.take(), Some(0..4));
+
+        instances.remove(handles[1]);
+        assert_eq!(instances.dirty.take(), Some(1..2));
+
+        // Removing the last instance moves nothing
+        instances.remove(handles[2]);
+        assert_eq!(instances.dirty.take(), None);
+    }
+
+    #[test]
+    fn reused_handle_indices_get_a_new_generation() {
+        let mut instances = Instances::new();
+        let a = instances.add(1);
+        instances.remove(a);
+
+        let b = instances.add(2);
+        assert_eq!(a.index, b.index);
+        assert_ne!(a, b);
+        assert_eq!(*instances.get(b), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Instance handle was removed")]
+    fn rejects_removed_handles() {
+        let mut instances = Instances::new();
+        let a = instances.add(1);
+        instances.add(2);
+        instances.remove(a);
+        instances.get(a);
+    }
+
+    #[test]
+    #[should_panic(expected = "Instance handle was removed")]
+    fn rejects_handles_whose_index_was_reused() {
+        let mut instances = Instances::new();
+        let a = instances.add(1);
+        instances.remove(a);
+        instances.add(2);
+        instances.set(a, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Instance handle was removed")]
+    fn clear_invalidates_handles() {
+        let mut instances = Instances::new();
+        let a = instances.add(1);
+        instances.clear();
+        instances.add(2);
+        instances.get(a);
+    }
+}
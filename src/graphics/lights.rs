@@ -0,0 +1,535 @@
+use std::rc::Rc;
+
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, Device, Queue, TextureView};
+
+use super::{Camera, Environment, LightClusters, Projection, ShadowCaster, ShadowMaps, ShadowSettings, Texture2D, Uniform, CLUSTERS_Z, MAX_SHADOW_LAYERS};
+
+// Lights are uploaded to a fixed size storage buffer, the shaders get it from light_constants.wgsl
+pub const MAX_LIGHTS: usize = 1024;
+
+const KIND_DIRECTIONAL: f32 = 0.0;
+const KIND_POINT: f32 = 1.0;
+const KIND_SPOT: f32 = 2.0;
+
+// Point and spot lights fade out smoothly and reach zero at `range`.
+// Spot cone angles are half angles in radians, full intensity inside `inner_angle`.
+#[derive(Copy, Clone, Debug)]
+pub enum Light {
+    Directional {
+        direction: Vector3<f32>,
+        color: [f32; 3],
+        intensity: f32,
+    },
+    Point {
+        position: Vector3<f32>,
+        color: [f32; 3],
+        intensity: f32,
+        range: f32,
+    },
+    Spot {
+        position: Vector3<f32>,
+        direction: Vector3<f32>,
+        color: [f32; 3],
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+impl Light {
+    fn to_raw(self) -> LightRaw {
+        match self {
+            Light::Directional { direction, color, intensity } => LightRaw {
+                position: [0.0, 0.0, 0.0, KIND_DIRECTIONAL],
+                direction: direction.normalize().extend(0.0).into(),
+                color: [color[0], color[1], color[2], intensity],
+                cone: [0.0; 4],
+            },
+            Light::Point { position, color, intensity, range } => LightRaw {
+                position: position.extend(KIND_POINT).into(),
+                direction: [0.0, 0.0, 0.0, range],
+                color: [color[0], color[1], color[2], intensity],
+                cone: [0.0; 4],
+            },
+            Light::Spot { position, direction, color, intensity, range, inner_angle, outer_angle } => LightRaw {
+                position: position.extend(KIND_SPOT).into(),
+                direction: direction.normalize().extend(range).into(),
+                color: [color[0], color[1], color[2], intensity],
+                cone: [inner_angle.cos(), outer_angle.cos(), 0.0, 0.0],
+            },
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LightId(usize);
+
+// position.w is the kind, direction.w the range, color.w the intensity,
+// cone.xy the cosines of the inner and outer spot angles, cone.zw the first shadow layer and layer count
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightRaw {
+    position: [f32; 4],
+    direction: [f32; 4],
+    color: [f32; 4],
+    cone: [f32; 4],
+}
+
+// ambient.w is the environment intensity, count.y the highest prefiltered environment mip,
+// count.z 1 when screen space occlusion is bound,
+// shadow holds the depth bias, normal bias, PCF radius and shadow map texel size,
+// cluster the scale and bias turning view depth into a cluster slice and w 1 when that depth is logarithmic
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightsUniform {
+    ambient: [f32; 4],
+    count: [u32; 4],
+    shadow: [f32; 4],
+    shadow_matrices: [[[f32; 4]; 4]; MAX_SHADOW_LAYERS],
+    view: [[f32; 4]; 4],
+    projection: [[f32; 4]; 4],
+    inverse_projection: [[f32; 4]; 4],
+    cluster: [f32; 4],
+}
+
+// Owns bind group 1 of lit shaders: the lights uniform at binding 0, the environment's
+// irradiance, prefiltered and BRDF LUT textures and their sampler at 1 to 4,
+// then the shadow map array and its comparison sampler at 5 and 6, the screen space occlusion at 7,
+// and the light list with the cluster light counts and indices at 8 to 10, see `LightClusters`.
+// Directional and spot lights can cast shadows, which are assigned shadow map layers in slot order.
+pub struct Lights {
+    slots: Vec<Option<Light>>,
+    shadowed: Vec<bool>,
+    ambient: [f32; 3],
+    environment: Environment,
+    environment_intensity: f32,
+    shadows: ShadowMaps,
+    clusters: LightClusters,
+    occlusion: Option<Rc<Texture2D>>,
+    // Bound while there is no occlusion, the shaders don't sample it
+    no_occlusion: Texture2D,
+    dirty: bool,
+    // The camera the shadow matrices were last fitted to, without jitter
+    shadow_camera: Option<Matrix4<f32>>,
+    pub uniform: Uniform<LightsUniform>,
+    pub bind_layout: BindGroupLayout,
+    pub bind_group: BindGroup,
+}
+
+impl Lights {
+    pub fn new(device: &Device) -> Self {
+        let uniform = Uniform::new(device,
+            "Lights Uniform Buffer",
+            wgpu::ShaderStages::FRAGMENT,
+            bytemuck::Zeroable::zeroed());
+
+        let environment = Environment::empty(device);
+        let shadows = ShadowMaps::new(device, ShadowSettings::default());
+        let clusters = LightClusters::new(device, &uniform.buffer);
+        let no_occlusion = Lights::create_no_occlusion(device);
+        let bind_layout = Lights::create_layout(device);
+        let bind_group = Lights::create_bind_group(device, &bind_layout, &uniform, &environment, &shadows, &clusters, &no_occlusion.view);
+
+        Self {
+            slots: vec![None; MAX_LIGHTS],
+            shadowed: vec![false; MAX_LIGHTS],
+            ambient: [0.1, 0.1, 0.1],
+            environment,
+            environment_intensity: 1.0,
+            shadows,
+            clusters,
+            occlusion: None,
+            no_occlusion,
+            dirty: true,
+            shadow_camera: None,
+            uniform,
+            bind_layout,
+            bind_group,
+        }
+    }
+
+    fn create_layout(device: &Device) -> BindGroupLayout {
+        let texture = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Lights Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<LightsUniform>() as u64),
+                    },
+                    count: None,
+                },
+                texture(1, wgpu::TextureViewDimension::Cube),
+                texture(2, wgpu::TextureViewDimension::Cube),
+                texture(3, wgpu::TextureViewDimension::D2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                texture(7, wgpu::TextureViewDimension::D2),
+                storage(8),
+                storage(9),
+                storage(10),
+            ],
+        })
+    }
+
+    fn create_bind_group(device: &Device,
+        layout: &BindGroupLayout,
+        uniform: &Uniform<LightsUniform>,
+        environment: &Environment,
+        shadows: &ShadowMaps,
+        clusters: &LightClusters,
+        occlusion: &TextureView) -> BindGroup {
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Lights Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&environment.irradiance.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&environment.prefiltered.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&environment.brdf_lut.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&environment.prefiltered.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&shadows.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::Sampler(&shadows.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::TextureView(occlusion),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: clusters.light_list.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: clusters.light_counts.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: clusters.light_indices.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn create_no_occlusion(device: &Device) -> Texture2D {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("No Occlusion"),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        Texture2D {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor::default()),
+            texture,
+        }
+    }
+
+    fn occlusion_view(&self) -> &TextureView {
+        match &self.occlusion {
+            Some(occlusion) => &occlusion.view,
+            None => &self.no_occlusion.view,
+        }
+    }
+    // Returns None when MAX_LIGHTS lights already exist
+    pub fn add(&mut self, light: Light) -> Option<LightId> {
+        let index = self.slots.iter().position(|slot| slot.is_none())?;
+        self.slots[index] = Some(light);
+        self.shadowed[index] = false;
+        self.dirty = true;
+        Some(LightId(index))
+    }
+
+    pub fn remove(&mut self, id: LightId) -> Option<Light> {
+        self.dirty = true;
+        self.slots[id.0].take()
+    }
+
+    pub fn get(&self, id: LightId) -> Option<&Light> {
+        self.slots[id.0].as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: LightId) -> Option<&mut Light> {
+        self.dirty = true;
+        self.slots[id.0].as_mut()
+    }
+
+    pub fn set(&mut self, id: LightId, light: Light) {
+        self.slots[id.0] = Some(light);
+        self.dirty = true;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LightId, &Light)> {
+        self.slots.iter()
+            .enumerate()
+            .filter_map(|(i, slot)| slot.as_ref().map(|light| (LightId(i), light)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn ambient(&self) -> [f32; 3] {
+        self.ambient
+    }
+
+    pub fn set_ambient(&mut self, ambient: [f32; 3]) {
+        self.ambient = ambient;
+        self.dirty = true;
+    }
+
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    // Replaces the image based lighting, the bind group is recreated but the layout stays valid
+    pub fn set_environment(&mut self, device: &Device, environment: Environment) {
+        self.bind_group = Lights::create_bind_group(device, &self.bind_layout, &self.uniform, &environment, &self.shadows, &self.clusters, self.occlusion_view());
+        self.environment = environment;
+        self.dirty = true;
+    }
+
+    // Point lights never cast shadows
+    pub fn set_shadows(&mut self, id: LightId, enabled: bool) {
+        self.shadowed[id.0] = enabled;
+        self.dirty = true;
+    }
+
+    pub fn casts_shadows(&self, id: LightId) -> bool {
+        self.shadowed[id.0]
+    }
+
+    pub fn shadow_settings(&self) -> &ShadowSettings {
+        self.shadows.settings()
+    }
+
+    // Recreates the shadow maps, so changing the resolution or cascades is not free
+    pub fn set_shadow_settings(&mut self, device: &Device, settings: ShadowSettings) {
+        self.shadows = ShadowMaps::new(device, settings);
+        self.bind_group = Lights::create_bind_group(device, &self.bind_layout, &self.uniform, &self.environment, &self.shadows, &self.clusters, self.occlusion_view());
+        self.dirty = true;
+    }
+
+    pub fn occlusion(&self) -> Option<&Rc<Texture2D>> {
+        self.occlusion.as_ref()
+    }
+
+    // Ambient occlusion the lit shaders multiply their ambient light by, e.g. from `Ssao`.
+    // It is sampled at the fragment's screen position, so it must be the size of the target drawn into.
+    pub fn set_occlusion(&mut self, device: &Device, occlusion: Option<Rc<Texture2D>>) {
+        self.occlusion = occlusion;
+        self.bind_group = Lights::create_bind_group(device, &self.bind_layout, &self.uniform, &self.environment, &self.shadows, &self.clusters, self.occlusion_view());
+        self.dirty = true;
+    }
+
+    // Shadow map layers a light needs, directional cascades follow the camera
+    fn shadow_layers(&self, light: &Light, camera: &Camera) -> Vec<Matrix4<f32>> {
+        match *light {
+            Light::Directional { direction, .. } => self.shadows.directional_matrices(direction, camera),
+            Light::Spot { position, direction, range, outer_angle, .. } => 
+                vec![ShadowMaps::spot_matrix(position, direction, outer_angle, range)],
+            Light::Point { .. } => Vec::new(),
+        }
+    }
+
+    fn shadow_layer_count(&self, light: &Light) -> usize {
+        match light {
+            Light::Directional { .. } => self.shadows.cascade_count(),
+            Light::Spot { .. } => 1,
+            Light::Point { .. } => 0,
+        }
+    }
+
+    // The first shadow map layer and layer count of each shadowed light, in slot order.
+    // Lights that don't fit in the remaining layers get none.
+    fn shadow_assignments(&self) -> Vec<(LightId, usize, usize)> {
+        let mut assignments = Vec::new();
+        let mut taken = 0;
+        for (id, light) in self.iter().filter(|(id, _)| self.shadowed[id.0]) {
+            let count = self.shadow_layer_count(light);
+            if count > 0 && taken + count <= MAX_SHADOW_LAYERS {
+                assignments.push((id, taken, count));
+                taken += count;
+            }
+        }
+        assignments
+    }
+
+    // Assigns the lights to the clusters of the camera given to `update_buffer`, call after it and before the lit passes
+    pub fn cull_lights(&mut self, device: &Device, encoder: &mut CommandEncoder) {
+        self.clusters.cull(device, encoder);
+    }
+
+    // Draws the casters into the shadow maps, call after `update_buffer` and before the lit passes
+    pub fn render_shadows(&mut self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, casters: &[&dyn ShadowCaster]) {
+        self.shadows.render(device, queue, encoder, casters);
+    }
+
+    pub fn environment_intensity(&self) -> f32 {
+        self.environment_intensity
+    }
+
+    pub fn set_environment_intensity(&mut self, intensity: f32) {
+        self.environment_intensity = intensity;
+        self.dirty = true;
+    }
+
+    // The scale, bias and kind of the view depth to cluster slice mapping, see `cluster_slice` in resources/lights.wgsl
+    fn cluster_mapping(camera: &Camera) -> [f32; 4] {
+        let slices = CLUSTERS_Z as f32;
+        let (near, far) = (camera.near(), camera.far());
+        match camera.projection() {
+            Projection::Perspective { .. } => {
+                let scale = slices / (far / near).ln();
+                [scale, -near.ln() * scale, 0.0, 1.0]
+            },
+            Projection::Orthographic => {
+                let scale = slices / (far - near);
+                [scale, -near * scale, 0.0, 0.0]
+            },
+        }
+    }
+
+    // The light list is only re-uploaded after the lights changed, and the shadow matrices are
+    // only refitted when that happened or the camera moved
+    pub fn update_buffer(&mut self, queue: &Queue, camera: &Camera) {
+        let assignments = self.shadow_assignments();
+        let view_projection = camera.projection_matrix() * camera.view();
+        let camera_moved = self.shadow_camera != Some(view_projection);
+
+        if self.dirty {
+            let lights: Vec<LightRaw> = self.iter()
+                .map(|(id, light)| {
+                    let mut raw = light.to_raw();
+                    if let Some((_, first, count)) = assignments.iter().find(|(shadowed, _, _)| *shadowed == id) {
+                        raw.cone[2] = *first as f32;
+                        raw.cone[3] = *count as f32;
+                    }
+                    raw
+                })
+                .collect();
+            if !lights.is_empty() {
+                queue.write_buffer(&self.clusters.light_list, 0, bytemuck::cast_slice(&lights));
+            }
+
+            let settings = self.shadows.settings();
+            let shadow = [settings.depth_bias,
+                settings.normal_bias,
+                settings.pcf_radius as f32,
+                1.0 / settings.resolution as f32];
+            let count = [lights.len() as u32, self.environment.prefiltered_mips - 1, self.occlusion.is_some() as u32, 0];
+            let ambient = [self.ambient[0], self.ambient[1], self.ambient[2], self.environment_intensity];
+
+            let uniform = self.uniform.get_mut();
+            uniform.ambient = ambient;
+            uniform.count = count;
+            uniform.shadow = shadow;
+        }
+
+        if self.dirty || (camera_moved && !assignments.is_empty()) {
+            let mut matrices = Vec::new();
+            for (id, _, _) in &assignments {
+                let light = self.get(*id).expect("Shadowed light exists");
+                matrices.extend(self.shadow_layers(light, camera));
+            }
+
+            let uniform = self.uniform.get_mut();
+            for (i, matrix) in matrices.iter().enumerate() {
+                uniform.shadow_matrices[i] = (*matrix).into();
+            }
+            self.shadows.set_matrices(matrices);
+            self.shadow_camera = Some(view_projection);
+        }
+        self.dirty = false;
+
+        // The clusters follow the camera, jitter included so they match what the lit shaders draw
+        let projection = Matrix4::from_translation(camera.jitter().extend(0.0)) * camera.projection_matrix();
+        let uniform = self.uniform.get_mut();
+        uniform.view = camera.view().into();
+        uniform.projection = projection.into();
+        uniform.inverse_projection = projection.invert().unwrap_or_else(Matrix4::identity).into();
+        uniform.cluster = Lights::cluster_mapping(camera);
+
+        self.uniform.update_buffer(queue);
+    }
+}
@@ -0,0 +1,224 @@
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use wgpu::{BindGroup, BindGroupLayout, Device, Queue, RenderPass};
+
+use super::{Renderable, Shader, TargetFormat, Texture2D, Vertex, Instance, InstanceVertex, InstanceBuffer, Mesh, Uniform, GBuffer};
+
+// Uniform block for materials that only need a tint
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ColorParams {
+    pub color: [f32; 4],
+}
+
+// Uniform block for resources/lit.wgsl, `blinn` picks Blinn-Phong (1) or Phong (0) specular
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PhongParams {
+    pub diffuse: [f32; 4],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub blinn: u32,
+    pub _padding: [u32; 3],
+}
+
+// A shader, its textures and a typed uniform block `U`.
+// The material's bind groups come after the shared groups (camera, ...) given at creation:
+// one group with `U` at binding 0, then one group with two bindings per texture (texture, sampler).
+// The pipeline expects `Vertex` data plus instances of type `I`.
+// Shaders with an `fs_gbuffer` entry point can also draw into the deferred path's G-buffer.
+pub struct Material<U: bytemuck::Pod, I: Instance = InstanceVertex> {
+    pub renderable: Renderable,
+    gbuffer_pipeline: Option<wgpu::RenderPipeline>,
+    params: Uniform<U>,
+    pub texture_layout: BindGroupLayout,
+    textures: Vec<Rc<Texture2D>>,
+    texture_group: Option<BindGroup>,
+    instance_type: PhantomData<I>,
+}
+
+impl<U: bytemuck::Pod, I: Instance> Material<U, I> {
+    pub fn new(device: &Device,
+        target: &TargetFormat,
+        shader: &Shader,
+        shared_layouts: &[&BindGroupLayout],
+        params: U,
+        textures: Vec<Rc<Texture2D>>) -> Self {
+
+        let params = Uniform::new(device, 
+            "Material Uniform Buffer", 
+            wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT, 
+            params);
+
+        let texture_layout = Material::<U, I>::create_layout(device, textures.len());
+
+        let mut bind_layouts = shared_layouts.to_vec();
+        bind_layouts.push(&params.bind_layout);
+        bind_layouts.push(&texture_layout);
+
+        let renderable = Renderable::new(
+            device,
+            target,
+            shader,
+            &[Vertex::layout(), I::layout()],
+            &bind_layouts);
+
+        Self {
+            renderable,
+            gbuffer_pipeline: None,
+            params,
+            texture_layout,
+            textures,
+            texture_group: None,
+            instance_type: PhantomData,
+        }
+    }
+
+    fn create_layout(device: &Device, texture_count: usize) -> BindGroupLayout {
+        let mut entries = Vec::with_capacity(texture_count * 2);
+
+        for i in 0..texture_count as u32 {
+            entries.push(wgpu::BindGroupLayoutEntry { // texture entry
+                binding: i * 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            });
+            entries.push(wgpu::BindGroupLayoutEntry { // sampler entry
+                binding: i * 2 + 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Material Texture Bind Group Layout"),
+            entries: &entries,
+        })
+    }
+
+    fn create_texture_group(&self, device: &Device) -> BindGroup {
+        let mut entries = Vec::with_capacity(self.textures.len() * 2);
+
+        for (i, texture) in self.textures.iter().enumerate() {
+            let i = i as u32;
+            entries.push(wgpu::BindGroupEntry {
+                binding: i * 2,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: i * 2 + 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Material Texture Bind Group"),
+            layout: &self.texture_layout,
+            entries: &entries,
+        })
+    }
+
+    pub fn set_target(&mut self, device: &Device, target: &TargetFormat) {
+        self.renderable.set_target(device, target);
+    }
+
+    pub fn params(&self) -> &U {
+        self.params.get()
+    }
+
+    pub fn params_mut(&mut self) -> &mut U {
+        self.params.get_mut()
+    }
+
+    pub fn set_params(&mut self, params: U) {
+        self.params.set(params);
+    }
+
+    pub fn texture(&self, slot: usize) -> &Rc<Texture2D> {
+        &self.textures[slot]
+    }
+
+    // Swapping a texture drops the cached bind group, it is rebuilt by the next `prepare`
+    pub fn set_texture(&mut self, slot: usize, texture: Rc<Texture2D>) {
+        self.textures[slot] = texture;
+        self.texture_group = None;
+    }
+
+    // Uploads changed parameters and (re)creates the texture bind group, call once per frame before drawing
+    pub fn prepare(&mut self, device: &Device, queue: &Queue) {
+        self.params.update_buffer(queue);
+
+        if self.texture_group.is_none() {
+            self.texture_group = Some(self.create_texture_group(device));
+        }
+    }
+
+    pub fn texture_group(&self) -> &BindGroup {
+        self.texture_group.as_ref()
+            .expect("Material::prepare must be called before the material is bound")
+    }
+
+    // Binds the material's groups right after `first_group` shared groups
+    pub fn bind<'a>(&'a self, rp: &mut RenderPass<'a>, first_group: u32) {
+        rp.set_bind_group(first_group, &self.params.bind_group, &[]);
+        rp.set_bind_group(first_group + 1, self.texture_group(), &[]);
+    }
+
+    // Builds the pipeline `draw_gbuffer` uses, the shader must have an `fs_gbuffer` entry point
+    pub fn enable_deferred(&mut self, device: &Device) {
+        if self.gbuffer_pipeline.is_none() {
+            self.gbuffer_pipeline = Some(self.renderable.create_variant(device,
+                "fs_gbuffer",
+                &GBuffer::color_targets(),
+                GBuffer::depth_stencil(),
+                wgpu::MultisampleState::default()));
+        }
+    }
+
+    pub fn draw<'a>(&'a self,
+        rp: &mut RenderPass<'a>,
+        shared_groups: &[&'a BindGroup],
+        mesh: &'a Mesh,
+        instances: &'a InstanceBuffer<I>) {
+
+        self.draw_with(rp, &self.renderable.pipeline, shared_groups, mesh, instances);
+    }
+
+    // Draws into the G-buffer pass begun by `DeferredRenderer::begin_gbuffer_pass`,
+    // with the same shared groups as `draw`
+    pub fn draw_gbuffer<'a>(&'a self,
+        rp: &mut RenderPass<'a>,
+        shared_groups: &[&'a BindGroup],
+        mesh: &'a Mesh,
+        instances: &'a InstanceBuffer<I>) {
+
+        let pipeline = self.gbuffer_pipeline.as_ref()
+            .expect("Material::enable_deferred must be called before drawing into a G-buffer");
+        self.draw_with(rp, pipeline, shared_groups, mesh, instances);
+    }
+
+    fn draw_with<'a>(&'a self,
+        rp: &mut RenderPass<'a>,
+        pipeline: &'a wgpu::RenderPipeline,
+        shared_groups: &[&'a BindGroup],
+        mesh: &'a Mesh,
+        instances: &'a InstanceBuffer<I>) {
+
+        rp.set_pipeline(pipeline);
+        for (i, group) in shared_groups.iter().enumerate() {
+            rp.set_bind_group(i as u32, group, &[]);
+        }
+        self.bind(rp, shared_groups.len() as u32);
+        rp.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        rp.set_vertex_buffer(1, instances.buffer.slice(..));
+        rp.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        rp.draw_indexed(0..mesh.num_indices, 0, 0..instances.len() as u32);
+    }
+}
@@ -2,7 +2,13 @@ mod shapes;
 
 mod vertex;
 pub use self::vertex::Vertex;
+pub use self::vertex::Instance;
+pub use self::vertex::ModelInstance;
 pub use self::vertex::InstanceVertex;
+pub use self::vertex::ColorInstance;
+pub use self::vertex::LayerInstance;
+pub use self::vertex::NormalInstance;
+pub use self::vertex::SpriteInstance;
 
 mod state;
 pub use self::state::State;
@@ -10,15 +16,141 @@ pub use self::state::State;
 mod shader;
 pub use self::shader::Shader;
 
+mod compute;
+pub use self::compute::ComputePass;
+pub use self::compute::Readback;
+
+mod targets;
+pub use self::targets::FrameTargets;
+pub use self::targets::TargetFormat;
+pub use self::targets::DEPTH_FORMAT;
+
+mod render_target;
+pub use self::render_target::RenderTarget;
+pub use self::render_target::RenderTargets;
+pub use self::render_target::RenderTargetId;
+
+mod render_graph;
+pub use self::render_graph::RenderGraph;
+pub use self::render_graph::GraphResource;
+pub use self::render_graph::GraphResources;
+pub use self::render_graph::PassId;
+
+mod compressed;
+
 mod texture;
 pub use self::texture::Texture2D;
 
+mod cubemap;
+pub use self::cubemap::TextureCube;
+
+mod texture_array;
+pub use self::texture_array::Texture2DArray;
+
+mod atlas;
+pub use self::atlas::TextureAtlas;
+pub use self::atlas::AtlasRegion;
+
+mod uniform;
+pub use self::uniform::Uniform;
+pub use self::uniform::UniformArray;
+
+mod mesh;
+pub use self::mesh::Mesh;
+
+mod instance;
+pub use self::instance::InstanceBuffer;
+pub use self::instance::InstanceHandle;
+
+mod scene;
+pub use self::scene::SceneGraph;
+pub use self::scene::NodeId;
+pub use self::scene::Transform;
+
 mod renderable;
 pub use self::renderable::Index;
 pub use self::renderable::InstanceIndex;
+pub use self::renderable::Renderable;
+
+mod material;
+pub use self::material::Material;
+pub use self::material::ColorParams;
+pub use self::material::PhongParams;
+
+mod draw_list;
+pub use self::draw_list::DrawList;
+pub use self::draw_list::DrawItemId;
+pub use self::draw_list::DrawInstance;
+pub use self::draw_list::LitMaterial;
+pub use self::draw_list::MaterialId;
+
+mod pbr;
+pub use self::pbr::PbrMaterial;
+pub use self::pbr::PbrParams;
+pub use self::pbr::PbrTextures;
+
+mod deferred;
+pub use self::deferred::DeferredRenderer;
+pub use self::deferred::GBuffer;
+pub use self::deferred::RenderPath;
+pub use self::deferred::GBUFFER_FORMATS;
+
+mod environment;
+pub use self::environment::Environment;
+
+mod shadows;
+pub use self::shadows::ShadowMaps;
+pub use self::shadows::ShadowSettings;
+pub use self::shadows::ShadowCaster;
+pub use self::shadows::MAX_SHADOW_LAYERS;
+pub use self::shadows::MAX_CASCADES;
+
+mod clusters;
+pub use self::clusters::LightClusters;
+pub use self::clusters::CLUSTERS_X;
+pub use self::clusters::CLUSTERS_Y;
+pub use self::clusters::CLUSTERS_Z;
+pub use self::clusters::MAX_CLUSTER_LIGHTS;
+
+mod lights;
+pub use self::lights::Lights;
+pub use self::lights::Light;
+pub use self::lights::LightId;
+pub use self::lights::MAX_LIGHTS;
+
+mod ssao;
+pub use self::ssao::Ssao;
+pub use self::ssao::SsaoSettings;
+pub use self::ssao::MAX_SSAO_SAMPLES;
+
+mod tonemap;
+pub use self::tonemap::ToneMapper;
+pub use self::tonemap::ToneMapping;
+pub use self::tonemap::ToneMapSettings;
+pub use self::tonemap::HDR_FORMAT;
+
+mod antialiasing;
+pub use self::antialiasing::AntiAliasing;
+pub use self::antialiasing::TemporalAA;
+
+mod bloom;
+pub use self::bloom::Bloom;
+pub use self::bloom::BloomSettings;
+
+mod post;
+pub use self::post::PostStack;
+pub use self::post::PostEffect;
+pub use self::post::PostParam;
+pub use self::post::MAX_POST_PARAMS;
 
 mod camera;
 pub use self::camera::Camera;
+pub use self::camera::Projection;
+
+mod background;
+pub use self::background::Background;
+pub use self::background::BackgroundRenderer;
 
 mod gui;
-pub use self::gui::GUI;
\ No newline at end of file
+pub use self::gui::GUI;
+pub use self::gui::GuiFrame;
\ No newline at end of file
@@ -0,0 +1,83 @@
+use std::rc::Rc;
+
+use wgpu::{BindGroupLayout, Device, Queue};
+
+use super::{Material, NormalInstance, Shader, TargetFormat, Texture2D};
+
+// glTF metallic-roughness factors, multiplied with the matching textures
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PbrParams {
+    pub base_color: [f32; 4],
+    pub emissive: [f32; 3],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    pub _padding: f32,
+}
+
+impl Default for PbrParams {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            emissive: [0.0, 0.0, 0.0],
+            metallic: 1.0,
+            roughness: 1.0,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            _padding: 0.0,
+        }
+    }
+}
+
+// Missing textures are replaced by 1x1 defaults that leave the factors unchanged.
+// As in glTF, metallic is read from the blue channel and roughness from the green channel,
+// occlusion from the red channel. Base color and emissive are sRGB, the others linear.
+#[derive(Default)]
+pub struct PbrTextures {
+    pub base_color: Option<Rc<Texture2D>>,
+    pub metallic_roughness: Option<Rc<Texture2D>>,
+    pub normal: Option<Rc<Texture2D>>,
+    pub occlusion: Option<Rc<Texture2D>>,
+    pub emissive: Option<Rc<Texture2D>>,
+}
+
+impl PbrTextures {
+    pub const BASE_COLOR: usize = 0;
+    pub const METALLIC_ROUGHNESS: usize = 1;
+    pub const NORMAL: usize = 2;
+    pub const OCCLUSION: usize = 3;
+    pub const EMISSIVE: usize = 4;
+}
+
+pub type PbrMaterial = Material<PbrParams, NormalInstance>;
+
+impl Material<PbrParams, NormalInstance> {
+    // Uses resources/pbr.wgsl, `shared_layouts` must be the camera and lights layouts
+    pub fn pbr(device: &Device,
+        queue: &Queue,
+        target: &TargetFormat,
+        shared_layouts: &[&BindGroupLayout],
+        params: PbrParams,
+        textures: PbrTextures) -> Self {
+
+        let srgb_white = || Rc::new(Texture2D::from_rgba(device, queue, 1, 1, vec![255; 4]));
+        let linear = |pixel: [u8; 4]| Rc::new(Texture2D::from_pixels(device, queue, wgpu::TextureFormat::Rgba8Unorm, 1, 1, pixel.to_vec()));
+
+        let textures = vec![
+            textures.base_color.unwrap_or_else(srgb_white),
+            textures.metallic_roughness.unwrap_or_else(|| linear([255, 255, 255, 255])),
+            textures.normal.unwrap_or_else(|| linear([128, 128, 255, 255])),
+            textures.occlusion.unwrap_or_else(|| linear([255, 255, 255, 255])),
+            textures.emissive.unwrap_or_else(srgb_white),
+        ];
+
+        Material::new(device,
+            target,
+            &Shader::new("resources/pbr.wgsl", device),
+            shared_layouts,
+            params,
+            textures)
+    }
+}
@@ -0,0 +1,565 @@
+use std::rc::Rc;
+
+use imgui::Ui;
+use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, Device, Queue, TextureView};
+
+use super::{RenderTarget, Shader, TargetFormat, Texture2D, Uniform};
+
+// Parameter slots in resources/post.wgsl
+pub const MAX_POST_PARAMS: usize = 8;
+
+// Side of the identity lookup table used when color grading is created without one
+const IDENTITY_LUT_SIZE: u32 = 16;
+
+// A value an effect exposes to the GUI, packed into one vec4 slot of the effect's uniform
+#[derive(Clone, Debug, PartialEq)]
+pub enum PostParam {
+    Float { name: String, value: f32, min: f32, max: f32 },
+    Color { name: String, value: [f32; 3] },
+    Toggle { name: String, value: bool },
+}
+
+impl PostParam {
+    pub fn float(name: &str, value: f32, min: f32, max: f32) -> Self {
+        PostParam::Float { name: name.to_string(), value, min, max }
+    }
+
+    pub fn color(name: &str, value: [f32; 3]) -> Self {
+        PostParam::Color { name: name.to_string(), value }
+    }
+
+    pub fn toggle(name: &str, value: bool) -> Self {
+        PostParam::Toggle { name: name.to_string(), value }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            PostParam::Float { name, .. } | PostParam::Color { name, .. } | PostParam::Toggle { name, .. } => name,
+        }
+    }
+
+    fn packed(&self) -> [f32; 4] {
+        match self {
+            PostParam::Float { value, .. } => [*value, 0.0, 0.0, 0.0],
+            PostParam::Color { value, .. } => [value[0], value[1], value[2], 0.0],
+            PostParam::Toggle { value, .. } => [if *value { 1.0 } else { 0.0 }, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostUniform {
+    texel: [f32; 4],
+    values: [[f32; 4]; MAX_POST_PARAMS],
+}
+
+// One full screen pass: a fragment shader `fs_main` including resources/post.wgsl, its parameters
+// and any extra textures, bound in group 2 with the texture at 2i and its sampler at 2i + 1.
+pub struct PostEffect {
+    pub name: String,
+    pub enabled: bool,
+    pub params: Vec<PostParam>,
+    uniform: Uniform<PostUniform>,
+    shader: Shader,
+    textures: Vec<Rc<Texture2D>>,
+    texture_layout: Option<BindGroupLayout>,
+    texture_group: Option<BindGroup>,
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl PostEffect {
+    pub fn new(device: &Device,
+        input_layout: &BindGroupLayout,
+        format: wgpu::TextureFormat,
+        name: &str,
+        file: &str,
+        params: Vec<PostParam>,
+        textures: Vec<Rc<Texture2D>>) -> Self {
+
+        assert!(params.len() <= MAX_POST_PARAMS, "Post effect {name} has more than {MAX_POST_PARAMS} parameters");
+
+        let uniform = Uniform::new(device,
+            "Post Effect Uniform Buffer",
+            wgpu::ShaderStages::FRAGMENT,
+            PostUniform {
+                texel: [0.0; 4],
+                values: [[0.0; 4]; MAX_POST_PARAMS],
+            });
+
+        let (texture_layout, texture_group) = if textures.is_empty() {
+            (None, None)
+        } else {
+            let (layout, group) = PostEffect::create_texture_group(device, &textures);
+            (Some(layout), Some(group))
+        };
+
+        let shader = Shader::new(file, device);
+        let pipeline = PostEffect::create_pipeline(device, format, &shader, input_layout, &uniform.bind_layout, texture_layout.as_ref());
+
+        Self {
+            name: name.to_string(),
+            enabled: true,
+            params,
+            uniform,
+            shader,
+            textures,
+            texture_layout,
+            texture_group,
+            format,
+            pipeline,
+        }
+    }
+
+    // Parameters: intensity. Without a LUT the grading is the identity until one is set.
+    pub fn color_grading(device: &Device, queue: &Queue, stack: &PostStack, lut: Option<Rc<Texture2D>>) -> Self {
+        let lut = lut.unwrap_or_else(|| Rc::new(PostEffect::identity_lut(device, queue)));
+
+        PostEffect::new(device,
+            &stack.input_layout,
+            stack.format,
+            "Color Grading",
+            "resources/post_color_grading.wgsl",
+            vec![PostParam::float("Intensity", 1.0, 0.0, 1.0)],
+            vec![lut])
+    }
+
+    // Parameters: maximum span in pixels, direction reduction. Best first in the stack,
+    // before other effects blur or shift the edges it looks for.
+    pub fn fxaa(device: &Device, stack: &PostStack) -> Self {
+        PostEffect::new(device,
+            &stack.input_layout,
+            stack.format,
+            "FXAA",
+            "resources/post_fxaa.wgsl",
+            vec![PostParam::float("Span", 8.0, 1.0, 16.0), PostParam::float("Reduction", 0.125, 0.0, 0.5)],
+            Vec::new())
+    }
+
+    pub fn vignette(device: &Device, stack: &PostStack) -> Self {
+        PostEffect::new(device,
+            &stack.input_layout,
+            stack.format,
+            "Vignette",
+            "resources/post_vignette.wgsl",
+            vec![
+                PostParam::float("Intensity", 0.6, 0.0, 1.0),
+                PostParam::float("Radius", 0.4, 0.0, 1.0),
+                PostParam::float("Softness", 0.5, 0.01, 1.0),
+                PostParam::color("Color", [0.0, 0.0, 0.0]),
+            ],
+            Vec::new())
+    }
+
+    pub fn chromatic_aberration(device: &Device, stack: &PostStack) -> Self {
+        PostEffect::new(device,
+            &stack.input_layout,
+            stack.format,
+            "Chromatic Aberration",
+            "resources/post_chromatic_aberration.wgsl",
+            vec![PostParam::float("Strength", 3.0, 0.0, 20.0)],
+            Vec::new())
+    }
+
+    pub fn sharpen(device: &Device, stack: &PostStack) -> Self {
+        PostEffect::new(device,
+            &stack.input_layout,
+            stack.format,
+            "Sharpen",
+            "resources/post_sharpen.wgsl",
+            vec![PostParam::float("Amount", 0.3, 0.0, 2.0)],
+            Vec::new())
+    }
+
+    pub fn grayscale(device: &Device, stack: &PostStack) -> Self {
+        PostEffect::new(device,
+            &stack.input_layout,
+            stack.format,
+            "Grayscale",
+            "resources/post_grayscale.wgsl",
+            vec![PostParam::float("Amount", 1.0, 0.0, 1.0), PostParam::toggle("Sepia", false)],
+            Vec::new())
+    }
+
+    // 16 slices of 16 x 16 mapping every color to itself, in the layout resources/post_color_grading.wgsl reads
+    pub fn identity_lut(device: &Device, queue: &Queue) -> Texture2D {
+        let size = IDENTITY_LUT_SIZE;
+        let level = |value: u32| (value * 255 / (size - 1)) as u8;
+
+        let mut data = Vec::with_capacity((size * size * size * 4) as usize);
+        for y in 0..size {
+            for x in 0..size * size {
+                data.extend_from_slice(&[level(x % size), level(y), level(x / size), 255]);
+            }
+        }
+
+        Texture2D::from_pixels(device, queue, wgpu::TextureFormat::Rgba8Unorm, size * size, size, data)
+    }
+
+    pub fn set_texture(&mut self, device: &Device, slot: usize, texture: Rc<Texture2D>) {
+        self.textures[slot] = texture;
+        let layout = self.texture_layout.as_ref().expect("Post effect has no textures");
+        self.texture_group = Some(PostEffect::create_texture_entries(device, layout, &self.textures));
+    }
+
+    fn set_format(&mut self, device: &Device, input_layout: &BindGroupLayout, format: wgpu::TextureFormat) {
+        if self.format != format {
+            self.pipeline = PostEffect::create_pipeline(device, format, &self.shader, input_layout, &self.uniform.bind_layout, self.texture_layout.as_ref());
+            self.format = format;
+        }
+    }
+
+    fn create_texture_group(device: &Device, textures: &[Rc<Texture2D>]) -> (BindGroupLayout, BindGroup) {
+        let mut entries = Vec::with_capacity(textures.len() * 2);
+
+        for i in 0..textures.len() as u32 {
+            entries.push(wgpu::BindGroupLayoutEntry { // texture entry
+                binding: i * 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            });
+            entries.push(wgpu::BindGroupLayoutEntry { // sampler entry
+                binding: i * 2 + 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Effect Texture Bind Group Layout"),
+            entries: &entries,
+        });
+        let group = PostEffect::create_texture_entries(device, &layout, textures);
+        (layout, group)
+    }
+
+    fn create_texture_entries(device: &Device, layout: &BindGroupLayout, textures: &[Rc<Texture2D>]) -> BindGroup {
+        let mut entries = Vec::with_capacity(textures.len() * 2);
+
+        for (i, texture) in textures.iter().enumerate() {
+            let i = i as u32;
+            entries.push(wgpu::BindGroupEntry {
+                binding: i * 2,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: i * 2 + 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Effect Texture Bind Group"),
+            layout,
+            entries: &entries,
+        })
+    }
+
+    fn create_pipeline(device: &Device,
+        format: wgpu::TextureFormat,
+        shader: &Shader,
+        input_layout: &BindGroupLayout,
+        uniform_layout: &BindGroupLayout,
+        texture_layout: Option<&BindGroupLayout>) -> wgpu::RenderPipeline {
+
+        let mut bind_layouts = vec![input_layout, uniform_layout];
+        bind_layouts.extend(texture_layout);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Effect Pipeline Layout"),
+            bind_group_layouts: &bind_layouts,
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Post Effect Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader.module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader.module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    fn update_buffer(&mut self, queue: &Queue, size: (u32, u32)) {
+        let mut data = PostUniform {
+            texel: [1.0 / size.0 as f32, 1.0 / size.1 as f32, size.0 as f32, size.1 as f32],
+            values: [[0.0; 4]; MAX_POST_PARAMS],
+        };
+        for (slot, param) in data.values.iter_mut().zip(&self.params) {
+            *slot = param.packed();
+        }
+
+        if *self.uniform.get() != data {
+            self.uniform.set(data);
+            self.uniform.update_buffer(queue);
+        }
+    }
+}
+
+// Enabled effects run in order, each reading the previous result, the last one writing the output.
+// Intermediate results ping-pong between two targets the size of the input.
+// Effects keep the index `add` returned while they are reordered, only `remove` shifts later ones down.
+pub struct PostStack {
+    effects: Vec<PostEffect>,
+    // Indices into `effects` in the order they run
+    order: Vec<usize>,
+    format: wgpu::TextureFormat,
+    input_layout: BindGroupLayout,
+    copy: PostEffect,
+    // The input's bind group and the generation of the target it was made from
+    input_group: Option<(BindGroup, u32)>,
+    targets: Option<[(RenderTarget, BindGroup); 2]>,
+}
+
+impl PostStack {
+    // `format` is what the output and the intermediate targets use
+    pub fn new(device: &Device, format: wgpu::TextureFormat) -> Self {
+        let input_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Input Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry { // texture entry
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry { // sampler entry
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let copy = PostEffect::new(device, &input_layout, format, "Copy", "resources/post_copy.wgsl", Vec::new(), Vec::new());
+
+        Self {
+            effects: Vec::new(),
+            order: Vec::new(),
+            format,
+            input_layout,
+            copy,
+            input_group: None,
+            targets: None,
+        }
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    pub fn input_layout(&self) -> &BindGroupLayout {
+        &self.input_layout
+    }
+
+    // Rebuilds every effect's pipeline for another output format
+    pub fn set_format(&mut self, device: &Device, format: wgpu::TextureFormat) {
+        for effect in self.effects.iter_mut().chain(std::iter::once(&mut self.copy)) {
+            effect.set_format(device, &self.input_layout, format);
+        }
+        self.format = format;
+        self.targets = None;
+    }
+
+    // In the order they were added, see `order` for the order they run in
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+
+    pub fn effects_mut(&mut self) -> &mut [PostEffect] {
+        &mut self.effects
+    }
+
+    pub fn add(&mut self, effect: PostEffect) -> usize {
+        assert!(effect.format == self.format, "Post effect {} was built for another format", effect.name);
+        self.effects.push(effect);
+        self.order.push(self.effects.len() - 1);
+        self.effects.len() - 1
+    }
+
+    pub fn remove(&mut self, index: usize) -> PostEffect {
+        self.order.retain(|&effect| effect != index);
+        for effect in self.order.iter_mut().filter(|effect| **effect > index) {
+            *effect -= 1;
+        }
+        self.effects.remove(index)
+    }
+
+    // Effect indices in the order they run
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+
+    // Moves the effect running at position `from` so it runs at position `to`
+    pub fn move_effect(&mut self, from: usize, to: usize) {
+        let effect = self.order.remove(from);
+        self.order.insert(to, effect);
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        self.effects[index].enabled = enabled;
+    }
+
+    // Runs the enabled effects on `input` into `output`, which must be in the stack's format.
+    // Without any enabled effect the input is copied.
+    pub fn run(&mut self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, input: &RenderTarget, output: &TextureView) {
+        let size = input.size();
+
+        if !matches!(&self.input_group, Some((_, generation)) if *generation == input.generation()) {
+            self.input_group = Some((PostStack::create_input_group(device, &self.input_layout, input.sampled_view(), &input.sampler), input.generation()));
+        }
+
+        let stale = match &self.targets {
+            Some([(target, _), _]) => target.size() != size,
+            None => true,
+        };
+        if stale {
+            self.targets = Some([0, 1].map(|i| {
+                let format = TargetFormat {
+                    color: self.format,
+                    depth: None,
+                    sample_count: 1,
+                };
+                let target = RenderTarget::new(device, &format!("Post Target {i}"), format, 1.0, size);
+                let group = PostStack::create_input_group(device, &self.input_layout, target.sampled_view(), &target.sampler);
+                (target, group)
+            }));
+        }
+
+        let mut effects: Vec<Option<&mut PostEffect>> = self.effects.iter_mut().map(Some).collect();
+        let mut enabled: Vec<&mut PostEffect> = self.order.iter()
+            .filter_map(|&index| effects[index].take())
+            .filter(|effect| effect.enabled)
+            .collect();
+        if enabled.is_empty() {
+            enabled.push(&mut self.copy);
+        }
+
+        let input_group = &self.input_group.as_ref().unwrap().0;
+        let targets = self.targets.as_ref().unwrap();
+        let count = enabled.len();
+
+        for (i, effect) in enabled.into_iter().enumerate() {
+            effect.update_buffer(queue, size);
+
+            let source = if i == 0 { input_group } else { &targets[(i - 1) % 2].1 };
+            let destination = if i == count - 1 { output } else { &targets[i % 2].0.color_view };
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(&effect.name),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: destination,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(&effect.pipeline);
+            render_pass.set_bind_group(0, source, &[]);
+            render_pass.set_bind_group(1, &effect.uniform.bind_group, &[]);
+            if let Some(group) = &effect.texture_group {
+                render_pass.set_bind_group(2, group, &[]);
+            }
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    // Toggles, reorders and edits the effects
+    pub fn draw_gui(&mut self, ui: &Ui) {
+        let mut moved = None;
+        let count = self.effects.len();
+
+        ui.window("Post Processing")
+            .size([300.0, 400.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                for (i, &index) in self.order.iter().enumerate() {
+                    let effect = &mut self.effects[index];
+                    let _id = ui.push_id_usize(index);
+
+                    ui.checkbox("##enabled", &mut effect.enabled);
+                    ui.same_line();
+                    let open = ui.collapsing_header(&effect.name, imgui::TreeNodeFlags::empty());
+                    ui.same_line();
+                    if ui.arrow_button("##up", imgui::Direction::Up) && i > 0 {
+                        moved = Some((i, i - 1));
+                    }
+                    ui.same_line();
+                    if ui.arrow_button("##down", imgui::Direction::Down) && i + 1 < count {
+                        moved = Some((i, i + 1));
+                    }
+
+                    if open {
+                        for param in effect.params.iter_mut() {
+                            match param {
+                                PostParam::Float { name, value, min, max } => {
+                                    ui.slider(name, *min, *max, value);
+                                },
+                                PostParam::Color { name, value } => {
+                                    ui.color_edit3(name, value);
+                                },
+                                PostParam::Toggle { name, value } => {
+                                    ui.checkbox(name, value);
+                                },
+                            }
+                        }
+                    }
+                }
+            });
+
+        if let Some((from, to)) = moved {
+            self.move_effect(from, to);
+        }
+    }
+
+    fn create_input_group(device: &Device, layout: &BindGroupLayout, view: &TextureView, sampler: &wgpu::Sampler) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Input Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+}
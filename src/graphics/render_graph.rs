@@ -0,0 +1,629 @@
+use std::collections::{HashMap, HashSet};
+
+use wgpu::{CommandEncoder, Device, RenderPass, SurfaceConfiguration, TextureView};
+
+use super::{FrameTargets, RenderTarget, RenderTargetId, RenderTargets, TargetFormat};
+
+// Something passes read or write. `Surface` is the window, render passes draw into it through the MSAA
+// frame targets while overlays and encoder passes use `GraphResources::surface_view` directly.
+// `Target` a persistent render target, `Transient` a texture the graph allocates only for the
+// passes using it, and `External` data owned elsewhere (e.g. shadow maps) declared only for ordering.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GraphResource {
+    Surface,
+    Target(RenderTargetId),
+    Transient(usize),
+    External(usize),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PassId(usize);
+
+pub type RenderFn<C> = Box<dyn for<'a> FnMut(&mut RenderPass<'a>, &'a GraphResources, &'a mut C)>;
+pub type EncoderFn<C> = Box<dyn FnMut(&mut CommandEncoder, &GraphResources, &mut C)>;
+
+enum PassKind<C> {
+    // The graph begins the pass on `target`, with its depth if it has one, clearing on first write
+    Render { target: GraphResource, record: RenderFn<C> },
+    // Records anything into the encoder, e.g. compute work or passes on textures the graph doesn't own
+    Encoder { record: EncoderFn<C> },
+    // Draws straight into the surface texture, single sampled and without depth, after every other pass
+    Overlay { record: RenderFn<C> },
+}
+
+struct GraphPass<C> {
+    name: String,
+    reads: Vec<GraphResource>,
+    writes: Vec<GraphResource>,
+    kind: PassKind<C>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct TransientDesc {
+    label: String,
+    format: TargetFormat,
+    scale: f32,
+}
+
+// The textures passes draw into and read from, handed to every pass while the graph runs
+pub struct GraphResources {
+    pub frame: FrameTargets,
+    pub targets: RenderTargets,
+    pool: Vec<RenderTarget>,
+    transient_slots: HashMap<usize, usize>,
+    surface: Option<TextureView>,
+    version: u32,
+}
+
+impl GraphResources {
+    // Increases whenever a texture owned by the graph is recreated or reassigned,
+    // bind groups made from older views must be rebuilt
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn target(&self, resource: GraphResource) -> &RenderTarget {
+        match resource {
+            GraphResource::Target(id) => self.targets.get(id),
+            GraphResource::Transient(index) => {
+                let slot = self.transient_slots.get(&index)
+                    .expect("Transient is not used by any pass");
+                &self.pool[*slot]
+            },
+            GraphResource::Surface | GraphResource::External(_) =>
+                panic!("{resource:?} is not a render target owned by the graph"),
+        }
+    }
+
+    // The current surface texture, only there while the graph runs
+    pub fn surface_view(&self) -> &TextureView {
+        self.surface.as_ref()
+            .expect("The surface is only available while the render graph runs")
+    }
+
+    // The single sampled color view of a target or transient
+    pub fn view(&self, resource: GraphResource) -> &TextureView {
+        self.target(resource).sampled_view()
+    }
+
+    pub fn format(&self, resource: GraphResource) -> TargetFormat {
+        match resource {
+            GraphResource::Surface => *self.frame.format(),
+            _ => *self.target(resource).format(),
+        }
+    }
+}
+
+// Passes declare the resources they read and write and the graph runs them in dependency order, see `schedule`.
+// The first pass writing a target clears it.
+pub struct RenderGraph<C> {
+    resources: GraphResources,
+    transients: Vec<TransientDesc>,
+    pool_descs: Vec<TransientDesc>,
+    externals: Vec<String>,
+    passes: Vec<Option<GraphPass<C>>>,
+    order: Option<Vec<usize>>,
+    clear_colors: HashMap<GraphResource, wgpu::Color>,
+}
+
+impl<C> RenderGraph<C> {
+    pub fn new(device: &Device, config: &SurfaceConfiguration, sample_count: u32) -> Self {
+        Self {
+            resources: GraphResources {
+                frame: FrameTargets::new(device, config, sample_count),
+                targets: RenderTargets::new((config.width, config.height)),
+                pool: Vec::new(),
+                transient_slots: HashMap::new(),
+                surface: None,
+                version: 0,
+            },
+            transients: Vec::new(),
+            pool_descs: Vec::new(),
+            externals: Vec::new(),
+            passes: Vec::new(),
+            order: None,
+            clear_colors: HashMap::new(),
+        }
+    }
+
+    pub fn resources(&self) -> &GraphResources {
+        &self.resources
+    }
+
+    pub fn frame(&self) -> &FrameTargets {
+        &self.resources.frame
+    }
+
+    // Persistent target kept between frames, e.g. for a scene view panel or picking
+    pub fn add_target(&mut self, device: &Device, label: &str, format: TargetFormat, scale: f32) -> GraphResource {
+        GraphResource::Target(self.resources.targets.add(device, label, format, scale))
+    }
+
+    // Recreates a persistent target in another format, e.g. to follow the frame's sample count
+    pub fn set_target_format(&mut self, device: &Device, resource: GraphResource, format: TargetFormat) {
+        let GraphResource::Target(id) = resource else {
+            panic!("{resource:?} is not a persistent render target");
+        };
+        self.resources.targets.get_mut(id).set_format(device, format);
+        self.resources.version += 1;
+    }
+
+    // Only exists while passes use it, transients with the same format and scale share textures
+    // when their uses don't overlap
+    pub fn add_transient(&mut self, label: &str, format: TargetFormat, scale: f32) -> GraphResource {
+        self.transients.push(TransientDesc { label: label.to_string(), format, scale });
+        self.order = None;
+        GraphResource::Transient(self.transients.len() - 1)
+    }
+
+    pub fn add_external(&mut self, label: &str) -> GraphResource {
+        self.externals.push(label.to_string());
+        GraphResource::External(self.externals.len() - 1)
+    }
+
+    pub fn set_clear_color(&mut self, resource: GraphResource, color: wgpu::Color) {
+        self.clear_colors.insert(resource, color);
+    }
+
+    pub fn add_render_pass(&mut self,
+        name: &str,
+        reads: &[GraphResource],
+        target: GraphResource,
+        record: impl for<'a> FnMut(&mut RenderPass<'a>, &'a GraphResources, &'a mut C) + 'static) -> PassId {
+
+        self.add(GraphPass {
+            name: name.to_string(),
+            reads: reads.to_vec(),
+            writes: vec![target],
+            kind: PassKind::Render { target, record: Box::new(record) },
+        })
+    }
+
+    // For UI drawn on top of the finished frame. Overlays run after all other passes in the order
+    // they were added and load what is already there, so post processing never touches them.
+    // Pipelines drawing here use `overlay_format`.
+    pub fn add_overlay_pass(&mut self,
+        name: &str,
+        reads: &[GraphResource],
+        record: impl for<'a> FnMut(&mut RenderPass<'a>, &'a GraphResources, &'a mut C) + 'static) -> PassId {
+
+        self.add(GraphPass {
+            name: name.to_string(),
+            reads: reads.to_vec(),
+            writes: vec![GraphResource::Surface],
+            kind: PassKind::Overlay { record: Box::new(record) },
+        })
+    }
+
+    pub fn overlay_format(&self) -> TargetFormat {
+        TargetFormat {
+            color: self.resources.frame.format().color,
+            depth: None,
+            sample_count: 1,
+        }
+    }
+
+    pub fn add_encoder_pass(&mut self,
+        name: &str,
+        reads: &[GraphResource],
+        writes: &[GraphResource],
+        record: impl FnMut(&mut CommandEncoder, &GraphResources, &mut C) + 'static) -> PassId {
+
+        self.add(GraphPass {
+            name: name.to_string(),
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            kind: PassKind::Encoder { record: Box::new(record) },
+        })
+    }
+
+    fn add(&mut self, pass: GraphPass<C>) -> PassId {
+        self.order = None;
+        match self.passes.iter().position(|slot| slot.is_none()) {
+            Some(index) => {
+                self.passes[index] = Some(pass);
+                PassId(index)
+            },
+            None => {
+                self.passes.push(Some(pass));
+                PassId(self.passes.len() - 1)
+            },
+        }
+    }
+
+    pub fn remove_pass(&mut self, id: PassId) {
+        self.passes[id.0] = None;
+        self.order = None;
+    }
+
+    // Pass names in the order they will run
+    pub fn pass_order(&mut self) -> Vec<&str> {
+        let order = self.compile_order();
+        order.iter()
+            .filter_map(|&index| self.passes[index].as_ref())
+            .map(|pass| pass.name.as_str())
+            .collect()
+    }
+
+    pub fn resize(&mut self, device: &Device, config: &SurfaceConfiguration) {
+        let window_size = (config.width, config.height);
+        self.resources.frame.resize(device, config);
+        self.resources.targets.resize(device, window_size);
+        for target in self.resources.pool.iter_mut() {
+            target.resize(device, window_size);
+        }
+        self.resources.version += 1;
+    }
+
+    // Pipelines drawing into the surface must follow with `set_target`
+    pub fn set_sample_count(&mut self, device: &Device, sample_count: u32) {
+        self.resources.frame.set_sample_count(device, sample_count);
+        self.resources.version += 1;
+    }
+
+    fn compile_order(&mut self) -> Vec<usize> {
+        if let Some(order) = &self.order {
+            return order.clone();
+        }
+
+        let dependencies: Vec<Dependencies> = self.passes.iter()
+            .enumerate()
+            .filter_map(|(index, pass)| pass.as_ref().map(|pass| Dependencies {
+                index,
+                reads: &pass.reads,
+                writes: &pass.writes,
+                overlay: matches!(pass.kind, PassKind::Overlay { .. }),
+            }))
+            .collect();
+        let order = schedule(&dependencies);
+
+        let uses = order.iter().map(|&index| {
+            let pass = self.passes[index].as_ref().unwrap();
+            pass.reads.iter().chain(pass.writes.iter()).copied().collect()
+        });
+        let (slots, pool_descs) = alias_transients(&self.transients, uses);
+        self.resources.transient_slots = slots;
+        self.pool_descs = pool_descs;
+
+        self.order = Some(order.clone());
+        order
+    }
+
+    fn allocate_pool(&mut self, device: &Device) {
+        let window_size = self.resources.targets.window_size();
+        let matches = self.resources.pool.len() == self.pool_descs.len()
+            && self.resources.pool.iter().zip(&self.pool_descs)
+                .all(|(target, desc)| *target.format() == desc.format && target.scale() == desc.scale);
+
+        if !matches {
+            self.resources.pool = self.pool_descs.iter()
+                .map(|desc| RenderTarget::new(device, &desc.label, desc.format, desc.scale, window_size))
+                .collect();
+            self.resources.version += 1;
+        }
+    }
+
+    // Orders the passes and creates the transient textures they need, call before `execute`
+    pub fn prepare(&mut self, device: &Device) {
+        self.compile_order();
+        self.allocate_pool(device);
+    }
+
+    // Runs every pass in order into `encoder`, `surface_view` is the current surface texture
+    pub fn execute(&mut self, encoder: &mut CommandEncoder, surface_view: TextureView, context: &mut C) {
+        let order = self.order.clone()
+            .expect("Render graph changed since it was prepared");
+        self.resources.surface = Some(surface_view);
+
+        let mut written: HashSet<GraphResource> = HashSet::new();
+        for index in order {
+            let pass = self.passes[index].as_mut().unwrap();
+            let resources = &self.resources;
+
+            match &mut pass.kind {
+                PassKind::Render { target, record } => {
+                    let first_write = written.insert(*target);
+                    let color_load = RenderGraph::<C>::color_load(&self.clear_colors, *target, first_write);
+                    let depth_load = if first_write { wgpu::LoadOp::Clear(1.0) } else { wgpu::LoadOp::Load };
+
+                    let (color_attachment, depth_attachment) = match target {
+                        GraphResource::Surface => (
+                            resources.frame.color_attachment(resources.surface_view(), color_load),
+                            Some(resources.frame.depth_attachment(depth_load)),
+                        ),
+                        _ => {
+                            let render_target = resources.target(*target);
+                            (render_target.color_attachment(color_load), render_target.depth_attachment(depth_load))
+                        },
+                    };
+
+                    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some(&pass.name),
+                        color_attachments: &[Some(color_attachment)],
+                        depth_stencil_attachment: depth_attachment,
+                    });
+                    record(&mut render_pass, resources, context);
+                },
+                PassKind::Overlay { record } => {
+                    let first_write = written.insert(GraphResource::Surface);
+                    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some(&pass.name),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: resources.surface_view(),
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: RenderGraph::<C>::color_load(&self.clear_colors, GraphResource::Surface, first_write),
+                                store: true,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                    });
+                    record(&mut render_pass, resources, context);
+                },
+                PassKind::Encoder { record } => {
+                    written.extend(pass.writes.iter().copied());
+                    record(encoder, resources, context);
+                },
+            }
+        }
+        self.resources.surface = None;
+    }
+
+    fn color_load(clear_colors: &HashMap<GraphResource, wgpu::Color>, target: GraphResource, first_write: bool) -> wgpu::LoadOp<wgpu::Color> {
+        if first_write {
+            wgpu::LoadOp::Clear(clear_colors.get(&target).copied().unwrap_or(wgpu::Color::BLACK))
+        } else {
+            wgpu::LoadOp::Load
+        }
+    }
+}
+
+// What a pass reads and writes, all `schedule` needs to know about it
+struct Dependencies<'a> {
+    index: usize,
+    reads: &'a [GraphResource],
+    writes: &'a [GraphResource],
+    overlay: bool,
+}
+
+// Orders the passes so that:
+// - writers of a resource run in the order they were added, overlays after every other pass,
+// - readers run after every writer of what they read, also writers added after them,
+// - except where that writer already has to run after the reader (e.g. A reads X and writes Y,
+//   then B reads Y and writes X). The reader then sees the resource before it is overwritten,
+//   as if the passes ran in the order they were added.
+// Independent passes keep their insertion order.
+fn schedule(passes: &[Dependencies]) -> Vec<usize> {
+    let mut writers: HashMap<GraphResource, Vec<&Dependencies>> = HashMap::new();
+    for pass in passes {
+        for resource in pass.writes {
+            writers.entry(*resource).or_default().push(pass);
+        }
+    }
+
+    // Edges run from each pass to the passes that must wait for it
+    let mut dependents: HashMap<usize, HashSet<usize>> = HashMap::new();
+    let add_edge = |dependents: &mut HashMap<usize, HashSet<usize>>, from: usize, to: usize| {
+        if from != to {
+            dependents.entry(from).or_default().insert(to);
+        }
+    };
+
+    for overlay in passes.iter().filter(|pass| pass.overlay) {
+        for pass in passes.iter().filter(|pass| !pass.overlay) {
+            add_edge(&mut dependents, pass.index, overlay.index);
+        }
+    }
+    for resource_writers in writers.values_mut() {
+        resource_writers.sort_by_key(|pass| (pass.overlay, pass.index));
+        for pair in resource_writers.windows(2) {
+            add_edge(&mut dependents, pair[0].index, pair[1].index);
+        }
+    }
+
+    // Writers added before the reader first, so a later writer only goes first where it can
+    let mut reads: Vec<(usize, usize)> = passes.iter()
+        .flat_map(|pass| pass.reads.iter().map(move |resource| (pass.index, resource)))
+        .flat_map(|(reader, resource)| writers.get(resource).into_iter().flatten().map(move |writer| (writer.index, reader)))
+        .collect();
+    reads.sort_by_key(|&(writer, reader)| (writer > reader, reader, writer));
+    for (writer, reader) in reads {
+        if !reaches(&dependents, reader, writer) {
+            add_edge(&mut dependents, writer, reader);
+        }
+    }
+
+    let mut waiting_on: HashMap<usize, usize> = passes.iter().map(|pass| (pass.index, 0)).collect();
+    for dependent in dependents.values().flatten() {
+        *waiting_on.get_mut(dependent).unwrap() += 1;
+    }
+
+    // Kahn's algorithm, always taking the earliest added pass that is ready
+    let mut order = Vec::with_capacity(passes.len());
+    let mut ready: Vec<usize> = passes.iter().map(|pass| pass.index).filter(|index| waiting_on[index] == 0).collect();
+    while let Some(position) = ready.iter().enumerate().min_by_key(|(_, &index)| index).map(|(i, _)| i) {
+        let index = ready.swap_remove(position);
+        order.push(index);
+
+        for &dependent in dependents.get(&index).into_iter().flatten() {
+            let count = waiting_on.get_mut(&dependent).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                ready.push(dependent);
+            }
+        }
+    }
+    // Edges are only added where they keep the graph acyclic
+    assert!(order.len() == passes.len(), "Render graph passes depend on each other in a cycle");
+    order
+}
+
+// Whether `to` has to wait for `from`, directly or through other passes
+fn reaches(dependents: &HashMap<usize, HashSet<usize>>, from: usize, to: usize) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = vec![from];
+    while let Some(index) = stack.pop() {
+        if index == to {
+            return true;
+        }
+        if visited.insert(index) {
+            stack.extend(dependents.get(&index).into_iter().flatten());
+        }
+    }
+    false
+}
+
+// Gives every used transient a pool slot, sharing slots between transients with the same format and scale
+// whose uses don't overlap. `uses` are the resources of each pass in running order.
+// Returns the slot of each transient and the description of each slot.
+fn alias_transients(transients: &[TransientDesc], uses: impl Iterator<Item = Vec<GraphResource>>) -> (HashMap<usize, usize>, Vec<TransientDesc>) {
+    let mut lifetimes: HashMap<usize, (usize, usize)> = HashMap::new();
+    for (step, resources) in uses.enumerate() {
+        for resource in resources {
+            if let GraphResource::Transient(transient) = resource {
+                let lifetime = lifetimes.entry(transient).or_insert((step, step));
+                lifetime.1 = step;
+            }
+        }
+    }
+
+    let mut used: Vec<(usize, (usize, usize))> = lifetimes.into_iter().collect();
+    used.sort_by_key(|(transient, (first, _))| (*first, *transient));
+
+    // Pool slot descriptions and the step after which each slot is free again
+    let mut slots: Vec<(TransientDesc, usize)> = Vec::new();
+    let mut transient_slots = HashMap::new();
+    for (transient, (first, last)) in used {
+        let desc = &transients[transient];
+        let slot = slots.iter()
+            .position(|(slot_desc, free_after)| *free_after < first
+                && slot_desc.format == desc.format
+                && slot_desc.scale == desc.scale);
+
+        let slot = match slot {
+            Some(slot) => {
+                slots[slot].1 = last;
+                slot
+            },
+            None => {
+                slots.push((desc.clone(), last));
+                slots.len() - 1
+            },
+        };
+        transient_slots.insert(transient, slot);
+    }
+
+    (transient_slots, slots.into_iter().map(|(desc, _)| desc).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const X: GraphResource = GraphResource::External(0);
+    const Y: GraphResource = GraphResource::External(1);
+
+    struct Pass {
+        reads: Vec<GraphResource>,
+        writes: Vec<GraphResource>,
+        overlay: bool,
+    }
+
+    fn pass(reads: &[GraphResource], writes: &[GraphResource]) -> Pass {
+        Pass { reads: reads.to_vec(), writes: writes.to_vec(), overlay: false }
+    }
+
+    fn overlay() -> Pass {
+        Pass { reads: Vec::new(), writes: vec![GraphResource::Surface], overlay: true }
+    }
+
+    fn order(passes: &[Pass]) -> Vec<usize> {
+        let dependencies: Vec<Dependencies> = passes.iter()
+            .enumerate()
+            .map(|(index, pass)| Dependencies { index, reads: &pass.reads, writes: &pass.writes, overlay: pass.overlay })
+            .collect();
+        schedule(&dependencies)
+    }
+
+    fn desc(label: &str, scale: f32) -> TransientDesc {
+        TransientDesc {
+            label: label.to_string(),
+            format: TargetFormat {
+                color: wgpu::TextureFormat::Rgba8Unorm,
+                depth: None,
+                sample_count: 1,
+            },
+            scale,
+        }
+    }
+
+    #[test]
+    fn independent_passes_keep_insertion_order() {
+        assert_eq!(order(&[pass(&[], &[X]), pass(&[], &[Y]), pass(&[], &[])]), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn readers_wait_for_writers_added_later() {
+        assert_eq!(order(&[pass(&[X], &[Y]), pass(&[], &[X])]), vec![1, 0]);
+    }
+
+    #[test]
+    fn readers_wait_for_every_writer() {
+        let passes = [pass(&[], &[X]), pass(&[X], &[GraphResource::Surface]), pass(&[], &[X])];
+        assert_eq!(order(&passes), vec![0, 2, 1]);
+    }
+
+    #[test]
+    fn writers_keep_their_order() {
+        let passes = [pass(&[], &[X]), pass(&[], &[Y]), pass(&[Y], &[X])];
+        assert_eq!(order(&passes), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn passes_reading_and_writing_wait_only_for_earlier_writers() {
+        let passes = [pass(&[], &[X]), pass(&[X], &[X]), pass(&[X], &[X]), pass(&[X], &[Y])];
+        assert_eq!(order(&passes), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn crossed_reads_and_writes_run_in_insertion_order() {
+        // The first pass reads X before the second one overwrites it, instead of a cycle
+        assert_eq!(order(&[pass(&[X], &[Y]), pass(&[Y], &[X])]), vec![0, 1]);
+        assert_eq!(order(&[pass(&[Y], &[X]), pass(&[X], &[Y])]), vec![0, 1]);
+    }
+
+    #[test]
+    fn overlays_run_last() {
+        let passes = [overlay(), pass(&[], &[GraphResource::Surface]), pass(&[], &[X])];
+        assert_eq!(order(&passes), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn transients_with_disjoint_uses_share_a_slot() {
+        let transients = [desc("a", 1.0), desc("b", 1.0), desc("c", 0.5)];
+        let uses = vec![
+            vec![GraphResource::Transient(0)],
+            vec![GraphResource::Transient(0), GraphResource::Transient(2)],
+            vec![GraphResource::Transient(1), GraphResource::Transient(2)],
+        ];
+        let (slots, descs) = alias_transients(&transients, uses.into_iter());
+
+        assert_eq!(slots[&0], slots[&1]);
+        assert_ne!(slots[&0], slots[&2]);
+        assert_eq!(descs.len(), 2);
+    }
+
+    #[test]
+    fn overlapping_transients_get_their_own_slots() {
+        let transients = [desc("a", 1.0), desc("b", 1.0), desc("unused", 1.0)];
+        let uses = vec![
+            vec![GraphResource::Transient(0)],
+            vec![GraphResource::Transient(0), GraphResource::Transient(1)],
+        ];
+        let (slots, descs) = alias_transients(&transients, uses.into_iter());
+
+        assert_ne!(slots[&0], slots[&1]);
+        assert!(!slots.contains_key(&2));
+        assert_eq!(descs.len(), 2);
+    }
+}
@@ -0,0 +1,228 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use wgpu::{BindGroup, BindGroupLayout, Device, TextureView};
+
+use super::texture;
+use super::TargetFormat;
+
+static NEXT_GENERATION: AtomicU32 = AtomicU32::new(0);
+
+// An offscreen color texture with optional depth, sized as a fraction of the window.
+// With more than one sample, drawing goes to a multisampled texture resolved into `resolve`,
+// which is then what gets sampled. Views change on resize, see `generation`.
+pub struct RenderTarget {
+    pub label: String,
+    format: TargetFormat,
+    scale: f32,
+    width: u32,
+    height: u32,
+    generation: u32,
+    pub color: wgpu::Texture,
+    pub color_view: TextureView,
+    pub resolve: Option<(wgpu::Texture, TextureView)>,
+    pub depth: Option<(wgpu::Texture, TextureView)>,
+    pub sampler: wgpu::Sampler,
+}
+
+impl RenderTarget {
+    pub fn new(device: &Device, label: &str, format: TargetFormat, scale: f32, window_size: (u32, u32)) -> Self {
+        let (width, height) = scaled_size(window_size, scale);
+        RenderTarget::create(device, label, format, scale, width, height)
+    }
+
+    fn create(device: &Device, label: &str, format: TargetFormat, scale: f32, width: u32, height: u32) -> Self {
+        let (color, color_view) = create_texture(device, label, format.color, format.sample_count, width, height);
+
+        let resolve = (format.sample_count > 1)
+            .then(|| create_texture(device, label, format.color, 1, width, height));
+        let depth = format.depth
+            .map(|depth| create_texture(device, label, depth, format.sample_count, width, height));
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            label: label.to_string(),
+            format,
+            scale,
+            width,
+            height,
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+            color,
+            color_view,
+            resolve,
+            depth,
+            sampler,
+        }
+    }
+
+    pub fn format(&self) -> &TargetFormat {
+        &self.format
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    // Different for every set of textures ever created, so it also tells targets apart.
+    // Bind groups made from the views of another generation are stale.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    // Recreates the textures if the scaled size changed
+    pub fn resize(&mut self, device: &Device, window_size: (u32, u32)) {
+        if scaled_size(window_size, self.scale) != (self.width, self.height) {
+            *self = RenderTarget::new(device, &self.label, self.format, self.scale, window_size);
+        }
+    }
+
+    // Recreates the textures if the format changed
+    pub fn set_format(&mut self, device: &Device, format: TargetFormat) {
+        if self.format != format {
+            *self = RenderTarget::create(device, &self.label, format, self.scale, self.width, self.height);
+        }
+    }
+
+    // The single sampled color view shaders read from
+    pub fn sampled_view(&self) -> &TextureView {
+        match &self.resolve {
+            Some((_, view)) => view,
+            None => &self.color_view,
+        }
+    }
+
+    pub fn color_attachment(&self, load: wgpu::LoadOp<wgpu::Color>) -> wgpu::RenderPassColorAttachment<'_> {
+        wgpu::RenderPassColorAttachment {
+            view: &self.color_view,
+            resolve_target: self.resolve.as_ref().map(|(_, view)| view),
+            ops: wgpu::Operations {
+                load,
+                store: true,
+            },
+        }
+    }
+
+    pub fn depth_attachment(&self, load: wgpu::LoadOp<f32>) -> Option<wgpu::RenderPassDepthStencilAttachment<'_>> {
+        self.depth.as_ref().map(|(_, view)| wgpu::RenderPassDepthStencilAttachment {
+            view,
+            depth_ops: Some(wgpu::Operations {
+                load,
+                store: true,
+            }),
+            stencil_ops: None,
+        })
+    }
+
+    // Binds the sampled color view at `index` and its sampler at `index + 1`
+    pub fn create_binding(&self, device: &Device, index: u32) -> (BindGroupLayout, BindGroup) {
+        texture::create_texture_binding(device, index, self.sampled_view(), &self.sampler, wgpu::TextureViewDimension::D2)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RenderTargetId(usize);
+
+// Every offscreen target of the window, recreated by `State::resize`
+pub struct RenderTargets {
+    targets: Vec<Option<RenderTarget>>,
+    window_size: (u32, u32),
+}
+
+impl RenderTargets {
+    pub fn new(window_size: (u32, u32)) -> Self {
+        Self {
+            targets: Vec::new(),
+            window_size,
+        }
+    }
+
+    pub fn add(&mut self, device: &Device, label: &str, format: TargetFormat, scale: f32) -> RenderTargetId {
+        let target = RenderTarget::new(device, label, format, scale, self.window_size);
+
+        match self.targets.iter().position(|slot| slot.is_none()) {
+            Some(index) => {
+                self.targets[index] = Some(target);
+                RenderTargetId(index)
+            },
+            None => {
+                self.targets.push(Some(target));
+                RenderTargetId(self.targets.len() - 1)
+            },
+        }
+    }
+
+    pub fn remove(&mut self, id: RenderTargetId) -> Option<RenderTarget> {
+        self.targets[id.0].take()
+    }
+
+    pub fn get(&self, id: RenderTargetId) -> &RenderTarget {
+        self.targets[id.0].as_ref()
+            .expect("Render target was removed")
+    }
+
+    pub fn get_mut(&mut self, id: RenderTargetId) -> &mut RenderTarget {
+        self.targets[id.0].as_mut()
+            .expect("Render target was removed")
+    }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        self.window_size
+    }
+
+    pub fn resize(&mut self, device: &Device, window_size: (u32, u32)) {
+        self.window_size = window_size;
+        for target in self.targets.iter_mut().flatten() {
+            target.resize(device, window_size);
+        }
+    }
+}
+
+fn scaled_size((width, height): (u32, u32), scale: f32) -> (u32, u32) {
+    (((width as f32 * scale).round() as u32).max(1),
+        ((height as f32 * scale).round() as u32).max(1))
+}
+
+fn create_texture(device: &Device,
+    label: &str,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    width: u32,
+    height: u32) -> (wgpu::Texture, TextureView) {
+
+    // Multisampled textures can't be copied, the others allow reading back (e.g. for picking)
+    let mut usage = wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+    if sample_count == 1 {
+        usage |= wgpu::TextureUsages::COPY_SRC;
+    }
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+    });
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
@@ -1,43 +1,87 @@
-use wgpu::{BindGroupLayout, Queue, BufferAddress};
-use wgpu::{util::DeviceExt, Device, SurfaceConfiguration, ShaderModule, VertexBufferLayout};
+use std::rc::Rc;
 
-use super::{Vertex, InstanceVertex};
+use wgpu::{BindGroupLayout, Queue};
+use wgpu::{Device, ShaderModule, VertexBufferLayout};
 
+use super::{Vertex, Instance, InstanceVertex, Mesh, InstanceBuffer, InstanceHandle, Shader, TargetFormat};
+
+// A pipeline running `vs_main` and `fs_main` of its shader. It keeps what it was built from,
+// so it can be rebuilt for another target with `set_target`.
 pub struct Renderable {
     pub pipeline: wgpu::RenderPipeline,
+    module: Rc<ShaderModule>,
+    layout: wgpu::PipelineLayout,
+    vertex_layouts: Vec<VertexBufferLayout<'static>>,
+    target: TargetFormat,
 }
 
 impl Renderable {
-    fn new(
+    pub fn new(
         device: &Device,
-        config: &SurfaceConfiguration,
-        shader: &ShaderModule,
-        vertex_layouts: &[VertexBufferLayout<'_>],
+        target: &TargetFormat,
+        shader: &Shader,
+        vertex_layouts: &[VertexBufferLayout<'static>],
         bind_layouts: &Vec<&BindGroupLayout>) -> Self {
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Render Pipeline Layout"),
             bind_group_layouts: &bind_layouts,
             push_constant_ranges: &[],
         });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        let pipeline = Renderable::create_pipeline(device, target, &shader.module, &layout, vertex_layouts);
+
+        Self {
+            pipeline,
+            module: shader.module.clone(),
+            layout,
+            vertex_layouts: vertex_layouts.to_vec(),
+            target: *target,
+        }
+    }
+
+    fn create_pipeline(device: &Device,
+        target: &TargetFormat,
+        module: &ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        vertex_layouts: &[VertexBufferLayout<'static>]) -> wgpu::RenderPipeline {
+
+        let color_targets = [Some(wgpu::ColorTargetState {
+            format: target.color,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::ALL,
+        })];
+
+        Renderable::create_pipeline_for(device,
+            module,
+            layout,
+            vertex_layouts,
+            wgpu::FragmentState {
+                module,
+                entry_point: "fs_main",
+                targets: &color_targets,
+            },
+            target.depth_stencil(true, wgpu::CompareFunction::Less),
+            target.multisample())
+    }
+
+    fn create_pipeline_for(device: &Device,
+        module: &ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        vertex_layouts: &[VertexBufferLayout<'static>],
+        fragment: wgpu::FragmentState,
+        depth_stencil: Option<wgpu::DepthStencilState>,
+        multisample: wgpu::MultisampleState) -> wgpu::RenderPipeline {
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Render Pipeline"),
-            layout: Some(&pipeline_layout),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
-                module: &shader,
+                module,
                 entry_point: "vs_main",
                 buffers: vertex_layouts,
             },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
+            fragment: Some(fragment),
             primitive: wgpu::PrimitiveState {
                 topology: wgpu::PrimitiveTopology::TriangleList,
                 strip_index_format: None,
@@ -47,107 +91,118 @@ impl Renderable {
                 unclipped_depth: false,
                 conservative: false,
             },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0, // all masks
-                alpha_to_coverage_enabled: false, // anti-aliasing
-            },
+            depth_stencil,
+            multisample,
             multiview: None,
-        });
+        })
+    }
 
-        Self {
-            pipeline,
+    // Another pipeline from the same shader, layout and vertex buffers running `fragment_entry`,
+    // e.g. `fs_gbuffer` for `GBuffer::color_targets`. It isn't rebuilt by `set_target`.
+    pub fn create_variant(&self,
+        device: &Device,
+        fragment_entry: &str,
+        color_targets: &[Option<wgpu::ColorTargetState>],
+        depth_stencil: Option<wgpu::DepthStencilState>,
+        multisample: wgpu::MultisampleState) -> wgpu::RenderPipeline {
+
+        Renderable::create_pipeline_for(device,
+            &self.module,
+            &self.layout,
+            &self.vertex_layouts,
+            wgpu::FragmentState {
+                module: &self.module,
+                entry_point: fragment_entry,
+                targets: color_targets,
+            },
+            depth_stencil,
+            multisample)
+    }
+
+    pub fn target(&self) -> &TargetFormat {
+        &self.target
+    }
+
+    // Rebuilds the pipeline when the target differs from the one it was built for
+    pub fn set_target(&mut self, device: &Device, target: &TargetFormat) {
+        if self.target != *target {
+            self.pipeline = Renderable::create_pipeline(device, target, &self.module, &self.layout, &self.vertex_layouts);
+            self.target = *target;
         }
     }
 }
 
 pub struct Index {
     pub renderable: Renderable,
-    pub vertex_buffer: wgpu::Buffer,
-    pub index_buffer: wgpu::Buffer,
-    pub num_indices: u32,
+    pub mesh: Mesh,
 }
 
 impl Index {
     pub fn new(device: &Device,
-            config: &SurfaceConfiguration,
-            shader: &ShaderModule,
+            target: &TargetFormat,
+            shader: &Shader,
             vertex_layouts: &[VertexBufferLayout<'static>],
             vertices: &[Vertex],
             indices: &[u16],
             bind_layouts: &Vec<&BindGroupLayout>) -> Self {
 
-        let renderable = Renderable::new(device, config, shader, vertex_layouts, bind_layouts);
+        let renderable = Renderable::new(device, target, shader, vertex_layouts, bind_layouts);
 
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(indices),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-
-        let num_indices = indices.len() as u32;
+        let mesh = Mesh::new(device, vertices, indices);
 
         Self {
             renderable,
-            vertex_buffer,
-            index_buffer,
-            num_indices,
+            mesh,
         }
     }
 }
 
-pub struct InstanceIndex {
+pub struct InstanceIndex<T: Instance = InstanceVertex> {
     pub index: Index,
-    pub instance_buffer: wgpu::Buffer,
-    pub instance_data: Vec<InstanceVertex>,
+    pub instances: InstanceBuffer<T>,
 }
 
-impl InstanceIndex {
+impl<T: Instance> InstanceIndex<T> {
     pub fn new(device: &Device,
-        config: &SurfaceConfiguration,
-        shader: &ShaderModule,
+        target: &TargetFormat,
+        shader: &Shader,
         vertex_layouts: &[VertexBufferLayout<'static>],
         vertices: &[Vertex],
         indices: &[u16],
-        instances: &[InstanceVertex],
+        instances: &[T],
         bind_layouts: &Vec<&BindGroupLayout>) -> Self {
 
-        let index = Index::new(device, config, shader, vertex_layouts, vertices, indices, bind_layouts);
+        let index = Index::new(device, target, shader, vertex_layouts, vertices, indices, bind_layouts);
         
-        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Instance Buffer"),
-            contents: bytemuck::cast_slice(&instances),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-        });
+        let instances = InstanceBuffer::new(device, instances);
 
         Self {
             index,
-            instance_buffer,
-            instance_data: instances.to_vec(),
+            instances,
         }
     }
 
-    pub fn add_instance(&mut self, device: &Device, data: InstanceVertex) {
-        self.instance_data.push(data);
-        
-        self.instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Instance Buffer"),
-            contents: bytemuck::cast_slice(&self.instance_data),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-        });
+    pub fn add_instance(&mut self, data: T) -> InstanceHandle {
+        self.instances.add_instance(data)
+    }
+
+    pub fn remove_instance(&mut self, handle: InstanceHandle) -> T {
+        self.instances.remove_instance(handle)
+    }
+
+    pub fn set_instance(&mut self, handle: InstanceHandle, data: T) {
+        self.instances.set_instance(handle, data);
     }
 
-    pub fn update_instance_buffer(&self, queue: &Queue, offset: u32, data: InstanceVertex) {
-        queue.write_buffer(
-            &self.instance_buffer, 
-            (offset * std::mem::size_of::<InstanceVertex>() as u32) as BufferAddress, 
-            bytemuck::cast_slice(&[data]));
+    pub fn set_instances(&mut self, instances: &[T]) -> Vec<InstanceHandle> {
+        self.instances.set_instances(instances)
     }
-}
\ No newline at end of file
+
+    pub fn clear(&mut self) {
+        self.instances.clear();
+    }
+
+    pub fn update_instance_buffer(&mut self, device: &Device, queue: &Queue) {
+        self.instances.update_buffer(device, queue);
+    }
+}
@@ -0,0 +1,357 @@
+use cgmath::{Matrix4, Quaternion, Vector3, SquareMatrix, One};
+
+use super::{InstanceBuffer, InstanceHandle, ModelInstance};
+
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    pub fn new(translation: Vector3<f32>, rotation: Quaternion<f32>, scale: Vector3<f32>) -> Self {
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn identity() -> Self {
+        Transform::new(Vector3::new(0.0, 0.0, 0.0), Quaternion::one(), Vector3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Transform {
+            translation,
+            ..Transform::identity()
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: usize,
+    generation: u32,
+}
+
+struct Node<H> {
+    local: Transform,
+    world: Matrix4<f32>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    instance: Option<H>,
+    generation: u32,
+    alive: bool,
+    dirty: bool,
+}
+
+// Nodes with a local transform and an optional parent. World matrices are only
+// recomputed for nodes whose own or an ancestor's transform changed since the last `update`.
+// A node can drive one instance, by default an `InstanceHandle` of a single `InstanceBuffer`,
+// or e.g. a `DrawInstance` of a `DrawList`, see `update_with`.
+pub struct SceneGraph<H: Copy = InstanceHandle> {
+    nodes: Vec<Node<H>>,
+    roots: Vec<NodeId>,
+    free: Vec<usize>,
+}
+
+impl<H: Copy> Default for SceneGraph<H> {
+    fn default() -> Self {
+        SceneGraph::new()
+    }
+}
+
+impl<H: Copy> SceneGraph<H> {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            roots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    fn node(&self, id: NodeId) -> &Node<H> {
+        let node = &self.nodes[id.index];
+        assert!(node.alive && node.generation == id.generation, "Scene node was removed");
+        node
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut Node<H> {
+        let node = &mut self.nodes[id.index];
+        assert!(node.alive && node.generation == id.generation, "Scene node was removed");
+        node
+    }
+
+    pub fn add_node(&mut self, parent: Option<NodeId>, local: Transform) -> NodeId {
+        let node = Node {
+            local,
+            world: Matrix4::identity(),
+            parent,
+            children: Vec::new(),
+            instance: None,
+            generation: 0,
+            alive: true,
+            dirty: true,
+        };
+
+        let id = match self.free.pop() {
+            Some(index) => {
+                let generation = self.nodes[index].generation + 1;
+                self.nodes[index] = Node { generation, ..node };
+                NodeId { index, generation }
+            },
+            None => {
+                self.nodes.push(node);
+                NodeId { index: self.nodes.len() - 1, generation: 0 }
+            },
+        };
+
+        match parent {
+            Some(parent) => self.node_mut(parent).children.push(id),
+            None => self.roots.push(id),
+        }
+
+        id
+    }
+
+    // Removes the node and all of its descendants, returning the instances they drove
+    pub fn remove_node(&mut self, id: NodeId) -> Vec<H> {
+        self.detach(id);
+
+        let mut handles = Vec::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let node = self.node_mut(id);
+            node.alive = false;
+            handles.extend(node.instance.take());
+            stack.append(&mut node.children);
+            self.free.push(id.index);
+        }
+
+        handles
+    }
+
+    fn detach(&mut self, id: NodeId) {
+        match self.node(id).parent {
+            Some(parent) => self.node_mut(parent).children.retain(|child| *child != id),
+            None => self.roots.retain(|root| *root != id),
+        }
+    }
+
+    // Moves the node (with its subtree) under a new parent, or to the root with None
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        let mut ancestor = parent;
+        while let Some(a) = ancestor {
+            assert!(a != id, "A scene node cannot be parented to its own descendant");
+            ancestor = self.node(a).parent;
+        }
+
+        self.detach(id);
+        match parent {
+            Some(parent) => self.node_mut(parent).children.push(id),
+            None => self.roots.push(id),
+        }
+
+        let node = self.node_mut(id);
+        node.parent = parent;
+        node.dirty = true;
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.node(id).children
+    }
+
+    // The node's world matrix is written into this instance on every update that changes it
+    pub fn attach_instance(&mut self, id: NodeId, handle: H) {
+        let node = self.node_mut(id);
+        node.instance = Some(handle);
+        node.dirty = true;
+    }
+
+    pub fn local(&self, id: NodeId) -> &Transform {
+        &self.node(id).local
+    }
+
+    pub fn local_mut(&mut self, id: NodeId) -> &mut Transform {
+        let node = self.node_mut(id);
+        node.dirty = true;
+        &mut node.local
+    }
+
+    pub fn set_local(&mut self, id: NodeId, local: Transform) {
+        *self.local_mut(id) = local;
+    }
+
+    // World matrix as of the last `update`
+    pub fn world(&self, id: NodeId) -> Matrix4<f32> {
+        self.node(id).world
+    }
+
+    // Recomputes the changed world matrices and hands those with an instance to `write`
+    pub fn update_with(&mut self, mut write: impl FnMut(H, Matrix4<f32>)) {
+        let mut stack: Vec<(NodeId, Matrix4<f32>, bool)> = self.roots.iter()
+            .map(|root| (*root, Matrix4::identity(), false))
+            .collect();
+
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = &mut self.nodes[id.index];
+            let changed = node.dirty || parent_changed;
+
+            if changed {
+                node.world = parent_world * node.local.matrix();
+                node.dirty = false;
+
+                if let Some(handle) = node.instance {
+                    write(handle, node.world);
+                }
+            }
+
+            let world = node.world;
+            stack.extend(node.children.iter().map(|child| (*child, world, changed)));
+        }
+    }
+}
+
+impl SceneGraph<InstanceHandle> {
+    pub fn update<T: ModelInstance>(&mut self, instances: &mut InstanceBuffer<T>) {
+        self.update_with(|handle, world| {
+            let mut data = *instances.get(handle);
+            data.set_model(world);
+            instances.set_instance(handle, data);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32) -> Transform {
+        Transform::from_translation(Vector3::new(x, 0.0, 0.0))
+    }
+
+    fn x(world: Matrix4<f32>) -> f32 {
+        world.w.x
+    }
+
+    #[test]
+    fn children_inherit_parent_transforms() {
+        let mut scene: SceneGraph<u32> = SceneGraph::new();
+        let root = scene.add_node(None, at(1.0));
+        let child = scene.add_node(Some(root), at(2.0));
+        let grandchild = scene.add_node(Some(child), at(4.0));
+        scene.update_with(|_, _| {});
+
+        assert_eq!(x(scene.world(root)), 1.0);
+        assert_eq!(x(scene.world(child)), 3.0);
+        assert_eq!(x(scene.world(grandchild)), 7.0);
+    }
+
+    #[test]
+    fn parent_changes_propagate_to_descendants() {
+        let mut scene: SceneGraph<u32> = SceneGraph::new();
+        let root = scene.add_node(None, at(1.0));
+        let child = scene.add_node(Some(root), at(2.0));
+        let grandchild = scene.add_node(Some(child), at(4.0));
+        scene.update_with(|_, _| {});
+
+        scene.local_mut(root).translation.x = 10.0;
+        scene.update_with(|_, _| {});
+
+        assert_eq!(x(scene.world(child)), 12.0);
+        assert_eq!(x(scene.world(grandchild)), 16.0);
+    }
+
+    #[test]
+    fn only_changed_instances_are_written() {
+        let mut scene: SceneGraph<u32> = SceneGraph::new();
+        let a = scene.add_node(None, at(1.0));
+        let b = scene.add_node(None, at(2.0));
+        let child = scene.add_node(Some(b), at(3.0));
+        scene.attach_instance(a, 0);
+        scene.attach_instance(b, 1);
+        scene.attach_instance(child, 2);
+        scene.update_with(|_, _| {});
+
+        scene.local_mut(b).translation.x = 5.0;
+        let mut written = Vec::new();
+        scene.update_with(|handle, world| written.push((handle, x(world))));
+        written.sort_by_key(|(index, _)| *index);
+
+        assert_eq!(written, vec![(1, 5.0), (2, 8.0)]);
+
+        let mut written = Vec::new();
+        scene.update_with(|handle, _| written.push(handle));
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn reparenting_moves_the_subtree() {
+        let mut scene: SceneGraph<u32> = SceneGraph::new();
+        let a = scene.add_node(None, at(1.0));
+        let b = scene.add_node(None, at(10.0));
+        let child = scene.add_node(Some(a), at(2.0));
+        scene.update_with(|_, _| {});
+
+        scene.set_parent(child, Some(b));
+        scene.update_with(|_, _| {});
+
+        assert_eq!(scene.parent(child), Some(b));
+        assert!(scene.children(a).is_empty());
+        assert_eq!(x(scene.world(child)), 12.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "own descendant")]
+    fn rejects_parenting_to_a_descendant() {
+        let mut scene: SceneGraph<u32> = SceneGraph::new();
+        let root = scene.add_node(None, at(0.0));
+        let child = scene.add_node(Some(root), at(0.0));
+
+        scene.set_parent(root, Some(child));
+    }
+
+    #[test]
+    fn removing_a_node_returns_its_subtree_instances() {
+        let mut scene: SceneGraph<u32> = SceneGraph::new();
+        let root = scene.add_node(None, at(0.0));
+        let child = scene.add_node(Some(root), at(0.0));
+        let other = scene.add_node(None, at(0.0));
+        scene.attach_instance(root, 0);
+        scene.attach_instance(child, 1);
+        scene.attach_instance(other, 2);
+
+        let mut removed = scene.remove_node(root);
+        removed.sort();
+
+        assert_eq!(removed, vec![0, 1]);
+        let mut written = Vec::new();
+        scene.update_with(|handle, _| written.push(handle));
+        assert_eq!(written, vec![2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Scene node was removed")]
+    fn removed_ids_are_stale_after_reuse() {
+        let mut scene: SceneGraph<u32> = SceneGraph::new();
+        let root = scene.add_node(None, at(0.0));
+        let child = scene.add_node(Some(root), at(0.0));
+        scene.remove_node(root);
+        let reused = scene.add_node(None, at(0.0));
+
+        assert_ne!(reused, child);
+        scene.world(child);
+    }
+}
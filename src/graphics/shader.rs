@@ -1,22 +1,131 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
 use wgpu::{ShaderModule, Device};
 
+use super::{CLUSTERS_X, CLUSTERS_Y, CLUSTERS_Z, MAX_CLUSTER_LIGHTS, MAX_LIGHTS, MAX_SHADOW_LAYERS};
+
+const INCLUDE_DIRECTIVE: &str = "#include";
+
+// The module is shared so pipelines can be rebuilt from it later
+#[derive(Clone)]
 pub struct Shader {
-    pub module: ShaderModule
+    pub module: Rc<ShaderModule>
 }
 
 impl Shader {
+    // Loads a WGSL file with the files it includes, see `resolve_includes` and `generated_include`
     pub fn new(file: &str, device: &Device) -> Self {
-        
-        let content = std::fs::read_to_string(file)
-            .expect("Failed to read shader file");
+        let source = resolve_includes(Path::new(file), &mut HashSet::new(), &|path| {
+            generated_include(path).unwrap_or_else(|| std::fs::read_to_string(path)
+                .unwrap_or_else(|_| panic!("Failed to read shader file {}", path.display())))
+        });
 
+        Shader::from_source(file, source, device)
+    }
+
+    pub fn from_source(label: &str, source: String, device: &Device) -> Self {
         let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(content.into()),
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
         });
 
         Self {
-            module,
+            module: Rc::new(module),
         }
     }
-}
\ No newline at end of file
+}
+
+// Includes written from the Rust constants instead of read from a file, so shaders and buffer sizes
+// can't disagree. `light_constants.wgsl` holds those of resources/lights.wgsl and resources/clusters.wgsl.
+fn generated_include(path: &Path) -> Option<String> {
+    match path.file_name()?.to_str()? {
+        "light_constants.wgsl" => Some([
+                ("MAX_LIGHTS", MAX_LIGHTS as u32),
+                ("MAX_SHADOW_LAYERS", MAX_SHADOW_LAYERS as u32),
+                ("CLUSTERS_X", CLUSTERS_X),
+                ("CLUSTERS_Y", CLUSTERS_Y),
+                ("CLUSTERS_Z", CLUSTERS_Z),
+                ("MAX_CLUSTER_LIGHTS", MAX_CLUSTER_LIGHTS),
+            ].iter()
+            .map(|(name, value)| format!("const {}: u32 = {}u;", name, value))
+            .collect::<Vec<String>>()
+            .join("\n")),
+        _ => None,
+    }
+}
+
+// Replaces `#include "other.wgsl"` lines with that file, found relative to the including one.
+// A file is only pasted where it is first included, so every file can include what it uses
+// (e.g. resources/instance_inputs.wgsl) without caring whether something else already did.
+fn resolve_includes(file: &Path, included: &mut HashSet<PathBuf>, read: &dyn Fn(&Path) -> String) -> String {
+    if !included.insert(file.to_path_buf()) {
+        return String::new();
+    }
+
+    let directory = file.parent().unwrap_or(Path::new(""));
+    read(file).lines()
+        .map(|line| match line.trim().strip_prefix(INCLUDE_DIRECTIVE) {
+            Some(include) => resolve_includes(&directory.join(include.trim().trim_matches('"')), included, read),
+            None => line.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn resolve(files: &[(&str, &str)], file: &str) -> String {
+        let files: HashMap<PathBuf, String> = files.iter()
+            .map(|(path, source)| (PathBuf::from(path), source.to_string()))
+            .collect();
+
+        resolve_includes(Path::new(file), &mut HashSet::new(), &|path| files[path].clone())
+    }
+
+    #[test]
+    fn pastes_included_files() {
+        let source = resolve(&[
+            ("shaders/main.wgsl", "#include \"common.wgsl\"\nfn main() {}"),
+            ("shaders/common.wgsl", "const A: u32 = 1u;"),
+        ], "shaders/main.wgsl");
+
+        assert_eq!(source, "const A: u32 = 1u;\nfn main() {}");
+    }
+
+    #[test]
+    fn includes_each_file_once() {
+        let source = resolve(&[
+            ("main.wgsl", "#include \"a.wgsl\"\n#include \"b.wgsl\"\nmain"),
+            ("a.wgsl", "#include \"common.wgsl\"\na"),
+            ("b.wgsl", "  #include \"common.wgsl\"\nb"),
+            ("common.wgsl", "common"),
+        ], "main.wgsl");
+
+        assert_eq!(source, "common\na\n\nb\nmain");
+    }
+
+    #[test]
+    fn ignores_include_cycles() {
+        let source = resolve(&[
+            ("a.wgsl", "#include \"b.wgsl\"\na"),
+            ("b.wgsl", "#include \"a.wgsl\"\nb"),
+        ], "a.wgsl");
+
+        assert_eq!(source, "\nb\na");
+    }
+
+    #[test]
+    fn generates_light_constants() {
+        let source = generated_include(Path::new("resources/light_constants.wgsl")).unwrap();
+
+        assert!(source.contains(&format!("const CLUSTERS_Z: u32 = {}u;", CLUSTERS_Z)));
+        assert!(source.contains(&format!("const MAX_LIGHTS: u32 = {}u;", MAX_LIGHTS)));
+        assert!(generated_include(Path::new("resources/lights.wgsl")).is_none());
+    }
+}
@@ -0,0 +1,326 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Transform, Vector3, Zero};
+use wgpu::{CommandEncoder, Device, Queue, RenderPass, VertexBufferLayout};
+
+use super::camera::OPENGL_TO_WGPU_MATRIX;
+use super::{Camera, Instance, InstanceBuffer, InstanceIndex, Mesh, ModelInstance, Shader, UniformArray, Vertex};
+
+// Layers of the shadow map array shared by all lights, a directional light uses one per cascade
+pub const MAX_SHADOW_LAYERS: usize = 8;
+pub const MAX_CASCADES: usize = 4;
+
+const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+// Spot shadows start at this fraction of the light's range
+const SPOT_NEAR_FRACTION: f32 = 0.01;
+
+// `depth_bias` is subtracted from the compared depth, `normal_bias` offsets the receiving point
+// along its normal in world units and `slope_bias` is applied by the depth pass rasterizer.
+// `cascade_splits` gives the far end of each directional cascade as a fraction of the camera's
+// depth range, in increasing order, one entry per cascade.
+#[derive(Clone, Debug)]
+pub struct ShadowSettings {
+    pub resolution: u32,
+    pub depth_bias: f32,
+    pub normal_bias: f32,
+    pub slope_bias: f32,
+    pub pcf_radius: u32,
+    pub cascade_splits: Vec<f32>,
+    // How far behind a cascade casters are still rendered, in world units
+    pub caster_distance: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            depth_bias: 0.001,
+            normal_bias: 1.0,
+            slope_bias: 2.0,
+            pcf_radius: 1,
+            cascade_splits: vec![0.15, 0.4, 1.0],
+            caster_distance: 1000.0,
+        }
+    }
+}
+
+// Geometry drawn into the shadow maps. Instances must start with their model matrix,
+// which every `ModelInstance` does.
+pub trait ShadowCaster {
+    fn instance_type(&self) -> TypeId;
+    fn instance_layout(&self) -> VertexBufferLayout<'static>;
+    fn draw_depth<'a>(&'a self, rp: &mut RenderPass<'a>);
+}
+
+impl<T: ModelInstance> ShadowCaster for InstanceIndex<T> {
+    fn instance_type(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn instance_layout(&self) -> VertexBufferLayout<'static> {
+        T::layout()
+    }
+
+    fn draw_depth<'a>(&'a self, rp: &mut RenderPass<'a>) {
+        draw_instances(rp, &self.index.mesh, &self.instances);
+    }
+}
+
+impl<T: ModelInstance> ShadowCaster for (&Mesh, &InstanceBuffer<T>) {
+    fn instance_type(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn instance_layout(&self) -> VertexBufferLayout<'static> {
+        T::layout()
+    }
+
+    fn draw_depth<'a>(&'a self, rp: &mut RenderPass<'a>) {
+        draw_instances(rp, self.0, self.1);
+    }
+}
+
+fn draw_instances<'a, T: Instance>(rp: &mut RenderPass<'a>, mesh: &'a Mesh, instances: &'a InstanceBuffer<T>) {
+    if instances.is_empty() {
+        return;
+    }
+
+    rp.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+    rp.set_vertex_buffer(1, instances.buffer.slice(..));
+    rp.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+    rp.draw_indexed(0..mesh.num_indices, 0, 0..instances.len() as u32);
+}
+
+// The depth texture array all shadow casting lights render into, owned by `Lights`.
+// Each layer has its own light view-projection, bound with a dynamic offset in the depth pass.
+pub struct ShadowMaps {
+    settings: ShadowSettings,
+    shader: Shader,
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    layer_views: Vec<wgpu::TextureView>,
+    matrices: Vec<Matrix4<f32>>,
+    layer_uniforms: UniformArray<[[f32; 4]; 4]>,
+    pipelines: HashMap<TypeId, wgpu::RenderPipeline>,
+}
+
+impl ShadowMaps {
+    pub fn new(device: &Device, settings: ShadowSettings) -> Self {
+        assert!(!settings.cascade_splits.is_empty() && settings.cascade_splits.len() <= MAX_CASCADES,
+            "Shadows need between 1 and {MAX_CASCADES} cascade splits");
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Maps"),
+            size: wgpu::Extent3d {
+                width: settings.resolution,
+                height: settings.resolution,
+                depth_or_array_layers: MAX_SHADOW_LAYERS as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Shadow Maps View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let layer_views = (0..MAX_SHADOW_LAYERS as u32)
+            .map(|layer| texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Shadow Map Layer View"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            }))
+            .collect();
+
+        // Comparison sampling with linear filtering gives 2x2 PCF per tap
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let mut layer_uniforms = UniformArray::new(device,
+            "Shadow Layer Uniform Buffer",
+            wgpu::ShaderStages::VERTEX,
+            MAX_SHADOW_LAYERS);
+        for _ in 0..MAX_SHADOW_LAYERS {
+            layer_uniforms.push(device, Matrix4::identity().into());
+        }
+
+        Self {
+            settings,
+            shader: Shader::new("resources/shadow.wgsl", device),
+            texture,
+            view,
+            sampler,
+            layer_views,
+            matrices: Vec::new(),
+            layer_uniforms,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    pub fn settings(&self) -> &ShadowSettings {
+        &self.settings
+    }
+
+    pub fn cascade_count(&self) -> usize {
+        self.settings.cascade_splits.len()
+    }
+
+    // View-projections of the layers in use, set by `Lights::update_buffer`
+    pub fn matrices(&self) -> &[Matrix4<f32>] {
+        &self.matrices
+    }
+
+    pub fn set_matrices(&mut self, matrices: Vec<Matrix4<f32>>) {
+        assert!(matrices.len() <= MAX_SHADOW_LAYERS, "Too many shadow layers");
+        for (i, matrix) in matrices.iter().enumerate() {
+            self.layer_uniforms.set(i, (*matrix).into());
+        }
+        self.matrices = matrices;
+    }
+
+    // One matrix per cascade, each fitted around a slice of the camera's view
+    pub fn directional_matrices(&self, direction: Vector3<f32>, camera: &Camera) -> Vec<Matrix4<f32>> {
+        let direction = direction.normalize();
+        let up = if direction.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+        let light_view = Matrix4::look_to_rh(Point3::origin(), direction, up);
+
+        let depth = camera.far() - camera.near();
+        let mut start = camera.near();
+
+        self.settings.cascade_splits.iter()
+            .map(|split| {
+                let end = camera.near() + depth * split;
+                let corners = camera.frustum_corners(start, end);
+                start = end;
+
+                let center = corners.iter()
+                    .fold(Vector3::zero(), |sum, corner| sum + corner.to_vec()) / corners.len() as f32;
+                // A bounding sphere keeps the cascade size fixed while the camera rotates
+                let radius = corners.iter()
+                    .map(|corner| (corner.to_vec() - center).magnitude())
+                    .fold(0.0, f32::max);
+                let radius = (radius * 16.0).ceil() / 16.0;
+
+                // Snapping to whole texels stops the shadow edges from shimmering as the camera moves
+                let texel = 2.0 * radius / self.settings.resolution as f32;
+                let mut center = light_view.transform_point(Point3::from_vec(center));
+                center.x = (center.x / texel).floor() * texel;
+                center.y = (center.y / texel).floor() * texel;
+
+                let projection = cgmath::ortho(center.x - radius,
+                    center.x + radius,
+                    center.y - radius,
+                    center.y + radius,
+                    -center.z - radius - self.settings.caster_distance,
+                    -center.z + radius);
+
+                OPENGL_TO_WGPU_MATRIX * projection * light_view
+            })
+            .collect()
+    }
+
+    pub fn spot_matrix(position: Vector3<f32>, direction: Vector3<f32>, outer_angle: f32, range: f32) -> Matrix4<f32> {
+        let direction = direction.normalize();
+        let up = if direction.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+        let view = Matrix4::look_to_rh(Point3::from_vec(position), direction, up);
+
+        let fov = (2.0 * outer_angle).clamp(0.01, std::f32::consts::PI - 0.01);
+        let projection = cgmath::perspective(Rad(fov), 1.0, range * SPOT_NEAR_FRACTION, range);
+
+        OPENGL_TO_WGPU_MATRIX * projection * view
+    }
+
+    fn create_pipeline(&self, device: &Device, instance_layout: VertexBufferLayout<'static>) -> wgpu::RenderPipeline {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&self.layer_uniforms.bind_layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader.module,
+                entry_point: "vs_main",
+                buffers: &[Vertex::layout(), instance_layout],
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: SHADOW_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: 0,
+                    slope_scale: self.settings.slope_bias,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    // Renders every caster once per layer in use
+    pub fn render(&mut self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, casters: &[&dyn ShadowCaster]) {
+        for caster in casters {
+            if !self.pipelines.contains_key(&caster.instance_type()) {
+                let pipeline = self.create_pipeline(device, caster.instance_layout());
+                self.pipelines.insert(caster.instance_type(), pipeline);
+            }
+        }
+
+        self.layer_uniforms.update_buffer(queue);
+
+        for layer in 0..self.matrices.len() {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.layer_views[layer],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            render_pass.set_bind_group(0, &self.layer_uniforms.bind_group, &[self.layer_uniforms.offset(layer)]);
+            for caster in casters {
+                render_pass.set_pipeline(&self.pipelines[&caster.instance_type()]);
+                caster.draw_depth(&mut render_pass);
+            }
+        }
+    }
+}
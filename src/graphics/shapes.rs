@@ -2,18 +2,18 @@ use super::Vertex;
 
 pub fn _triangle() -> [Vertex; 3] {
     [
-        Vertex { position: [0.0, 0.5, 0.0], color: [0.4, 0.2, 0.5], },
-        Vertex { position: [-0.5, -0.5, 0.0], color: [0.4, 0.2, 0.5], },
-        Vertex { position: [0.5, -0.5, 0.0], color: [0.4, 0.2, 0.5], },
+        Vertex { position: [0.0, 0.5, 0.0], color: [0.4, 0.2, 0.5], tex_coords: [0.5, 0.0], normal: [0.0, 0.0, 1.0], },
+        Vertex { position: [-0.5, -0.5, 0.0], color: [0.4, 0.2, 0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 1.0], },
+        Vertex { position: [0.5, -0.5, 0.0], color: [0.4, 0.2, 0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 1.0], },
     ]
 }
 
 pub fn plane() -> [Vertex; 4] {
     [
-        Vertex { position: [1.0, 1.0, 0.0], color: [0.4, 0.2, 0.5], },
-        Vertex { position: [-1.0, 1.0, 0.0], color: [0.4, 0.2, 0.5], }, 
-        Vertex { position: [-1.0, -1.0, 0.0], color: [0.4, 0.2, 0.5], },
-        Vertex { position: [1.0, -1.0, 0.0], color: [0.4, 0.2, 0.5], },
+        Vertex { position: [1.0, 1.0, 0.0], color: [0.4, 0.2, 0.5], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 1.0], },
+        Vertex { position: [-1.0, 1.0, 0.0], color: [0.4, 0.2, 0.5], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 1.0], }, 
+        Vertex { position: [-1.0, -1.0, 0.0], color: [0.4, 0.2, 0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 1.0], },
+        Vertex { position: [1.0, -1.0, 0.0], color: [0.4, 0.2, 0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 1.0], },
     ]
 }
 
@@ -22,4 +22,65 @@ pub fn plane_indices() -> [u16; 6] {
         0, 1, 2,
         0, 2, 3,
     ]
-}
\ No newline at end of file
+}
+
+// Unit cube from -1 to 1 with a separate set of vertices per face, so every face has its own normal
+pub fn cube() -> [Vertex; 24] {
+    let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+        // normal, right, up (right x up = normal, so faces wind counter-clockwise from outside)
+        ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ];
+    let corners: [(f32, f32); 4] = [(1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)];
+
+    let mut vertices = [Vertex { position: [0.0; 3], color: [0.4, 0.2, 0.5], tex_coords: [0.0; 2], normal: [0.0; 3], }; 24];
+    for (f, (normal, right, up)) in faces.iter().enumerate() {
+        for (c, (r, u)) in corners.iter().enumerate() {
+            let vertex = &mut vertices[f * 4 + c];
+            for i in 0..3 {
+                vertex.position[i] = normal[i] + right[i] * r + up[i] * u;
+            }
+            vertex.tex_coords = [(r + 1.0) / 2.0, (1.0 - u) / 2.0];
+            vertex.normal = *normal;
+        }
+    }
+    vertices
+}
+
+pub fn cube_indices() -> [u16; 36] {
+    let mut indices = [0; 36];
+    for face in 0..6 {
+        let base = face as u16 * 4;
+        indices[face * 6..face * 6 + 6].copy_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+    indices
+}
+
+// Unit sphere of radius 1 made of `rings` bands of `segments` quads, with texture coordinates wrapping around once
+pub fn sphere(segments: u16, rings: u16) -> (Vec<Vertex>, Vec<u16>) {
+    let mut vertices = Vec::with_capacity(((segments + 1) * (rings + 1)) as usize);
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        let (sin_theta, cos_theta) = (v * std::f32::consts::PI).sin_cos();
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let (sin_phi, cos_phi) = (u * std::f32::consts::TAU).sin_cos();
+            let normal = [sin_theta * cos_phi, cos_theta, -sin_theta * sin_phi];
+            vertices.push(Vertex { position: normal, color: [0.4, 0.2, 0.5], tex_coords: [u, v], normal });
+        }
+    }
+
+    let mut indices = Vec::with_capacity((segments * rings * 6) as usize);
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let top = ring * (segments + 1) + segment;
+            let bottom = top + segments + 1;
+            indices.extend_from_slice(&[top, bottom, bottom + 1, top, bottom + 1, top + 1]);
+        }
+    }
+    (vertices, indices)
+}
@@ -0,0 +1,443 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+use imgui::Ui;
+use rand::Rng;
+use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, Device, Queue, TextureView, VertexBufferLayout};
+
+use super::{Camera, GBuffer, ShadowCaster, Shader, Texture2D, Uniform, Vertex, DEPTH_FORMAT};
+
+// Keep in sync with MAX_SSAO_SAMPLES in resources/ssao.wgsl
+pub const MAX_SSAO_SAMPLES: usize = 64;
+
+const OCCLUSION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+// `radius` is how far around a point occluders are looked for and `bias` how far in front of a
+// sample they must be, both in view space units. The result is raised to the power of `strength`.
+#[derive(Clone, Debug)]
+pub struct SsaoSettings {
+    pub enabled: bool,
+    pub radius: f32,
+    pub bias: f32,
+    pub strength: f32,
+    pub sample_count: u32,
+    // In pixels, 0 leaves the noise in
+    pub blur_radius: u32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            radius: 30.0,
+            bias: 0.5,
+            strength: 1.5,
+            sample_count: 16,
+            blur_radius: 2,
+        }
+    }
+}
+
+// params holds the radius, depth bias, strength and sample count, blur.x the blur radius in pixels
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    projection: [[f32; 4]; 4],
+    inverse_projection: [[f32; 4]; 4],
+    view: [[f32; 4]; 4],
+    params: [f32; 4],
+    blur: [f32; 4],
+    kernel: [[f32; 4]; MAX_SSAO_SAMPLES],
+}
+
+// The depth prepass and the occlusion before and after the blur, all the size of the scene
+struct Targets {
+    size: (u32, u32),
+    _depth: wgpu::Texture,
+    depth_view: TextureView,
+    depth_group: BindGroup,
+    _raw: wgpu::Texture,
+    raw_view: TextureView,
+    raw_group: BindGroup,
+    output: Rc<Texture2D>,
+}
+
+// Screen space ambient occlusion. The casters are drawn into a depth prepass of their own, since
+// the scene's depth may be multisampled, then occlusion is estimated from it and blurred. The
+// deferred path has the depth and normals in its G-buffer already, see `render_gbuffer`.
+// Bind `occlusion` with `Lights::set_occlusion` whenever `resize` recreates it.
+pub struct Ssao {
+    settings: SsaoSettings,
+    uniform: Uniform<SsaoUniform>,
+    depth_shader: Shader,
+    depth_pipelines: HashMap<TypeId, wgpu::RenderPipeline>,
+    depth_layout: BindGroupLayout,
+    gbuffer_layout: BindGroupLayout,
+    occlusion_layout: BindGroupLayout,
+    occlusion_pipeline: wgpu::RenderPipeline,
+    gbuffer_pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    targets: Option<Targets>,
+}
+
+impl Ssao {
+    pub fn new(device: &Device, settings: SsaoSettings) -> Self {
+        let mut data: SsaoUniform = bytemuck::Zeroable::zeroed();
+        data.kernel = Ssao::create_kernel();
+        let uniform = Uniform::new(device,
+            "SSAO Uniform Buffer",
+            wgpu::ShaderStages::FRAGMENT,
+            data);
+
+        let texture = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        };
+        let create_layout = |label, entries: &[wgpu::BindGroupLayoutEntry]| device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries,
+        });
+        let unfilterable = wgpu::TextureSampleType::Float { filterable: false };
+        let depth_layout = create_layout("SSAO Depth Bind Group Layout", &[texture(0, wgpu::TextureSampleType::Depth)]);
+        let gbuffer_layout = create_layout("SSAO G-Buffer Bind Group Layout",
+            &[texture(0, wgpu::TextureSampleType::Depth), texture(1, unfilterable)]);
+        let occlusion_layout = create_layout("SSAO Occlusion Bind Group Layout", &[texture(0, unfilterable)]);
+
+        let shader = Shader::new("resources/ssao.wgsl", device);
+        let occlusion_pipeline = Ssao::create_pipeline(device, &shader, "fs_occlusion", &[&depth_layout, &uniform.bind_layout]);
+        let gbuffer_pipeline = Ssao::create_pipeline(device, &shader, "fs_occlusion_gbuffer", &[&gbuffer_layout, &uniform.bind_layout]);
+        let blur_pipeline = Ssao::create_pipeline(device, &shader, "fs_blur", &[&depth_layout, &uniform.bind_layout, &occlusion_layout]);
+
+        Self {
+            settings,
+            uniform,
+            depth_shader: Shader::new("resources/shadow.wgsl", device),
+            depth_pipelines: HashMap::new(),
+            depth_layout,
+            gbuffer_layout,
+            occlusion_layout,
+            occlusion_pipeline,
+            gbuffer_pipeline,
+            blur_pipeline,
+            targets: None,
+        }
+    }
+
+    // Points in the +z hemisphere, more of them close to the center
+    fn create_kernel() -> [[f32; 4]; MAX_SSAO_SAMPLES] {
+        let mut rng = rand::thread_rng();
+        let mut kernel = [[0.0; 4]; MAX_SSAO_SAMPLES];
+        for (i, sample) in kernel.iter_mut().enumerate() {
+            let direction = Vector3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(0.05..1.0)).normalize();
+            let t = i as f32 / MAX_SSAO_SAMPLES as f32;
+            let scale = 0.1 + 0.9 * t * t;
+            *sample = (direction * rng.gen_range(0.0..1.0f32) * scale).extend(0.0).into();
+        }
+        kernel
+    }
+
+    pub fn settings(&self) -> &SsaoSettings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut SsaoSettings {
+        &mut self.settings
+    }
+
+    // The blurred occlusion, None before the first `resize`
+    pub fn occlusion(&self) -> Option<Rc<Texture2D>> {
+        self.targets.as_ref().map(|targets| targets.output.clone())
+    }
+
+    // Recreates the targets at `size`, which should be that of the target the lit shaders draw into.
+    // Returns true when `occlusion` changed and needs binding again.
+    pub fn resize(&mut self, device: &Device, size: (u32, u32)) -> bool {
+        if matches!(&self.targets, Some(targets) if targets.size == size) {
+            return false;
+        }
+        self.targets = Some(self.create_targets(device, size));
+        true
+    }
+
+    // The projection has to match the camera's, jitter included, to find the prepass positions again.
+    // The view turns the G-buffer's world space normals into view space.
+    pub fn update_buffer(&mut self, queue: &Queue, camera: &Camera) {
+        let settings = &self.settings;
+        let projection = Matrix4::from_translation(camera.jitter().extend(0.0)) * camera.projection_matrix();
+        let inverse_projection = projection.invert()
+            .unwrap_or_else(Matrix4::identity);
+
+        let data = self.uniform.get_mut();
+        data.projection = projection.into();
+        data.inverse_projection = inverse_projection.into();
+        data.view = camera.view().into();
+        data.params = [settings.radius.max(0.0001),
+            settings.bias,
+            settings.strength,
+            settings.sample_count.min(MAX_SSAO_SAMPLES as u32) as f32];
+        data.blur = [settings.blur_radius as f32, 0.0, 0.0, 0.0];
+        self.uniform.update_buffer(queue);
+    }
+
+    // Draws the casters' depth and the occlusion from it, after `resize` and `update_buffer`.
+    // While disabled the occlusion is cleared to 1 instead.
+    pub fn render(&mut self, device: &Device, encoder: &mut CommandEncoder, camera: &Camera, casters: &[&dyn ShadowCaster]) {
+        let targets = self.targets.as_ref().expect("SSAO targets are created by resize");
+
+        if !self.settings.enabled {
+            Ssao::draw(encoder, "SSAO Clear", None, &targets.output.view, &[]);
+            return;
+        }
+
+        for caster in casters {
+            if !self.depth_pipelines.contains_key(&caster.instance_type()) {
+                let pipeline = Ssao::create_depth_pipeline(device, &self.depth_shader, &camera.uniform.bind_layout, caster.instance_layout());
+                self.depth_pipelines.insert(caster.instance_type(), pipeline);
+            }
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("SSAO Depth Prepass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &targets.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            render_pass.set_bind_group(0, &camera.uniform.bind_group, &[]);
+            for caster in casters {
+                render_pass.set_pipeline(&self.depth_pipelines[&caster.instance_type()]);
+                caster.draw_depth(&mut render_pass);
+            }
+        }
+
+        Ssao::draw(encoder, "SSAO", Some(&self.occlusion_pipeline), &targets.raw_view,
+            &[&targets.depth_group, &self.uniform.bind_group]);
+        Ssao::draw(encoder, "SSAO Blur", Some(&self.blur_pipeline), &targets.output.view,
+            &[&targets.depth_group, &self.uniform.bind_group, &targets.raw_group]);
+    }
+
+    // Like `render`, but with the depth and normals the G-buffer pass drew instead of a prepass.
+    // The G-buffer has to be the size of the occlusion, see `resize`.
+    pub fn render_gbuffer(&self, device: &Device, encoder: &mut CommandEncoder, gbuffer: &GBuffer) {
+        let targets = self.targets.as_ref().expect("SSAO targets are created by resize");
+
+        if !self.settings.enabled {
+            Ssao::draw(encoder, "SSAO Clear", None, &targets.output.view, &[]);
+            return;
+        }
+
+        let create_group = |layout, views: &[&TextureView]| {
+            let entries: Vec<wgpu::BindGroupEntry> = views.iter()
+                .enumerate()
+                .map(|(i, view)| wgpu::BindGroupEntry {
+                    binding: i as u32,
+                    resource: wgpu::BindingResource::TextureView(view),
+                })
+                .collect();
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("SSAO G-Buffer Bind Group"),
+                layout,
+                entries: &entries,
+            })
+        };
+        let gbuffer_group = create_group(&self.gbuffer_layout, &[gbuffer.depth_view(), gbuffer.normal_view()]);
+        let depth_group = create_group(&self.depth_layout, &[gbuffer.depth_view()]);
+
+        Ssao::draw(encoder, "SSAO", Some(&self.gbuffer_pipeline), &targets.raw_view,
+            &[&gbuffer_group, &self.uniform.bind_group]);
+        Ssao::draw(encoder, "SSAO Blur", Some(&self.blur_pipeline), &targets.output.view,
+            &[&depth_group, &self.uniform.bind_group, &targets.raw_group]);
+    }
+
+    pub fn draw_gui(&mut self, ui: &Ui) {
+        let settings = &mut self.settings;
+
+        ui.window("SSAO")
+            .size([300.0, 160.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.checkbox("Enabled", &mut settings.enabled);
+                ui.slider("Radius", 1.0, 200.0, &mut settings.radius);
+                ui.slider("Bias", 0.0, 5.0, &mut settings.bias);
+                ui.slider("Strength", 0.0, 4.0, &mut settings.strength);
+                ui.slider("Samples", 1, MAX_SSAO_SAMPLES as u32, &mut settings.sample_count);
+                ui.slider("Blur Radius", 0, 4, &mut settings.blur_radius);
+            });
+    }
+
+    // A full screen triangle, or only the clear to white without a pipeline
+    fn draw(encoder: &mut CommandEncoder,
+        label: &str,
+        pipeline: Option<&wgpu::RenderPipeline>,
+        destination: &TextureView,
+        groups: &[&BindGroup]) {
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: destination,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        if let Some(pipeline) = pipeline {
+            render_pass.set_pipeline(pipeline);
+            for (index, group) in groups.iter().enumerate() {
+                render_pass.set_bind_group(index as u32, group, &[]);
+            }
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    fn create_targets(&self, device: &Device, size: (u32, u32)) -> Targets {
+        let create_texture = |label, format| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            (texture, view)
+        };
+
+        let (depth, depth_view) = create_texture("SSAO Depth", DEPTH_FORMAT);
+        let (raw, raw_view) = create_texture("SSAO Raw Occlusion", OCCLUSION_FORMAT);
+        let (output, output_view) = create_texture("SSAO Occlusion", OCCLUSION_FORMAT);
+
+        let create_group = |layout, view| device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SSAO Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+            ],
+        });
+
+        Targets {
+            size,
+            depth_group: create_group(&self.depth_layout, &depth_view),
+            raw_group: create_group(&self.occlusion_layout, &raw_view),
+            _depth: depth,
+            depth_view,
+            _raw: raw,
+            raw_view,
+            output: Rc::new(Texture2D {
+                texture: output,
+                view: output_view,
+                sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                    label: Some("SSAO Sampler"),
+                    mag_filter: wgpu::FilterMode::Linear,
+                    min_filter: wgpu::FilterMode::Linear,
+                    ..Default::default()
+                }),
+            }),
+        }
+    }
+
+    fn create_pipeline(device: &Device, shader: &Shader, entry_point: &str, bind_layouts: &[&BindGroupLayout]) -> wgpu::RenderPipeline {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("SSAO Pipeline Layout"),
+            bind_group_layouts: bind_layouts,
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader.module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader.module,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: OCCLUSION_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    // resources/shadow.wgsl with the camera in place of a light
+    fn create_depth_pipeline(device: &Device,
+        shader: &Shader,
+        camera_layout: &BindGroupLayout,
+        instance_layout: VertexBufferLayout<'static>) -> wgpu::RenderPipeline {
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("SSAO Depth Pipeline Layout"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("SSAO Depth Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader.module,
+                entry_point: "vs_main",
+                buffers: &[Vertex::layout(), instance_layout],
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+}
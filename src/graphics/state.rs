@@ -1,13 +1,59 @@
 use winit::event::Event;
-use winit::{window::Window, event::WindowEvent};
+use winit::{window::Window, event::WindowEvent, event::VirtualKeyCode, event::ElementState};
+
+use std::rc::Rc;
+
+use cgmath::{SquareMatrix, Rotation3, InnerSpace, EuclideanSpace};
+use image::{Rgba, RgbaImage};
 
 use super::shapes;
 use super::Camera;
-use super::Vertex;
+use super::Projection;
 use super::Shader;
-use super::InstanceIndex;
-use super::InstanceVertex;
+use super::Mesh;
+use super::Material;
+use super::PhongParams;
+use super::PbrParams;
+use super::PbrTextures;
+use super::ColorParams;
+use super::TextureAtlas;
+use super::SpriteInstance;
+use super::Texture2D;
+use super::texture;
+use super::Environment;
+use super::NormalInstance;
+use super::InstanceBuffer;
+use super::DrawList;
+use super::DrawInstance;
+use super::SceneGraph;
+use super::Transform;
+use super::Lights;
+use super::Light;
+use super::Background;
+use super::BackgroundRenderer;
+use super::FrameTargets;
+use super::TargetFormat;
+use super::RenderGraph;
+use super::GraphResource;
+use super::DeferredRenderer;
+use super::RenderPath;
+use super::Ssao;
+use super::SsaoSettings;
+use super::AntiAliasing;
+use super::TemporalAA;
+use super::Bloom;
+use super::BloomSettings;
+use super::ToneMapper;
+use super::ToneMapSettings;
+use super::HDR_FORMAT;
+use super::PostStack;
+use super::PostEffect;
 use super::GUI;
+use super::GuiFrame;
+
+// Depth range of the orthographic view, centered on the scene
+const ORTHOGRAPHIC_NEAR: f32 = -1000.0;
+const ORTHOGRAPHIC_FAR: f32 = 1000.0;
 
 pub struct State {
     pub window: Window,
@@ -17,6 +63,32 @@ pub struct State {
     pub config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
     pub gui: GUI,
+    // Taken out while it runs, so its passes can borrow the rest of the state
+    pub graph: Option<RenderGraph<State>>,
+    pub sample_counts: Vec<u32>,
+    pub dt: f32,
+    // The scene is drawn here in HDR, then tone mapped and post processed into the surface
+    pub scene_color: GraphResource,
+    pub render_path: RenderPath,
+    pub deferred: DeferredRenderer,
+    pub ssao: Ssao,
+    pub anti_aliasing: AntiAliasing,
+    pub taa: TemporalAA,
+    pub bloom: Bloom,
+    pub tone_mapper: ToneMapper,
+    pub post: PostStack,
+    // Index of the FXAA effect in `post`
+    pub fxaa: usize,
+    pub camera: Camera,
+    pub background: BackgroundRenderer,
+    // The lit objects, drawn by the forward, G-buffer, shadow and SSAO passes alike
+    pub draw_list: DrawList,
+    pub scene: SceneGraph<DrawInstance>,
+    pub lights: Lights,
+    pub atlas: TextureAtlas,
+    pub quad: Mesh,
+    pub sprites: Material<ColorParams, SpriteInstance>,
+    pub sprite_instances: InstanceBuffer<SpriteInstance>,
 }
 
 impl State {
@@ -40,7 +112,8 @@ impl State {
 
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
+                features: adapter.features() & (wgpu::Features::TEXTURE_COMPRESSION_BC 
+                    | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES),
                 limits: if cfg!(target_arch = "wasm32") {
                     wgpu::Limits::downlevel_webgl2_defaults()
                 } else {
@@ -67,7 +140,253 @@ impl State {
         };
         surface.configure(&device, &config);
 
-        let gui = GUI::new(&window, &device, &queue, &config);
+        // 4x MSAA is always available, other counts depend on the adapter
+        let hdr_sample_counts = FrameTargets::supported_sample_counts(&adapter, &device, HDR_FORMAT);
+        let sample_counts = FrameTargets::supported_sample_counts(&adapter, &device, config.format).into_iter()
+            .filter(|count| hdr_sample_counts.contains(count))
+            .collect();
+        let mut graph = RenderGraph::new(&device, &config, 4);
+        let target = TargetFormat {
+            color: HDR_FORMAT,
+            ..*graph.frame().format()
+        };
+
+        let gui = GUI::new(&window, &device, &queue, &graph.overlay_format());
+
+        let camera = Camera::new(&device, &config, ORTHOGRAPHIC_NEAR, ORTHOGRAPHIC_FAR);
+        let mut background = BackgroundRenderer::new(&device, &target, gradient_background());
+        let cube = Rc::new(Mesh::new(&device, &shapes::cube(), &shapes::cube_indices()));
+        let (sphere_vertices, sphere_indices) = shapes::sphere(48, 24);
+        let sphere = Rc::new(Mesh::new(&device, &sphere_vertices, &sphere_indices));
+
+        let mut lights = Lights::new(&device);
+        let sun = lights.add(Light::Directional {
+            direction: cgmath::Vector3 { x: -0.5, y: -1.0, z: -1.0 },
+            color: [1.0, 1.0, 1.0],
+            intensity: 0.8,
+        }).expect("Too many lights");
+        lights.set_shadows(sun, true);
+
+        // Image based lighting from a generated sky with the sun where the directional light comes from
+        let (sky_width, sky_height, sky) = sky_image(cgmath::Vector3 { x: 0.5, y: 1.0, z: 1.0 }.normalize());
+        let sky = Texture2D::from_pixels(&device, &queue, wgpu::TextureFormat::Rgba16Float, sky_width, sky_height, texture::to_f16_bytes(&sky));
+        let environment = Environment::from_equirectangular_texture(&device, &queue, &sky, 256);
+        lights.set_environment(&device, environment);
+        lights.set_environment_intensity(0.6);
+        background.set_background(&device, Background::Skybox(lights.environment().cube.clone()));
+        lights.add(Light::Point {
+            position: cgmath::Vector3 { x: 0.0, y: 150.0, z: 200.0 },
+            color: [1.0, 0.6, 0.2],
+            intensity: 1.0,
+            range: 600.0,
+        });
+        // A shadowed spot light on the sphere from above
+        let spot = lights.add(Light::Spot {
+            position: cgmath::Vector3 { x: 0.0, y: 300.0, z: 250.0 },
+            direction: cgmath::Vector3 { x: 0.0, y: -300.0, z: -250.0 },
+            color: [0.6, 0.8, 1.0],
+            intensity: 1.5,
+            range: 900.0,
+            inner_angle: 12.0_f32.to_radians(),
+            outer_angle: 20.0_f32.to_radians(),
+        }).expect("Too many lights");
+        lights.set_shadows(spot, true);
+        // A grid of small lights over the backdrop, the light clusters keep each pixel to the few nearby
+        for row in 0..12 {
+            for column in 0..16 {
+                let hue = (row * 16 + column) as f32 * 0.61;
+                lights.add(Light::Point {
+                    position: cgmath::Vector3 {
+                        x: column as f32 * 60.0 - 450.0,
+                        y: row as f32 * 60.0 - 330.0,
+                        z: -160.0,
+                    },
+                    color: [0.5 + 0.5 * hue.cos(), 0.5 + 0.5 * (hue + 2.1).cos(), 0.5 + 0.5 * (hue + 4.2).cos()],
+                    intensity: 0.6,
+                    range: 50.0,
+                });
+            }
+        }
+
+        let white = Rc::new(Texture2D::from_rgba(&device, &queue, 1, 1, vec![255; 4]));
+        let mut material = Material::new(
+            &device,
+            &target,
+            &Shader::new("resources/lit.wgsl", &device),
+            &[&camera.uniform.bind_layout, &lights.bind_layout],
+            PhongParams { 
+                diffuse: [1.0, 1.0, 1.0, 1.0], 
+                specular: [0.5, 0.5, 0.5], 
+                shininess: 32.0, 
+                blinn: 1, 
+                _padding: [0; 3] 
+            },
+            vec![white]);
+
+        material.enable_deferred(&device);
+
+        let mut gold = Material::pbr(&device,
+            &queue,
+            &target,
+            &[&camera.uniform.bind_layout, &lights.bind_layout],
+            PbrParams {
+                base_color: [1.0, 0.78, 0.34, 1.0],
+                metallic: 1.0,
+                roughness: 0.3,
+                ..PbrParams::default()
+            },
+            PbrTextures::default());
+        gold.enable_deferred(&device);
+        let deferred = DeferredRenderer::new(&device, &target, &lights.bind_layout);
+
+        let mut draw_list = DrawList::new();
+        let phong = draw_list.add_material(material);
+        let gold = draw_list.add_material(gold);
+        let cubes = draw_list.add_item(&device, phong, cube);
+        let spheres = draw_list.add_item(&device, gold, sphere);
+        let mut scene = SceneGraph::new();
+
+        let root = scene.add_node(None, Transform::identity());
+
+        // Backdrop for the cubes to cast shadows on
+        let backdrop = scene.add_node(Some(root), Transform {
+            translation: cgmath::Vector3 { x: 0.0, y: 0.0, z: -200.0 },
+            scale: cgmath::Vector3 { x: 500.0, y: 400.0, z: 10.0 },
+            ..Transform::identity()
+        });
+        let instance = draw_list.add_instance(cubes, NormalInstance::new(cgmath::Matrix4::identity()));
+        scene.attach_instance(backdrop, instance);
+
+        for x in [-150.0, 150.0] {
+            let node = scene.add_node(Some(root), Transform {
+                translation: cgmath::Vector3 { x, y: 0.0, z: 0.0 },
+                rotation: cgmath::Quaternion::from_axis_angle(cgmath::Vector3 { x: 1.0, y: 1.0, z: 0.0 }.normalize(), cgmath::Deg(30.0)),
+                scale: cgmath::Vector3 { x: 80.0, y: 80.0, z: 80.0 },
+            });
+            let instance = draw_list.add_instance(cubes, NormalInstance::new(cgmath::Matrix4::identity()));
+            scene.attach_instance(node, instance);
+        }
+
+        // A metallic sphere between the cubes, lit by resources/pbr.wgsl
+        let node = scene.add_node(Some(root), Transform {
+            scale: cgmath::Vector3 { x: 70.0, y: 70.0, z: 70.0 },
+            ..Transform::identity()
+        });
+        let instance = draw_list.add_instance(spheres, NormalInstance::new(cgmath::Matrix4::identity()));
+        scene.attach_instance(node, instance);
+
+        // Sprites along the top, all drawn from one atlas texture
+        let atlas = TextureAtlas::from_images(&device, &queue, 256, 256, 2, 2, sprite_images());
+        let quad = Mesh::new(&device, &shapes::plane(), &shapes::plane_indices());
+        let sprites = Material::new(&device,
+            &target,
+            &Shader::new("resources/sprite.wgsl", &device),
+            &[&camera.uniform.bind_layout],
+            ColorParams { color: [1.0, 1.0, 1.0, 1.0] },
+            vec![atlas.texture.clone()]);
+
+        let mut names: Vec<&String> = atlas.regions().keys().collect();
+        names.sort();
+        let sprite_data: Vec<SpriteInstance> = names.iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let region = atlas.region(name).expect("Atlas region was just listed");
+                SpriteInstance {
+                    position: [i as f32 * 120.0 - 180.0, 320.0],
+                    scale: [region.width as f32, region.height as f32],
+                    rotation: 0.0,
+                    uv_rect: [region.uv_min[0], region.uv_min[1], region.uv_max[0], region.uv_max[1]],
+                }
+            })
+            .collect();
+        let sprite_instances = InstanceBuffer::new(&device, &sprite_data);
+
+        let ssao = Ssao::new(&device, SsaoSettings::default());
+        let taa = TemporalAA::new(&device);
+        let bloom = Bloom::new(&device, BloomSettings::default());
+        let tone_mapper = ToneMapper::new(&device, &graph.overlay_format(), ToneMapSettings::default());
+
+        let mut post = PostStack::new(&device, config.format);
+        let fxaa = post.add(PostEffect::fxaa(&device, &post));
+        post.set_enabled(fxaa, false);
+        let color_grading = PostEffect::color_grading(&device, &queue, &post, None);
+        post.add(color_grading);
+        post.add(PostEffect::vignette(&device, &post));
+        let chromatic_aberration = post.add(PostEffect::chromatic_aberration(&device, &post));
+        post.add(PostEffect::sharpen(&device, &post));
+        let grayscale = post.add(PostEffect::grayscale(&device, &post));
+        for effect in [chromatic_aberration, grayscale] {
+            post.set_enabled(effect, false);
+        }
+
+        let scene_color = graph.add_target(&device, "Scene Color", target, 1.0);
+        let tone_mapped = graph.add_transient("Tone Mapped", graph.overlay_format(), 1.0);
+        let shadow_maps = graph.add_external("Shadow Maps");
+        let exposure = graph.add_external("Exposure");
+        let ambient_occlusion = graph.add_external("Ambient Occlusion");
+        let gbuffer = graph.add_external("G-Buffer");
+        let light_clusters = graph.add_external("Light Clusters");
+        graph.add_encoder_pass("Shadows", &[], &[shadow_maps], |encoder, _, state: &mut State| {
+            state.lights.render_shadows(&state.device, &state.queue, encoder, &state.draw_list.casters());
+        });
+        graph.add_encoder_pass("Light Culling", &[], &[light_clusters], |encoder, _, state: &mut State| {
+            state.lights.cull_lights(&state.device, encoder);
+        });
+        graph.add_encoder_pass("SSAO", &[gbuffer], &[ambient_occlusion], |encoder, _, state: &mut State| {
+            match (state.render_path, state.deferred.gbuffer()) {
+                (RenderPath::Deferred, Some(gbuffer)) => state.ssao.render_gbuffer(&state.device, encoder, gbuffer),
+                _ => state.ssao.render(&state.device, encoder, &state.camera, &state.draw_list.casters()),
+            }
+        });
+        graph.add_encoder_pass("G-Buffer", &[], &[gbuffer], |encoder, _, state: &mut State| {
+            if state.render_path != RenderPath::Deferred {
+                return;
+            }
+
+            let mut render_pass = state.deferred.begin_gbuffer_pass(encoder);
+            state.draw_list.draw_gbuffer(&mut render_pass, &[&state.camera.uniform.bind_group, &state.lights.bind_group]);
+        });
+        graph.add_render_pass("Scene", &[shadow_maps, light_clusters, ambient_occlusion, gbuffer], scene_color, |render_pass, _, state: &mut State| {
+            state.background.draw(render_pass);
+
+            match state.render_path {
+                RenderPath::Forward => state.draw_list.draw(render_pass, &[&state.camera.uniform.bind_group, &state.lights.bind_group]),
+                RenderPath::Deferred => state.deferred.draw_lighting(render_pass, &state.lights.bind_group),
+            }
+
+            state.sprites.draw(render_pass, &[&state.camera.uniform.bind_group], &state.quad, &state.sprite_instances);
+        });
+        graph.add_encoder_pass("TAA", &[scene_color], &[scene_color], move |encoder, resources, state: &mut State| {
+            state.taa.render(&state.device, &state.queue, encoder, resources.target(scene_color));
+        });
+        graph.add_encoder_pass("Bloom", &[scene_color], &[scene_color], move |encoder, resources, state: &mut State| {
+            state.bloom.render(&state.device, encoder, resources.target(scene_color));
+        });
+        graph.add_encoder_pass("Luminance Histogram", &[scene_color], &[exposure], move |encoder, resources, state: &mut State| {
+            state.tone_mapper.measure(&state.device, &state.queue, encoder, resources.target(scene_color));
+        });
+        graph.add_render_pass("Tone Mapping", &[scene_color, exposure], tone_mapped, |render_pass, _, state: &mut State| {
+            state.tone_mapper.draw(render_pass);
+        });
+        graph.add_encoder_pass("Post Processing", &[tone_mapped], &[GraphResource::Surface], move |encoder, resources, state: &mut State| {
+            state.post.run(&state.device, &state.queue, encoder, resources.target(tone_mapped), resources.surface_view());
+        });
+        graph.add_overlay_pass("GUI", &[], |render_pass, _, state: &mut State| {
+            let (post, ssao, bloom, tone_mapper) = (&mut state.post, &mut state.ssao, &mut state.bloom, &mut state.tone_mapper);
+            let frame = GuiFrame {
+                dt: state.dt,
+                window: &state.window,
+                device: &state.device,
+                queue: &state.queue,
+                dock_size: [state.config.width as f32, state.config.height as f32],
+            };
+            state.gui.render(frame, render_pass, |ui| {
+                ssao.draw_gui(ui);
+                bloom.draw_gui(ui);
+                tone_mapper.draw_gui(ui);
+                post.draw_gui(ui);
+            });
+        });
 
         Self {
             window,
@@ -77,6 +396,28 @@ impl State {
             config,
             size,
             gui,
+            graph: Some(graph),
+            sample_counts,
+            dt: 0.0,
+            scene_color,
+            render_path: RenderPath::Forward,
+            deferred,
+            ssao,
+            anti_aliasing: AntiAliasing::None,
+            taa,
+            bloom,
+            tone_mapper,
+            post,
+            fxaa,
+            camera,
+            background,
+            draw_list,
+            scene,
+            lights,
+            atlas,
+            quad,
+            sprites,
+            sprite_instances,
         }
     }
 
@@ -86,33 +427,112 @@ impl State {
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
+            self.graph.as_mut().expect("Render graph is running").resize(&self.device, &self.config);
+        }
+    }
+
+    // Registers an offscreen target `scale` times the window size, kept in sync on resize
+    pub fn add_render_target(&mut self, label: &str, format: TargetFormat, scale: f32) -> GraphResource {
+        self.graph.as_mut().expect("Render graph is running").add_target(&self.device, label, format, scale)
+    }
+
+    // Recreates the frame targets and rebuilds every pipeline drawing into them.
+    // Counts the adapter doesn't support are ignored.
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        let graph = self.graph.as_mut().expect("Render graph is running");
+        if !self.sample_counts.contains(&sample_count) || sample_count == graph.frame().sample_count() {
+            return;
+        }
+
+        graph.set_sample_count(&self.device, sample_count);
+        let target = TargetFormat {
+            color: HDR_FORMAT,
+            ..*graph.frame().format()
+        };
+        graph.set_target_format(&self.device, self.scene_color, target);
+
+        self.background.set_target(&self.device, &target);
+        self.draw_list.set_target(&self.device, &target);
+        self.sprites.set_target(&self.device, &target);
+        self.deferred.set_target(&self.device, &target);
+    }
+
+    // Materials drawn in the deferred path need `Material::enable_deferred`
+    pub fn set_render_path(&mut self, render_path: RenderPath) {
+        self.render_path = render_path;
+    }
+
+    pub fn set_background(&mut self, background: Background) {
+        self.background.set_background(&self.device, background);
+    }
+
+    // Orthographic looks straight at the scene, perspective views it from the side at a distance
+    // where the plane z = 0 still fills the view vertically
+    pub fn set_projection(&mut self, projection: Projection) {
+        match projection {
+            Projection::Orthographic => {
+                self.camera.look_at(cgmath::Point3::new(0.0, 0.0, 1.0), cgmath::Point3::new(0.0, 0.0, 0.0));
+                self.camera.set_depth_range(ORTHOGRAPHIC_NEAR, ORTHOGRAPHIC_FAR);
+            },
+            Projection::Perspective { fovy } => {
+                let distance = self.camera.height() / 2.0 / (fovy / 2.0).0.tan();
+                let eye = cgmath::Vector3 { x: 0.4, y: 0.3, z: 1.0 }.normalize() * distance;
+                self.camera.look_at(cgmath::Point3::from_vec(eye), cgmath::Point3::new(0.0, 0.0, 0.0));
+                self.camera.set_depth_range(1.0, distance * 3.0);
+            },
         }
+        self.camera.set_projection(projection);
+    }
+
+    // FXAA is the post effect at `fxaa`, TAA jitters the camera from the next frame on
+    pub fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        self.anti_aliasing = anti_aliasing;
+        self.taa.enabled = anti_aliasing == AntiAliasing::Taa;
+        self.post.set_enabled(self.fxaa, anti_aliasing == AntiAliasing::Fxaa);
     }
 
     pub fn handle_event(&mut self, event: &Event<'_, ()>) {
         self.gui.handle_event(&self.window, event);
     }
 
+    // Keys act when pressed, so toggles don't flip back on release
     pub fn input(&mut self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::ModifiersChanged(..) => { return false },
-            WindowEvent::KeyboardInput { input, .. } => {
-                match input.virtual_keycode {
-                    Some(key) => {
-                        match key {
-                            // --- Match all input here --- //
-                            _ => return false
-                        }
-                    },
-                    None => return false
-                }
-            },
+        let WindowEvent::KeyboardInput { input, .. } = event else {
+            return false;
+        };
+        let (Some(key), ElementState::Pressed) = (input.virtual_keycode, input.state) else {
+            return false;
+        };
+
+        match key {
+            // --- Match all input here --- //
+            VirtualKeyCode::Key1 => self.set_sample_count(1),
+            VirtualKeyCode::Key2 => self.set_sample_count(2),
+            VirtualKeyCode::Key4 => self.set_sample_count(4),
+            VirtualKeyCode::Key8 => self.set_sample_count(8),
+            VirtualKeyCode::N => self.set_anti_aliasing(AntiAliasing::None),
+            VirtualKeyCode::F => self.set_anti_aliasing(AntiAliasing::Fxaa),
+            VirtualKeyCode::T => self.set_anti_aliasing(AntiAliasing::Taa),
+            VirtualKeyCode::R => self.set_render_path(match self.render_path {
+                RenderPath::Forward => RenderPath::Deferred,
+                RenderPath::Deferred => RenderPath::Forward,
+            }),
+            VirtualKeyCode::B => self.set_background(match self.background.background() {
+                Background::Skybox(_) => gradient_background(),
+                _ => Background::Skybox(self.lights.environment().cube.clone()),
+            }),
+            VirtualKeyCode::P => self.set_projection(match self.camera.projection() {
+                Projection::Orthographic => Projection::Perspective { fovy: cgmath::Deg(60.0).into() },
+                Projection::Perspective { .. } => Projection::Orthographic,
+            }),
             _ => return false
         }
+        true
     }
 
     pub fn update(&mut self, _dt: f32) {
-        
+        let draw_list = &mut self.draw_list;
+        self.scene.update_with(|instance, world| draw_list.set_model(instance, world));
     }
 
     pub fn render(&mut self, dt: f32) -> Result<(), wgpu::SurfaceError> {
@@ -122,57 +542,33 @@ impl State {
             label: Some("Render Encoder"),
         });
 
-        let i1 = InstanceVertex { 
-            model: (cgmath::Matrix4::from_translation(cgmath::Vector3 { x: -150.0, y: 0.0, z: 0.0 }) * cgmath::Matrix4::from_scale(100.0)).into() 
-        };
-        let i2 = InstanceVertex { 
-            model: (cgmath::Matrix4::from_translation(cgmath::Vector3 { x: 150.0, y: 0.0, z: 0.0 }) * cgmath::Matrix4::from_scale(100.0)).into() 
-        };
-        
-        let mut c = Camera::new(&self.device, &self.config, -1000.0, 1000.0);
-        let s = Shader::new("resources\\shader.wgsl", &self.device);
-        let r = InstanceIndex::new(
-            &self.device,
-            &self.config, 
-            &s.module, 
-            &vec![Vertex::layout(), InstanceVertex::layout()],
-            &shapes::plane(),
-            &shapes::plane_indices(),
-            &vec![i1, i2],
-            &vec![&c.bind_layout]);
-
-        c.update_buffer(&self.queue);
-
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.2, g: 0.2, b: 0.2, a: 1.0,
-                        }),
-                        store: true,
-                    }
-                })],
-                depth_stencil_attachment: None,
-            });
-
-            render_pass.set_pipeline(&r.index.renderable.pipeline);
-            render_pass.set_bind_group(0, &c.bind_group, &[]);
-            render_pass.set_vertex_buffer(0, r.index.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, r.instance_buffer.slice(..));
-            render_pass.set_index_buffer(r.index.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..r.index.num_indices, 0, 0..r.instance_data.len() as u32);
-
-            self.gui.render(dt, 
-                &self.window, 
-                &self.device, 
-                &self.queue, 
-                &mut render_pass, 
-                [self.config.width as f32, self.config.height as f32]);
+        let scene_size = self.graph.as_ref().expect("Render graph is running")
+            .resources().target(self.scene_color).size();
+        let jitter = self.taa.next_jitter(scene_size);
+        self.camera.set_jitter(jitter);
+        self.camera.update_buffer(&self.queue);
+        if self.ssao.resize(&self.device, scene_size) {
+            self.lights.set_occlusion(&self.device, self.ssao.occlusion());
+        }
+        self.ssao.update_buffer(&self.queue, &self.camera);
+        if self.render_path == RenderPath::Deferred {
+            self.deferred.resize(&self.device, scene_size);
+            self.deferred.update_buffer(&self.queue, &self.camera);
         }
+        self.background.update_buffer(&self.queue, &self.camera);
+        self.lights.update_buffer(&self.queue, &self.camera);
+        self.draw_list.prepare(&self.device, &self.queue);
+        self.sprites.prepare(&self.device, &self.queue);
+        self.sprite_instances.update_buffer(&self.device, &self.queue);
+        self.bloom.update_buffer(&self.queue);
+        self.tone_mapper.update_buffer(&self.queue, dt);
+
+        self.dt = dt;
+        let mut graph = self.graph.take().expect("Render graph is already running");
+        graph.set_clear_color(self.scene_color, self.background.clear_color());
+        graph.prepare(&self.device);
+        graph.execute(&mut encoder, view, self);
+        self.graph = Some(graph);
 
         self.queue.submit(std::iter::once(encoder.finish()));
         frame.present();
@@ -180,3 +576,61 @@ impl State {
         Ok(())
     }
 }
+
+// Shown instead of the skybox when toggled with B
+fn gradient_background() -> Background {
+    Background::Gradient {
+        top: [0.25, 0.3, 0.4],
+        bottom: [0.05, 0.05, 0.06],
+    }
+}
+
+// Small generated images for the sprites, packed into `State::atlas`
+fn sprite_images() -> Vec<(&'static str, RgbaImage)> {
+    vec![
+        ("checker", RgbaImage::from_fn(32, 32, |x, y| {
+            if (x / 8 + y / 8) % 2 == 0 { Rgba([230, 230, 230, 255]) } else { Rgba([40, 40, 40, 255]) }
+        })),
+        ("gradient", RgbaImage::from_fn(48, 24, |x, y| {
+            Rgba([(x * 255 / 47) as u8, (y * 255 / 23) as u8, 128, 255])
+        })),
+        ("ring", RgbaImage::from_fn(40, 40, |x, y| {
+            let distance = ((x as f32 - 19.5).powi(2) + (y as f32 - 19.5).powi(2)).sqrt();
+            if (12.0..18.0).contains(&distance) { Rgba([255, 200, 40, 255]) } else { Rgba([30, 60, 120, 255]) }
+        })),
+        ("stripes", RgbaImage::from_fn(24, 40, |x, _| {
+            if x / 4 % 2 == 0 { Rgba([200, 40, 60, 255]) } else { Rgba([250, 250, 250, 255]) }
+        })),
+    ]
+}
+
+// Equirectangular HDR sky for `State::lights`' environment: a gradient from the horizon to the zenith,
+// dark ground below and a bright sun towards `sun`. Returns the width, height and RGBA texels.
+fn sky_image(sun: cgmath::Vector3<f32>) -> (u32, u32, Vec<f32>) {
+    let (width, height) = (512, 256);
+    let mut texels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            // The inverse of the mapping in `equirect_to_cube` of resources/ibl.wgsl
+            let longitude = ((x as f32 + 0.5) / width as f32 - 0.5) * std::f32::consts::TAU;
+            let latitude = (0.5 - (y as f32 + 0.5) / height as f32) * std::f32::consts::PI;
+            let direction = cgmath::Vector3 {
+                x: latitude.cos() * longitude.cos(),
+                y: latitude.sin(),
+                z: latitude.cos() * longitude.sin(),
+            };
+
+            let color = if direction.y < 0.0 {
+                [0.12, 0.1, 0.08]
+            } else {
+                let t = direction.y.sqrt();
+                let (horizon, zenith) = ([1.1, 1.15, 1.2], [0.25, 0.45, 0.95]);
+                let glow = direction.dot(sun).max(0.0).powf(64.0) * 4.0;
+                let disc = if direction.dot(sun) > 0.9995 { 60.0 } else { 0.0 };
+                [0, 1, 2].map(|i| horizon[i] + (zenith[i] - horizon[i]) * t + glow + disc)
+            };
+            texels.extend_from_slice(&[color[0], color[1], color[2], 1.0]);
+        }
+    }
+    (width, height, texels)
+}
@@ -0,0 +1,161 @@
+use wgpu::{Adapter, Device, SurfaceConfiguration, TextureView};
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+// Sample counts tried for MSAA, the adapter decides which of them work
+const SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+// What a pipeline draws into. Pipelines built for one format can't draw into targets of another,
+// so everything drawn into the frame is rebuilt through its `set_target` when this changes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TargetFormat {
+    pub color: wgpu::TextureFormat,
+    pub depth: Option<wgpu::TextureFormat>,
+    pub sample_count: u32,
+}
+
+impl TargetFormat {
+    // Depth state for pipelines drawing into this target, None when it has no depth
+    pub fn depth_stencil(&self, write: bool, compare: wgpu::CompareFunction) -> Option<wgpu::DepthStencilState> {
+        self.depth.map(|format| wgpu::DepthStencilState {
+            format,
+            depth_write_enabled: write,
+            depth_compare: compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        })
+    }
+
+    pub fn multisample(&self) -> wgpu::MultisampleState {
+        wgpu::MultisampleState {
+            count: self.sample_count,
+            mask: !0, // all masks
+            alpha_to_coverage_enabled: false,
+        }
+    }
+}
+
+// The color and depth textures the frame is drawn into. With more than one sample the scene
+// is drawn into a multisampled color texture that resolves into the surface texture.
+pub struct FrameTargets {
+    format: TargetFormat,
+    width: u32,
+    height: u32,
+    color: Option<TextureView>,
+    depth: TextureView,
+}
+
+impl FrameTargets {
+    pub fn new(device: &Device, config: &SurfaceConfiguration, sample_count: u32) -> Self {
+        let format = TargetFormat {
+            color: config.format,
+            depth: Some(DEPTH_FORMAT),
+            sample_count,
+        };
+
+        Self {
+            format,
+            width: config.width,
+            height: config.height,
+            color: FrameTargets::create_color(device, &format, config.width, config.height),
+            depth: FrameTargets::create_depth(device, &format, config.width, config.height),
+        }
+    }
+
+    // Sample counts usable with both the surface and depth formats. Counts other than 1 and 4
+    // need the TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES device feature.
+    pub fn supported_sample_counts(adapter: &Adapter, device: &Device, color: wgpu::TextureFormat) -> Vec<u32> {
+        let adapter_specific = device.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        let color_flags = adapter.get_texture_format_features(color).flags;
+        let depth_flags = adapter.get_texture_format_features(DEPTH_FORMAT).flags;
+
+        SAMPLE_COUNTS.into_iter()
+            .filter(|&count| count == 1 || count == 4 || adapter_specific)
+            .filter(|&count| color_flags.sample_count_supported(count) && depth_flags.sample_count_supported(count))
+            .collect()
+    }
+
+    fn create_texture(device: &Device,
+        label: &str,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        width: u32,
+        height: u32) -> TextureView {
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    fn create_color(device: &Device, format: &TargetFormat, width: u32, height: u32) -> Option<TextureView> {
+        (format.sample_count > 1)
+            .then(|| FrameTargets::create_texture(device, "Multisampled Color Target", format.color, format.sample_count, width, height))
+    }
+
+    fn create_depth(device: &Device, format: &TargetFormat, width: u32, height: u32) -> TextureView {
+        FrameTargets::create_texture(device, "Depth Target", DEPTH_FORMAT, format.sample_count, width, height)
+    }
+
+    pub fn format(&self) -> &TargetFormat {
+        &self.format
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.format.sample_count
+    }
+
+    pub fn resize(&mut self, device: &Device, config: &SurfaceConfiguration) {
+        self.width = config.width;
+        self.height = config.height;
+        self.color = FrameTargets::create_color(device, &self.format, self.width, self.height);
+        self.depth = FrameTargets::create_depth(device, &self.format, self.width, self.height);
+    }
+
+    // Recreates the targets, pipelines drawing into them must follow with `set_target`
+    pub fn set_sample_count(&mut self, device: &Device, sample_count: u32) {
+        self.format.sample_count = sample_count;
+        self.color = FrameTargets::create_color(device, &self.format, self.width, self.height);
+        self.depth = FrameTargets::create_depth(device, &self.format, self.width, self.height);
+    }
+
+    // Draws into `surface_view` directly or through the multisampled color texture
+    pub fn color_attachment<'a>(&'a self, surface_view: &'a TextureView, load: wgpu::LoadOp<wgpu::Color>) -> wgpu::RenderPassColorAttachment<'a> {
+        let (view, resolve_target) = match &self.color {
+            Some(color) => (color, Some(surface_view)),
+            None => (surface_view, None),
+        };
+
+        wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target,
+            ops: wgpu::Operations {
+                load,
+                store: true,
+            },
+        }
+    }
+
+    pub fn depth_attachment(&self, load: wgpu::LoadOp<f32>) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.depth,
+            depth_ops: Some(wgpu::Operations {
+                load,
+                store: true,
+            }),
+            stencil_ops: None,
+        }
+    }
+}
@@ -1,6 +1,11 @@
+use std::path::Path;
+
+use half::f16;
 use image::GenericImageView;
 use wgpu::{Device, Queue, BindGroupLayout, BindGroup};
 
+use super::compressed::{self, ImageLevels};
+
 pub struct Texture2D {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
@@ -12,45 +17,111 @@ impl Texture2D {
         let tex_bytes = std::fs::read(file)
             .expect("Cannot read texture image file");
 
-        let tex_image = image::load_from_memory(&tex_bytes)
-            .expect("Cannot load image from memory");
+        match file_extension(file).as_deref() {
+            Some("ktx2") => {
+                let image = compressed::parse_ktx2(&tex_bytes)
+                    .expect("Cannot parse KTX2 texture");
+                Texture2D::from_levels(device, queue, image)
+            },
+            Some("dds") => {
+                let image = compressed::parse_dds(&tex_bytes)
+                    .expect("Cannot parse DDS texture");
+                Texture2D::from_levels(device, queue, image)
+            },
+            Some("hdr") | Some("exr") => Texture2D::from_levels(device, queue, decode_hdr(&tex_bytes)),
+            _ => Texture2D::from_levels(device, queue, decode_ldr(&tex_bytes)),
+        }
+    }
 
-        let tex_rgba = tex_image.to_rgba8();
+    // For data textures (normal, metallic-roughness, ...) that must not be treated as sRGB
+    pub fn new_linear(device: &Device, queue: &Queue, file: &str) -> Self {
+        let tex_bytes = std::fs::read(file)
+            .expect("Cannot read texture image file");
 
-        let tex_dimension = tex_image.dimensions();
+        let mut image = decode_ldr(&tex_bytes);
+        image.format = wgpu::TextureFormat::Rgba8Unorm;
+
+        Texture2D::from_levels(device, queue, image)
+    }
+
+    pub fn from_rgba(device: &Device, queue: &Queue, width: u32, height: u32, data: Vec<u8>) -> Self {
+        Texture2D::from_pixels(device, queue, wgpu::TextureFormat::Rgba8UnormSrgb, width, height, data)
+    }
+
+    pub fn from_pixels(device: &Device, 
+        queue: &Queue, 
+        format: wgpu::TextureFormat, 
+        width: u32, 
+        height: u32, 
+        data: Vec<u8>) -> Self {
+
+        Texture2D::from_levels(device, queue, ImageLevels {
+            format,
+            width,
+            height,
+            levels: vec![data],
+        })
+    }
+
+    // Uploads every mip level as is, decoding BCn data on the CPU if the device can't sample it
+    // or its size isn't a whole number of blocks
+    fn from_levels(device: &Device, queue: &Queue, image: ImageLevels) -> Self {
+        let supported = device.features().contains(image.format.required_features()) && image.is_block_aligned();
+        let image = if image.format.is_compressed() && !supported {
+            compressed::decompress(&image)
+                .expect("Cannot decode compressed texture")
+        } else {
+            image
+        };
 
         let tex_size = wgpu::Extent3d {
-            width: tex_dimension.0,
-            height: tex_dimension.1,
+            width: image.width,
+            height: image.height,
             depth_or_array_layers: 1,
         };
 
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Texture"),
             size: tex_size,
-            mip_level_count: 1,
+            mip_level_count: image.levels.len() as u32,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format: image.format,
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             view_formats: &[],
         });
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &tex_rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * tex_dimension.0),
-                rows_per_image: Some(tex_dimension.1),
-            },
-            tex_size
-        );
+        let (block_w, block_h) = image.format.block_dimensions();
+        let block_size = image.format.block_size(None)
+            .expect("Texture format has no block size");
+
+        for (level, data) in image.levels.iter().enumerate() {
+            let level_size = tex_size
+                .mip_level_size(level as u32, wgpu::TextureDimension::D2)
+                .physical_size(image.format);
+
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(level_size.width / block_w * block_size),
+                    rows_per_image: Some(level_size.height / block_h),
+                },
+                level_size
+            );
+        }
+
+        let mipmap_filter = if image.levels.len() > 1 {
+            wgpu::FilterMode::Linear
+        } else {
+            wgpu::FilterMode::Nearest
+        };
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
@@ -59,7 +130,7 @@ impl Texture2D {
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter,
             ..Default::default()
         });
 
@@ -71,44 +142,163 @@ impl Texture2D {
     }
 
     pub fn create_binding(&self, device: &Device, index: u32) -> (BindGroupLayout, BindGroup) {
+        create_texture_binding(device, index, &self.view, &self.sampler, wgpu::TextureViewDimension::D2)
+    }
+}
 
-        let bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Bind Group Layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry { // texture entry
-                        binding: index,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry { // sampler entry
-                        binding: index + 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-            });
+fn file_extension(file: &str) -> Option<String> {
+    Path::new(file).extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+}
+
+fn decode_ldr(tex_bytes: &[u8]) -> ImageLevels {
+    let tex_image = image::load_from_memory(tex_bytes)
+        .expect("Cannot load image from memory");
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Bind Group"),
-            layout: &bind_layout,
+    let tex_dimension = tex_image.dimensions();
+
+    ImageLevels {
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        width: tex_dimension.0,
+        height: tex_dimension.1,
+        levels: vec![tex_image.to_rgba8().into_raw()],
+    }
+}
+
+// Radiance HDR and OpenEXR images are kept as linear floats.
+// Rgba16Float is used since Rgba32Float isn't filterable on every adapter.
+fn decode_hdr(tex_bytes: &[u8]) -> ImageLevels {
+    let tex_image = image::load_from_memory(tex_bytes)
+        .expect("Cannot load HDR image from memory");
+
+    let tex_dimension = tex_image.dimensions();
+
+    ImageLevels {
+        format: wgpu::TextureFormat::Rgba16Float,
+        width: tex_dimension.0,
+        height: tex_dimension.1,
+        levels: vec![to_f16_bytes(tex_image.to_rgba32f().as_raw())],
+    }
+}
+
+pub fn to_f16_bytes(data: &[f32]) -> Vec<u8> {
+    data.iter()
+        .flat_map(|v| f16::from_f32(*v).to_bits().to_le_bytes())
+        .collect()
+}
+
+pub fn is_hdr_file(file: &str) -> bool {
+    matches!(file_extension(file).as_deref(), Some("hdr") | Some("exr"))
+}
+
+// Loads an uncompressed image as a single level, used for cube faces and array layers
+pub fn load_image(file: &str) -> ImageLevels {
+    let tex_bytes = std::fs::read(file)
+        .expect("Cannot read texture image file");
+
+    if is_hdr_file(file) {
+        decode_hdr(&tex_bytes)
+    } else {
+        decode_ldr(&tex_bytes)
+    }
+}
+
+// Creates a single mip texture with one layer per image, all images must share a size and format
+pub fn create_layered_texture(device: &Device, queue: &Queue, label: &str, layers: &[ImageLevels]) -> wgpu::Texture {
+    let first = layers.first()
+        .expect("Layered texture needs at least one image");
+
+    let tex_size = wgpu::Extent3d {
+        width: first.width,
+        height: first.height,
+        depth_or_array_layers: layers.len() as u32,
+    };
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: tex_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: first.format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    let block_size = first.format.block_size(None)
+        .expect("Texture format has no block size");
+
+    for (layer, image) in layers.iter().enumerate() {
+        assert!(image.width == first.width && image.height == first.height && image.format == first.format,
+            "Layer {layer} does not match the size and format of the first layer");
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &image.levels[0],
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(image.width * block_size),
+                rows_per_image: Some(image.height),
+            },
+            wgpu::Extent3d {
+                width: image.width,
+                height: image.height,
+                depth_or_array_layers: 1,
+            }
+        );
+    }
+
+    texture
+}
+
+pub fn create_texture_binding(device: &Device, 
+    index: u32, 
+    view: &wgpu::TextureView, 
+    sampler: &wgpu::Sampler, 
+    view_dimension: wgpu::TextureViewDimension) -> (BindGroupLayout, BindGroup) {
+
+    let bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Bind Group Layout"),
             entries: &[
-                wgpu::BindGroupEntry {
+                wgpu::BindGroupLayoutEntry { // texture entry
                     binding: index,
-                    resource: wgpu::BindingResource::TextureView(&self.view),
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
                 },
-                wgpu::BindGroupEntry {
+                wgpu::BindGroupLayoutEntry { // sampler entry
                     binding: index + 1,
-                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
                 },
             ],
         });
 
-        return (bind_layout, bind_group)
-    }
-}
\ No newline at end of file
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Bind Group"),
+        layout: &bind_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: index,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: index + 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    });
+
+    (bind_layout, bind_group)
+}
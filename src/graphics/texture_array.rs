@@ -0,0 +1,48 @@
+use wgpu::{Device, Queue, BindGroupLayout, BindGroup};
+
+use super::compressed::ImageLevels;
+use super::texture;
+
+pub struct Texture2DArray {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub layer_count: u32,
+}
+
+impl Texture2DArray {
+    // Every image becomes one layer, in the order given
+    pub fn new(device: &Device, queue: &Queue, files: &[&str]) -> Self {
+        let layers: Vec<ImageLevels> = files.iter()
+            .map(|file| texture::load_image(file))
+            .collect();
+
+        let texture = texture::create_layered_texture(device, queue, "Texture Array", &layers);
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Texture Array View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+            layer_count: layers.len() as u32,
+        }
+    }
+
+    pub fn create_binding(&self, device: &Device, index: u32) -> (BindGroupLayout, BindGroup) {
+        texture::create_texture_binding(device, index, &self.view, &self.sampler, wgpu::TextureViewDimension::D2Array)
+    }
+}
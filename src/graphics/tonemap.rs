@@ -0,0 +1,369 @@
+use imgui::Ui;
+use wgpu::{util::DeviceExt, BindGroup, BindGroupLayout, CommandEncoder, Device, Queue, RenderPass};
+
+use super::{ComputePass, Readback, RenderTarget, Shader, TargetFormat, Uniform};
+
+// Bins of the luminance histogram in resources/luminance.wgsl
+const HISTOGRAM_BINS: usize = 256;
+const HISTOGRAM_WORKGROUP: u32 = 16;
+
+// Color format of HDR scene targets
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ToneMapping {
+    Reinhard,
+    Aces,
+    AgX,
+}
+
+impl ToneMapping {
+    pub const ALL: [ToneMapping; 3] = [ToneMapping::Reinhard, ToneMapping::Aces, ToneMapping::AgX];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapping::Reinhard => "Reinhard",
+            ToneMapping::Aces => "ACES",
+            ToneMapping::AgX => "AgX",
+        }
+    }
+}
+
+// `exposure_compensation` is in stops and applies with or without auto exposure.
+// Auto exposure measures log2 luminance between `min_log_luminance` and `max_log_luminance`
+// and moves towards it at `adaptation_speed` per second.
+#[derive(Clone, Debug)]
+pub struct ToneMapSettings {
+    pub curve: ToneMapping,
+    pub exposure_compensation: f32,
+    pub auto_exposure: bool,
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    pub adaptation_speed: f32,
+}
+
+impl Default for ToneMapSettings {
+    fn default() -> Self {
+        Self {
+            curve: ToneMapping::Aces,
+            exposure_compensation: 0.0,
+            auto_exposure: true,
+            min_log_luminance: -8.0,
+            max_log_luminance: 4.0,
+            adaptation_speed: 1.5,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ToneMapUniform {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    adaptation: f32,
+    exposure_compensation: f32,
+    curve: u32,
+    auto_exposure: u32,
+    pixel_count: u32,
+    _padding: u32,
+}
+
+// Maps an HDR target into display range. `measure` runs the auto exposure compute passes
+// and must come before `draw`, which is a full screen triangle in any render pass.
+pub struct ToneMapper {
+    settings: ToneMapSettings,
+    uniform: Uniform<ToneMapUniform>,
+    histogram: wgpu::Buffer,
+    adapted: wgpu::Buffer,
+    adapted_readback: Readback<f32>,
+    // Last adapted luminance read back from the GPU, shown in the GUI
+    measured_luminance: Option<f32>,
+    histogram_layout: BindGroupLayout,
+    input_layout: BindGroupLayout,
+    histogram_pass: ComputePass,
+    average_pass: ComputePass,
+    shader: Shader,
+    target: TargetFormat,
+    pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    // Bind groups for the input and the generation of the target they were made from
+    input_groups: Option<(BindGroup, BindGroup, u32)>,
+}
+
+impl ToneMapper {
+    pub fn new(device: &Device, target: &TargetFormat, settings: ToneMapSettings) -> Self {
+        let uniform = Uniform::new(device,
+            "Tone Map Uniform Buffer",
+            wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+            ToneMapUniform {
+                min_log_luminance: 0.0,
+                log_luminance_range: 1.0,
+                adaptation: 1.0,
+                exposure_compensation: 0.0,
+                curve: 0,
+                auto_exposure: 0,
+                pixel_count: 0,
+                _padding: 0,
+            });
+
+        let histogram = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Luminance Histogram Buffer"),
+            contents: bytemuck::cast_slice(&[0u32; HISTOGRAM_BINS]),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let adapted = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Adapted Luminance Buffer"),
+            contents: bytemuck::cast_slice(&[0.18f32]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+        let adapted_readback = Readback::new(device, "Adapted Luminance Readback Buffer", 1);
+
+        let histogram_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Luminance Histogram Bind Group Layout"),
+            entries: &[
+                ComputePass::texture_entry(0, wgpu::TextureViewDimension::D2, wgpu::TextureSampleType::Float { filterable: false }),
+                ComputePass::storage_entry(1, false),
+                ComputePass::storage_entry(2, false),
+            ],
+        });
+
+        let input_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tone Map Input Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let luminance = Shader::new("resources/luminance.wgsl", device);
+        let compute_layouts = [&uniform.bind_layout, &histogram_layout];
+        let histogram_pass = ComputePass::new(device, &luminance, "build_histogram", &compute_layouts, (HISTOGRAM_WORKGROUP, HISTOGRAM_WORKGROUP, 1));
+        let average_pass = ComputePass::new(device, &luminance, "average", &compute_layouts, (HISTOGRAM_BINS as u32, 1, 1));
+
+        let shader = Shader::new("resources/tonemap.wgsl", device);
+        let pipeline = ToneMapper::create_pipeline(device, target, &shader, &uniform.bind_layout, &input_layout);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Tone Map Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            settings,
+            uniform,
+            histogram,
+            adapted,
+            adapted_readback,
+            measured_luminance: None,
+            histogram_layout,
+            input_layout,
+            histogram_pass,
+            average_pass,
+            shader,
+            target: *target,
+            pipeline,
+            sampler,
+            input_groups: None,
+        }
+    }
+
+    pub fn settings(&self) -> &ToneMapSettings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut ToneMapSettings {
+        &mut self.settings
+    }
+
+    pub fn set_target(&mut self, device: &Device, target: &TargetFormat) {
+        if self.target != *target {
+            self.pipeline = ToneMapper::create_pipeline(device, target, &self.shader, &self.uniform.bind_layout, &self.input_layout);
+            self.target = *target;
+        }
+    }
+
+    pub fn update_buffer(&mut self, queue: &Queue, dt: f32) {
+        let settings = &self.settings;
+        let uniform = self.uniform.get_mut();
+        uniform.min_log_luminance = settings.min_log_luminance;
+        uniform.log_luminance_range = (settings.max_log_luminance - settings.min_log_luminance).max(0.001);
+        uniform.adaptation = 1.0 - (-dt * settings.adaptation_speed).exp();
+        uniform.exposure_compensation = settings.exposure_compensation;
+        uniform.curve = settings.curve as u32;
+        uniform.auto_exposure = settings.auto_exposure as u32;
+
+        self.uniform.update_buffer(queue);
+    }
+
+    // Builds the luminance histogram of `input` and adapts the exposure to it, when auto exposure is on.
+    // `input` must be single sampled or resolved, it is read through its sampled view.
+    pub fn measure(&mut self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder, input: &RenderTarget) {
+        if !matches!(&self.input_groups, Some((_, _, generation)) if *generation == input.generation()) {
+            self.input_groups = Some(self.create_input_groups(device, input));
+        }
+
+        // The copy of an earlier frame, which has been submitted by now
+        if let Some(luminance) = self.adapted_readback.try_read(device) {
+            self.measured_luminance = luminance.first().copied();
+        }
+
+        if !self.settings.auto_exposure {
+            return;
+        }
+
+        let (width, height) = input.size();
+        self.uniform.get_mut().pixel_count = width * height;
+        self.uniform.update_buffer(queue);
+
+        let (histogram_group, _, _) = self.input_groups.as_ref().unwrap();
+        let bind_groups = [&self.uniform.bind_group, histogram_group];
+        self.histogram_pass.dispatch_size(encoder, &bind_groups, (width, height, 1));
+        self.average_pass.dispatch(encoder, &bind_groups, (1, 1, 1));
+
+        self.adapted_readback.copy(encoder, &self.adapted, 0);
+    }
+
+    // Draws the input measured last into the current pass
+    pub fn draw<'a>(&'a self, rp: &mut RenderPass<'a>) {
+        let (_, input_group, _) = self.input_groups.as_ref()
+            .expect("Tone mapper has no input, call measure first");
+
+        rp.set_pipeline(&self.pipeline);
+        rp.set_bind_group(0, &self.uniform.bind_group, &[]);
+        rp.set_bind_group(1, input_group, &[]);
+        rp.draw(0..3, 0..1);
+    }
+
+    pub fn draw_gui(&mut self, ui: &Ui) {
+        let settings = &mut self.settings;
+        let measured_luminance = self.measured_luminance;
+
+        ui.window("Tone Mapping")
+            .size([300.0, 180.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                let mut curve = ToneMapping::ALL.iter().position(|curve| *curve == settings.curve).unwrap();
+                if ui.combo("Curve", &mut curve, &ToneMapping::ALL, |curve| curve.name().into()) {
+                    settings.curve = ToneMapping::ALL[curve];
+                }
+
+                ui.slider("Exposure", -8.0, 8.0, &mut settings.exposure_compensation);
+                ui.checkbox("Auto Exposure", &mut settings.auto_exposure);
+                if settings.auto_exposure {
+                    ui.slider("Adaptation Speed", 0.1, 10.0, &mut settings.adaptation_speed);
+                    if let Some(luminance) = measured_luminance {
+                        ui.text(format!("Adapted Luminance: {:.3}", luminance));
+                    }
+                }
+            });
+    }
+
+    fn create_input_groups(&self, device: &Device, input: &RenderTarget) -> (BindGroup, BindGroup, u32) {
+        let view = input.sampled_view();
+
+        let histogram_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Luminance Histogram Bind Group"),
+            layout: &self.histogram_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.histogram.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.adapted.as_entire_binding(),
+                },
+            ],
+        });
+
+        let input_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Tone Map Input Bind Group"),
+            layout: &self.input_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.adapted.as_entire_binding(),
+                },
+            ],
+        });
+
+        (histogram_group, input_group, input.generation())
+    }
+
+    fn create_pipeline(device: &Device,
+        target: &TargetFormat,
+        shader: &Shader,
+        uniform_layout: &BindGroupLayout,
+        input_layout: &BindGroupLayout) -> wgpu::RenderPipeline {
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tone Map Pipeline Layout"),
+            bind_group_layouts: &[uniform_layout, input_layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Tone Map Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader.module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader.module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target.color,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: target.depth_stencil(false, wgpu::CompareFunction::Always),
+            multisample: target.multisample(),
+            multiview: None,
+        })
+    }
+}
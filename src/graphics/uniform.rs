@@ -0,0 +1,209 @@
+use std::ops::Range;
+
+use wgpu::{util::DeviceExt, BindGroup, BindGroupLayout, Buffer, Device, Queue, ShaderStages};
+
+// A single `T` in its own uniform buffer, bound at binding 0 of its bind group.
+// Changes are kept on the CPU until `update_buffer` is called.
+pub struct Uniform<T: bytemuck::Pod> {
+    data: T,
+    dirty: bool,
+    pub buffer: Buffer,
+    pub bind_layout: BindGroupLayout,
+    pub bind_group: BindGroup,
+}
+
+impl<T: bytemuck::Pod> Uniform<T> {
+    pub fn new(device: &Device, label: &str, visibility: ShaderStages, data: T) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(&[data]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_layout = create_layout::<T>(device, label, visibility, false);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: &bind_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }
+            ],
+        });
+
+        Self {
+            data,
+            dirty: false,
+            buffer,
+            bind_layout,
+            bind_group,
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.data
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.dirty = true;
+        &mut self.data
+    }
+
+    pub fn set(&mut self, data: T) {
+        self.data = data;
+        self.dirty = true;
+    }
+
+    pub fn update_buffer(&mut self, queue: &Queue) {
+        if self.dirty {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.data]));
+            self.dirty = false;
+        }
+    }
+}
+
+// Many `T`s in one uniform buffer, each padded to the device's dynamic offset alignment.
+// Bind one element with `set_bind_group(group, &array.bind_group, &[array.offset(index)])`.
+pub struct UniformArray<T: bytemuck::Pod> {
+    label: String,
+    data: Vec<T>,
+    dirty: Option<Range<usize>>,
+    stride: u64,
+    capacity: usize,
+    pub buffer: Buffer,
+    pub bind_layout: BindGroupLayout,
+    pub bind_group: BindGroup,
+}
+
+impl<T: bytemuck::Pod> UniformArray<T> {
+    pub fn new(device: &Device, label: &str, visibility: ShaderStages, capacity: usize) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let size = std::mem::size_of::<T>() as u64;
+        let stride = size.div_ceil(alignment) * alignment;
+        let capacity = capacity.max(1);
+
+        let bind_layout = create_layout::<T>(device, label, visibility, true);
+        let buffer = UniformArray::<T>::create_buffer(device, label, stride, capacity);
+        let bind_group = UniformArray::<T>::create_bind_group(device, label, &bind_layout, &buffer);
+
+        Self {
+            label: label.to_string(),
+            data: Vec::with_capacity(capacity),
+            dirty: None,
+            stride,
+            capacity,
+            buffer,
+            bind_layout,
+            bind_group,
+        }
+    }
+
+    fn create_buffer(device: &Device, label: &str, stride: u64, capacity: usize) -> Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: stride * capacity as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(device: &Device, label: &str, layout: &BindGroupLayout, buffer: &Buffer) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<T>() as u64),
+                    }),
+                }
+            ],
+        })
+    }
+
+    fn mark_dirty(&mut self, range: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(range.start)..dirty.end.max(range.end),
+            None => range,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    // Doubles the buffer when full. The bind group is recreated, the layout stays valid.
+    pub fn push(&mut self, device: &Device, value: T) -> usize {
+        if self.data.len() == self.capacity {
+            self.capacity *= 2;
+            self.buffer = UniformArray::<T>::create_buffer(device, &self.label, self.stride, self.capacity);
+            self.bind_group = UniformArray::<T>::create_bind_group(device, &self.label, &self.bind_layout, &self.buffer);
+            self.mark_dirty(0..self.data.len());
+        }
+
+        self.data.push(value);
+        let index = self.data.len() - 1;
+        self.mark_dirty(index..index + 1);
+        index
+    }
+
+    pub fn get(&self, index: usize) -> &T {
+        &self.data[index]
+    }
+
+    pub fn set(&mut self, index: usize, value: T) {
+        self.data[index] = value;
+        self.mark_dirty(index..index + 1);
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.dirty = None;
+    }
+
+    pub fn offset(&self, index: usize) -> u32 {
+        (index as u64 * self.stride) as u32
+    }
+
+    // Uploads every element between the first and last changed one
+    pub fn update_buffer(&mut self, queue: &Queue) {
+        let Some(range) = self.dirty.take() else {
+            return;
+        };
+
+        let size = std::mem::size_of::<T>();
+        let mut bytes = vec![0u8; range.len() * self.stride as usize];
+        for (i, value) in self.data[range.clone()].iter().enumerate() {
+            let start = i * self.stride as usize;
+            bytes[start..start + size].copy_from_slice(bytemuck::bytes_of(value));
+        }
+
+        queue.write_buffer(&self.buffer, self.offset(range.start) as u64, &bytes);
+    }
+}
+
+fn create_layout<T>(device: &Device, label: &str, visibility: ShaderStages, has_dynamic_offset: bool) -> BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some(label),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<T>() as u64),
+                },
+                count: None,
+            }
+        ]
+    })
+}
@@ -4,8 +4,7 @@ use winit::event::{Event, WindowEvent, VirtualKeyCode};
 use winit::window::{Window, WindowBuilder};
 use winit::event_loop::EventLoop;
 
-mod graphics;
-use graphics::State;
+use learn_wgpu::graphics::State;
 
 const SCR_W: u32 = 800;
 const SCR_H: u32 = 600;